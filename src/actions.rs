@@ -0,0 +1,482 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::{Local, Utc};
+use futures::stream::{self, StreamExt};
+use reminder_rs::caldav::{Calendar, CalendarRef, CalendarTodo, Partial, TodoState, TodoTree};
+use reminder_rs::store::{self, Query, TodoStore};
+
+use crate::archive::Archive;
+use crate::args::Bulk;
+use crate::exit::CliError;
+use crate::filter::Filter;
+use crate::new::{self, Changes};
+use crate::output::{ActionResult, Output, TodoRow};
+use crate::prompt;
+use crate::snooze::{self, Shift};
+
+///how much of the UID `list` shows
+const SHORT_ID: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Done,
+    Cancel,
+    Undone,
+    Delete,
+    ///to [`Options::to`], subtasks and all
+    Move,
+    ///due later by [`Options::shift`]
+    Snooze,
+    ///changed as [`Options::changes`] says
+    Edit,
+    ///moved to [`Options::archive`] rather than deleted
+    Archive,
+}
+
+///how [`run`] goes about it
+#[derive(Default)]
+pub struct Options {
+    ///don't ask before deleting, or changing what `filter` matched
+    pub yes: bool,
+    ///every reminder this matches, rather than the ids given
+    pub filter: Option<Filter>,
+    ///only show what would change
+    pub dry_run: bool,
+    ///do the same to subtasks, and theirs
+    pub recursive: bool,
+    ///the calendar for [`Action::Move`]
+    pub to: Option<CalendarRef>,
+    ///how far [`Action::Snooze`] moves them
+    pub shift: Option<Shift>,
+    ///what [`Action::Edit`] changes
+    pub changes: Option<Changes>,
+    ///where [`Action::Archive`] puts them
+    pub archive: Option<Archive>,
+}
+
+impl Options {
+    ///a command's `--where`, `--dry-run` and `--yes`
+    pub fn new(bulk: &Bulk) -> Options {
+        Options {
+            yes: bulk.yes,
+            filter: bulk.filter.clone(),
+            dry_run: bulk.dry_run,
+            ..Default::default()
+        }
+    }
+}
+
+impl Action {
+    fn past(&self) -> &'static str {
+        match self {
+            Action::Done => "Done",
+            Action::Cancel => "Cancelled",
+            Action::Undone => "Reopened",
+            Action::Delete => "Deleted",
+            Action::Move => "Moved",
+            Action::Snooze => "Snoozed",
+            Action::Edit => "Edited",
+            Action::Archive => "Archived",
+        }
+    }
+
+    ///for `result` in the JSON
+    fn result(&self) -> &'static str {
+        match self {
+            Action::Done => "done",
+            Action::Cancel => "cancelled",
+            Action::Undone => "reopened",
+            Action::Delete => "deleted",
+            Action::Move => "moved",
+            Action::Snooze => "snoozed",
+            Action::Edit => "edited",
+            Action::Archive => "archived",
+        }
+    }
+
+    ///what it says instead when there's nothing to do
+    fn already(&self, state: TodoState) -> Option<&'static str> {
+        match (self, state) {
+            (Action::Done, TodoState::Completed) => Some("Already done"),
+            (Action::Cancel, TodoState::Cancelled) => Some("Already cancelled"),
+            (Action::Undone, TodoState::Open) => Some("Not done"),
+            (Action::Snooze, TodoState::Completed) => Some("Already done"),
+            (Action::Snooze, TodoState::Cancelled) => Some("Already cancelled"),
+            _ => None,
+        }
+    }
+
+    ///what to ask before doing it to `count` reminders
+    fn question(&self, count: usize) -> String {
+        match self {
+            Action::Done => format!("Mark {count} reminder(s) done?"),
+            Action::Cancel => format!("Cancel {count} reminder(s)?"),
+            Action::Undone => format!("Reopen {count} reminder(s)?"),
+            Action::Delete => format!("Delete {count} reminder(s)?"),
+            Action::Move => format!("Move {count} reminder(s)?"),
+            Action::Snooze => format!("Snooze {count} reminder(s)?"),
+            Action::Edit => format!("Edit {count} reminder(s)?"),
+            Action::Archive => format!("Archive {count} reminder(s)?"),
+        }
+    }
+}
+
+///a todo found by id. the calendar's todos are shared with its cache, so
+///this keeps an index into them rather than a copy
+#[derive(Clone)]
+pub struct Found {
+    pub cal: CalendarRef,
+    todos: Arc<Partial<CalendarTodo>>,
+    tree: Arc<TodoTree>,
+    index: usize,
+}
+
+impl Found {
+    ///each of `todos`, fetched from `cal`, with the tree of them made once
+    pub fn all(cal: &CalendarRef, todos: &Arc<Partial<CalendarTodo>>) -> Vec<Found> {
+        let tree = Arc::new(TodoTree::new(&todos.items));
+        (0..todos.items.len())
+            .map(|index| Found { cal: cal.clone(), todos: todos.clone(), tree: tree.clone(), index })
+            .collect()
+    }
+
+    pub fn todo(&self) -> &CalendarTodo {
+        &self.todos.items[self.index]
+    }
+
+    ///everything fetched from its calendar, itself included
+    pub fn siblings(&self) -> &[CalendarTodo] {
+        &self.todos.items
+    }
+
+    ///the ones right under it
+    pub fn subtasks(&self) -> Vec<&CalendarTodo> {
+        self.tree.subtasks(&self.todos.items, self.index)
+    }
+
+    ///its subtasks, each followed by its own
+    pub fn descendants(&self) -> Vec<Found> {
+        self.tree
+            .descendants(self.index)
+            .into_iter()
+            .map(|index| Found { index, ..self.clone() })
+            .collect()
+    }
+}
+
+///the id `list` shows for `todo`
+pub fn short_id(todo: &CalendarTodo) -> String {
+    todo.uid().unwrap_or_default().chars().take(SHORT_ID).collect()
+}
+
+///its SUMMARY, or nothing
+pub fn summary(todo: &CalendarTodo) -> String {
+    todo.text("SUMMARY").unwrap_or_default()
+}
+
+///looks up each of `ids` in every todo calendar. an id is a whole UID or
+///the start of exactly one
+pub async fn resolve(store: &dyn TodoStore, ids: &[String], jobs: usize) -> anyhow::Result<Vec<anyhow::Result<Found>>> {
+    let cals = store::todo_calendars(store).await;
+    let results = store::todos_many(store, &cals, Query::All, jobs).await;
+    let mut all = vec![];
+    for (cal, todos) in cals.into_iter().zip(results) {
+        all.extend(Found::all(&cal, &todos?));
+    }
+
+    let uid = |found: &Found| found.todo().uid().unwrap_or_default();
+    let mut resolved = vec![];
+    for id in ids {
+        let exact = all.iter().position(|found| uid(found) == *id);
+        let matching: Vec<usize> = match exact {
+            Some(index) => vec![index],
+            None => (0..all.len()).filter(|&i| uid(&all[i]).starts_with(id.as_str())).collect(),
+        };
+        resolved.push(match matching[..] {
+            [index] => Ok(all[index].clone()),
+            [] => Err(CliError::NoSuchReminder(id.clone()).into()),
+            _ => Err(CliError::AmbiguousReminder { id: id.clone(), count: matching.len() }.into()),
+        });
+    }
+    Ok(resolved)
+}
+
+///every todo in every todo calendar that `filter` matches, done and
+///cancelled ones included
+pub async fn matching(store: &dyn TodoStore, filter: &Filter, jobs: usize) -> anyhow::Result<Vec<Found>> {
+    let cals = store::todo_calendars(store).await;
+    let results = store::todos_many(store, &cals, Query::All, jobs).await;
+    let now = Local::now();
+    let mut found = vec![];
+    for (cal, todos) in cals.into_iter().zip(results) {
+        let calendar = cal.read().await;
+        for todo in Found::all(&cal, &todos?) {
+            if filter.matches(&TodoRow::new(&calendar, todo.todo(), &todo.subtasks()), now) {
+                found.push(todo);
+            }
+        }
+    }
+    Ok(found)
+}
+
+///runs `action` on each of `ids`, or what [`Options::filter`] matches, and
+///prints how each went. they go `jobs` at a time, printed in order. fails
+///with the last error if any of them did, after trying the rest
+pub async fn run(
+    store: &dyn TodoStore,
+    action: Action,
+    ids: &[String],
+    options: &Options,
+    output: &Output,
+    jobs: usize,
+) -> anyhow::Result<()> {
+    let resolved: Vec<(String, anyhow::Result<Found>)> = match &options.filter {
+        Some(filter) => matching(store, filter, jobs).await?.into_iter().map(|found| (short_id(found.todo()), Ok(found))).collect(),
+        None => ids.iter().cloned().zip(resolve(store, ids, jobs).await?).collect(),
+    };
+    run_resolved(store, action, resolved, options, output, jobs).await
+}
+
+///[`run`] on todos already found, for commands that pick them themselves
+pub async fn run_found(
+    store: &dyn TodoStore,
+    action: Action,
+    found: Vec<Found>,
+    options: &Options,
+    output: &Output,
+    jobs: usize,
+) -> anyhow::Result<()> {
+    let resolved = found.into_iter().map(|found| (short_id(found.todo()), Ok(found))).collect();
+    run_resolved(store, action, resolved, options, output, jobs).await
+}
+
+async fn run_resolved(
+    store: &dyn TodoStore,
+    action: Action,
+    resolved: Vec<(String, anyhow::Result<Found>)>,
+    options: &Options,
+    output: &Output,
+    jobs: usize,
+) -> anyhow::Result<()> {
+    if resolved.is_empty() {
+        return match output.is_json() {
+            true => output.json::<ActionResult>(&[]),
+            false => {
+                println!("Nothing matches");
+                Ok(())
+            }
+        };
+    }
+
+    //subtasks come right after their parent, under their own id
+    let mut tasks: Vec<(String, anyhow::Result<Found>)> = vec![];
+    let mut queued = HashSet::new();
+    for (id, found) in resolved {
+        let Ok(found) = found else {
+            tasks.push((id, found));
+            continue;
+        };
+        let descendants = match options.recursive || action == Action::Move {
+            true => found.descendants(),
+            false => vec![],
+        };
+        if !queued.insert(found.todo().url.clone()) {
+            continue;
+        }
+        tasks.push((id, Ok(found)));
+        for found in descendants {
+            if queued.insert(found.todo().url.clone()) {
+                tasks.push((short_id(found.todo()), Ok(found)));
+            }
+        }
+    }
+
+    let found: Vec<&Found> = tasks.iter().filter_map(|(_, r)| r.as_ref().ok()).collect();
+    if options.dry_run {
+        return dry_run(&tasks, output).await;
+    }
+    if (action == Action::Delete || options.filter.is_some()) && !options.yes && !found.is_empty() {
+        confirm(action, &found.iter().map(|found| found.todo()).collect::<Vec<_>>())?;
+    }
+
+    let total = tasks.len();
+    let mut applying = stream::iter(tasks)
+        .map(|(id, found)| async move {
+            let mut result = ActionResult { id, uid: None, summary: None, result: "failed", message: String::new() };
+            let applied = match found {
+                Ok(found) => {
+                    result.uid = found.todo().uid();
+                    result.summary = Some(summary(found.todo()));
+                    let applied = apply(store, action, &found, options).await;
+                    if applied.as_ref().is_ok_and(|(changed, _)| *changed) && !options.recursive {
+                        open_subtasks(action, &found);
+                    }
+                    applied
+                }
+                Err(e) => Err(e),
+            };
+            (result, applied)
+        })
+        .buffered(jobs.max(1));
+
+    let mut failed = vec![];
+    let mut last_error = None;
+    let mut results = vec![];
+    while let Some((mut result, applied)) = applying.next().await {
+        match applied {
+            Ok((changed, message)) => {
+                result.result = if changed { action.result() } else { "unchanged" };
+                result.message = message;
+                if !output.is_json() {
+                    println!("{}", result.message);
+                }
+            }
+            Err(e) => {
+                result.message = format!("{e:#}");
+                //one is said right away, several all together at the end
+                if !output.is_json() && total == 1 {
+                    eprintln!("{}: {}", result.id, result.message);
+                }
+                failed.push((result.id.clone(), result.summary.clone(), result.message.clone()));
+                last_error = Some(e);
+            }
+        }
+        results.push(result);
+    }
+    if output.is_json() {
+        output.json(&results)?;
+    } else if total > 1 && !failed.is_empty() {
+        eprintln!("These failed:");
+        for (id, summary, message) in &failed {
+            match summary {
+                Some(summary) => eprintln!("  {id}  {summary}: {message}"),
+                None => eprintln!("  {id}: {message}"),
+            }
+        }
+    }
+    failures(failed.len(), total, last_error)
+}
+
+///lists `todos` on stderr and asks before doing `action` to them
+pub fn confirm(action: Action, todos: &[&CalendarTodo]) -> anyhow::Result<()> {
+    for todo in todos {
+        eprintln!("{}  {}", short_id(todo), summary(todo));
+    }
+    match prompt::confirm(&action.question(todos.len())) {
+        true => Ok(()),
+        false => Err(CliError::Declined.into()),
+    }
+}
+
+///prints what [`run`] would change, and the ids that didn't resolve
+async fn dry_run(tasks: &[(String, anyhow::Result<Found>)], output: &Output) -> anyhow::Result<()> {
+    let mut rows = vec![];
+    let mut failed = 0;
+    let mut last_error = None;
+    for (id, found) in tasks {
+        match found {
+            Ok(found) => rows.push(TodoRow::new(&*found.cal.read().await, found.todo(), &found.subtasks())),
+            Err(e) => {
+                eprintln!("{id}: {e:#}");
+                failed += 1;
+                last_error = Some(anyhow::anyhow!("{e:#}"));
+            }
+        }
+    }
+    output.todos(rows)?;
+    eprintln!("Dry run, nothing was changed");
+    failures(failed, tasks.len(), last_error)
+}
+
+///a word on stderr about subtasks left open by finishing their parent
+fn open_subtasks(action: Action, found: &Found) {
+    if !matches!(action, Action::Done | Action::Cancel) {
+        return;
+    }
+    let open = found.descendants().iter().filter(|d| d.todo().state() == TodoState::Open).count();
+    if open > 0 {
+        eprintln!("{open} subtask(s) of {} are still open, --recursive does them too", summary(found.todo()));
+    }
+}
+
+///fails with the last error if any of `total` items did, keeping its
+///type for the exit code
+pub fn failures(failed: usize, total: usize, last_error: Option<anyhow::Error>) -> anyhow::Result<()> {
+    match last_error {
+        Some(e) => Err(e.context(format!("{failed} of {total} failed"))),
+        None => Ok(()),
+    }
+}
+
+///whether anything changed, and what to say about it
+async fn apply(store: &dyn TodoStore, action: Action, found: &Found, options: &Options) -> anyhow::Result<(bool, String)> {
+    let todo = found.todo();
+    let summary = summary(todo);
+    if let Some(already) = action.already(todo.state()) {
+        return Ok((false, format!("{already}: {summary}")));
+    }
+    let now = Utc::now();
+    let ics = match action {
+        Action::Done => todo.completed(now),
+        Action::Cancel => todo.cancelled(now),
+        Action::Undone => todo.reopened(now),
+        Action::Delete => {
+            store.delete(todo).await?;
+            return Ok((true, format!("{}: {summary}", action.past())));
+        }
+        Action::Move => {
+            let to = options.to.as_ref().context("no calendar to move to")?.read().await;
+            if found.cal.read().await.url == to.url {
+                return Ok((false, format!("Already in {}: {summary}", to.name)));
+            }
+            relocate(store, todo, &to).await?;
+            return Ok((true, format!("{} to {}: {summary}", action.past(), to.name)));
+        }
+        Action::Archive => match options.archive.as_ref().context("no archive to move to")? {
+            Archive::Calendar(to) => {
+                let to = to.read().await;
+                if found.cal.read().await.url == to.url {
+                    return Ok((false, format!("Already in {}: {summary}", to.name)));
+                }
+                relocate(store, todo, &to).await?;
+                return Ok((true, format!("{} to {}: {summary}", action.past(), to.name)));
+            }
+            Archive::Local(archive) => {
+                let name = found.cal.read().await.name.clone();
+                archive.keep(&name, todo).await?;
+                store.delete(todo).await?;
+                return Ok((true, format!("{}: {summary}", action.past())));
+            }
+        },
+        Action::Snooze => {
+            let shift = options.shift.as_ref().context("no time to snooze to")?;
+            let (ics, due) = snooze::snoozed(todo, shift, now)?;
+            store.update(todo, &ics).await?;
+            return Ok((true, format!("{} to {}: {summary}", action.past(), due.local())));
+        }
+        Action::Edit => {
+            let changes = options.changes.as_ref().context("nothing to change")?;
+            let Some(ics) = new::edited(todo, changes, now)? else {
+                return Ok((false, format!("Unchanged: {summary}")));
+            };
+            let todo = store.update(todo, &ics).await?;
+            return Ok((true, format!("{} {}: {}", action.past(), short_id(&todo), self::summary(&todo))));
+        }
+    };
+    store.update(todo, &ics).await?;
+    Ok((true, format!("{}: {summary}", action.past())))
+}
+
+///`todo` in `to` rather than where it is, copied first so a failure leaves
+///it where it was
+async fn relocate(store: &dyn TodoStore, todo: &CalendarTodo, to: &Calendar) -> anyhow::Result<()> {
+    let uid = todo.uid().with_context(|| format!("{} has no UID", todo.url))?;
+    store.create(to, &uid, &todo.ics).await?;
+    store
+        .delete(todo)
+        .await
+        .with_context(|| format!("copied to {}, but the original is still there", to.name))?;
+    Ok(())
+}
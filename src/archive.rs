@@ -0,0 +1,150 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Context;
+use chrono::{DateTime, Local, Utc};
+use reminder_rs::caldav::{CalendarProps, CalendarRef, CalendarTodo, TodoState};
+use reminder_rs::store::{self, Query, TodoStore, VdirStore};
+use tokio::fs;
+use tokio::sync::{Mutex, RwLock};
+
+use crate::actions::{self, Action, Found, Options};
+use crate::args::PurgeCommand;
+use crate::calendars::calendar_id;
+use crate::find_calendar;
+use crate::output::{Output, TodoRow};
+use crate::state_dir;
+use crate::when;
+
+///where `delete` puts reminders instead of deleting them
+pub enum Archive {
+    ///a calendar, REMINDER_ARCHIVE_CALENDAR
+    Calendar(CalendarRef),
+    ///a vdir in the state directory, with `--local-archive`
+    Local(LocalArchive),
+}
+
+///a vdir with a calendar for each one reminders were archived from, so
+///`REMINDER_VDIR` can point at it to look through them
+pub struct LocalArchive {
+    root: PathBuf,
+    ///one at a time, so two from the same calendar don't both make its directory
+    lock: Mutex<()>,
+}
+
+impl Archive {
+    ///the archive `delete` uses: none with `--permanent`, the local one with
+    ///`--local-archive`, otherwise REMINDER_ARCHIVE_CALENDAR if it's set
+    pub async fn chosen(store: &dyn TodoStore, local: bool, permanent: bool) -> anyhow::Result<Option<Archive>> {
+        if permanent {
+            return Ok(None);
+        }
+        if local {
+            return Ok(Some(Archive::Local(LocalArchive::new(state_dir()?.join("archive")))));
+        }
+        match env::var("REMINDER_ARCHIVE_CALENDAR") {
+            Ok(name) if !name.is_empty() => Ok(Some(Archive::Calendar(find_calendar(store, &name).await?))),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl LocalArchive {
+    pub fn new(root: PathBuf) -> LocalArchive {
+        LocalArchive { root, lock: Mutex::new(()) }
+    }
+
+    ///copies `todo` into the archive's calendar called `calendar`, over an
+    ///earlier copy with the same UID
+    pub async fn keep(&self, calendar: &str, todo: &CalendarTodo) -> anyhow::Result<()> {
+        let _lock = self.lock.lock().await;
+        fs::create_dir_all(&self.root).await.with_context(|| format!("can't make {}", self.root.display()))?;
+        let archive = VdirStore::open(&self.root).await?;
+        let cal = match store::find_calendar(&archive, calendar).await {
+            Some(cal) => cal,
+            None => {
+                let props = CalendarProps::builder().name(calendar.to_string()).build();
+                Arc::new(RwLock::new(archive.create_calendar(&calendar_id(calendar), &props).await?))
+            }
+        };
+        let uid = todo.uid().with_context(|| format!("{} has no UID", todo.url))?;
+        let todos = archive.todos(&cal, Query::All).await?;
+        match todos.items.iter().find(|kept| kept.uid().as_ref() == Some(&uid)) {
+            Some(kept) => archive.update(kept, &todo.ics).await?,
+            None => archive.create(&*cal.read().await, &uid, &todo.ics).await?,
+        };
+        Ok(())
+    }
+}
+
+///`90d` for that long ago, or a date like 2026-01-01. for clap
+pub fn before(text: &str) -> Result<DateTime<Utc>, String> {
+    match when::span(text.trim()) {
+        Some(span) => Utc::now().checked_sub_signed(span).ok_or(format!("{} is too long ago", text.trim())),
+        None => when::parse(text).map(|date| date.instant()),
+    }
+}
+
+///deletes for good the reminders completed before `--completed-before`,
+///after asking and then writing them all to one .ics file
+pub async fn purge(store: &dyn TodoStore, command: &PurgeCommand, output: &Output, jobs: usize) -> anyhow::Result<()> {
+    let cals = match &command.calendar {
+        Some(name) => vec![find_calendar(store, name).await?],
+        None => store::todo_calendars(store).await,
+    };
+    let query = Query::History { since: None, until: Some(command.completed_before) };
+    let now = Local::now();
+    let mut old = vec![];
+    for (cal_ref, todos) in cals.iter().zip(store::todos_many(store, &cals, query, jobs).await) {
+        let cal = cal_ref.read().await;
+        for found in Found::all(cal_ref, &todos?).into_iter().filter(|found| found.todo().state() == TodoState::Completed) {
+            let filter = &command.bulk.filter;
+            if filter.as_ref().is_none_or(|filter| filter.matches(&TodoRow::new(&cal, found.todo(), &found.subtasks()), now)) {
+                old.push(found);
+            }
+        }
+    }
+    if old.is_empty() {
+        return match output.is_json() {
+            true => output.json::<TodoRow>(&[]),
+            false => {
+                println!("Nothing was completed before {}", command.completed_before.with_timezone(&Local).format("%Y-%m-%d"));
+                Ok(())
+            }
+        };
+    }
+
+    //exactly these get deleted, so the export holds what went
+    let todos: Vec<&CalendarTodo> = old.iter().map(Found::todo).collect();
+    let options = Options { yes: command.bulk.yes, dry_run: command.bulk.dry_run, ..Default::default() };
+    if !options.dry_run {
+        //asked here rather than by actions::run, so a no leaves no export behind
+        if !options.yes {
+            actions::confirm(Action::Delete, &todos)?;
+        }
+        let path = match &command.export {
+            Some(path) => path.clone(),
+            None => state_dir()?.join(format!("purged-{}.ics", now.format("%Y%m%d-%H%M%S"))),
+        };
+        export(&todos, &path).await?;
+        eprintln!("Exported {} reminder(s) to {}", todos.len(), path.display());
+    }
+    actions::run_found(store, Action::Delete, old, &Options { yes: true, ..options }, output, jobs).await
+}
+
+///`todos` one after another in one file, which RFC 5545 allows as a stream
+///of calendar objects
+async fn export(todos: &[&CalendarTodo], path: &Path) -> anyhow::Result<()> {
+    let mut ics = String::new();
+    for todo in todos {
+        ics.push_str(&todo.ics);
+        if !todo.ics.ends_with('\n') {
+            ics.push_str("\r\n");
+        }
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).await?;
+    }
+    fs::write(path, ics).await.with_context(|| format!("can't write {}", path.display()))
+}
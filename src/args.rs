@@ -1,14 +1,51 @@
+use std::path::PathBuf;
+
 use clap:: {
     Args,
     Parser,
     Subcommand
 };
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use reminder_rs::caldav::alarm::Trigger;
+use reminder_rs::caldav::datetime::DateValue;
+
+use crate::archive;
+use crate::filter::Filter;
+use crate::listing::{GroupBy, SortKey};
+use crate::new::Setting;
+use crate::output::{Column, Format};
+use crate::when;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct ReminderArgs {
+    /// Print each request and service discovery attempt
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// Request timeout in seconds
+    #[arg(long, global = true, default_value_t = 30)]
+    pub timeout: u64,
+
+    /// How many times to retry requests that failed with a server or connection error
+    #[arg(long, global = true, default_value_t = 2)]
+    pub retries: u32,
+
+    /// How many calendars to fetch, or reminders to change, at once
+    #[arg(short, long, global = true, default_value_t = 4)]
+    pub jobs: usize,
+
+    /// Output format. Defaults to table on a terminal, plain otherwise or if NO_COLOR is set
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<Format>,
+
+    /// Comma separated columns for reminders in table and plain output [default: id,calendar,summary,due]
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    pub columns: Option<Vec<Column>>,
+
+    /// Defaults to today
     #[clap(subcommand)]
-    pub subcommand: ReminderSubcommands,
+    pub subcommand: Option<ReminderSubcommands>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -16,8 +53,10 @@ pub enum ReminderSubcommands {
     /// Open TUI
     Interactive(InteractiveCommand),
 
-    /// List calendars
+    /// List or manage calendars
     Calendars(CalendarsCommand),
+    /// Show what the server and each calendar support
+    ServerInfo(ServerInfoCommand),
 
     /// Create new reminder in TUI or with arguments
     New(NewCommand),
@@ -28,22 +67,45 @@ pub enum ReminderSubcommands {
     /// Import .ics file
     Import(ImportCommand),
 
+    /// Print what's overdue, due today or started, and important reminders without a due date
+    Today(TodayCommand),
+    /// Like today, followed by what's due in the next few days
+    Agenda(AgendaCommand),
     /// Print all incomplete reminders
     List(ListCommand),
     /// Search for a reminder
     Search(SearchCommand),
+    /// Print completed and cancelled reminders by the day they were finished
+    History(HistoryCommand),
     /// Move reminder(s) to another calendar
     Move(MoveCommand),
 
     /// Mark reminder(s) as cancelled
     Cancel(ActionCommand),
-    /// Delete reminder(s). This is PERMANENT.
-    Delete(ActionCommand),
+    /// Delete reminder(s), or archive them if REMINDER_ARCHIVE_CALENDAR is set. `undo` brings them back
+    Delete(DeleteCommand),
     /// Mark reminder(s) as done
     Done(ActionCommand),
+    /// Mark done or cancelled reminder(s) as not done
+    #[command(alias = "reopen")]
+    Undone(ActionCommand),
+    /// Move the due date of reminder(s), and the start date along with it
+    Snooze(SnoozeCommand),
+    /// Move every overdue reminder to another day
+    PostponeOverdue(PostponeCommand),
 
     /// Show all info about reminder(s)
-    Info(ActionCommand),
+    Info(InfoCommand),
+
+    /// Keep checking for alarms and run a command like notify-send for each one that goes off
+    Daemon(DaemonCommand),
+
+    /// Delete completed reminders for good after writing them to a file
+    Purge(PurgeCommand),
+    /// Put back what the last command that changed reminders changed
+    Undo(UndoCommand),
+    /// Print the latest commands that changed reminders, newest first
+    Log(LogCommand),
 
     /// TODO Remove
     Test(TestCommand)
@@ -51,6 +113,78 @@ pub enum ReminderSubcommands {
 
 #[derive(Debug, Args)]
 pub struct CalendarsCommand {
+    /// Include calendars that only hold events
+    #[arg(short, long)]
+    pub all: bool,
+
+    #[clap(subcommand)]
+    pub action: Option<CalendarsAction>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CalendarsAction {
+    /// Create a calendar for reminders
+    Create(CalendarCreateCommand),
+    /// Rename a calendar
+    Rename(CalendarRenameCommand),
+    /// Change a calendar's color
+    Color(CalendarColorCommand),
+    /// Change a calendar's description
+    Describe(CalendarDescribeCommand),
+    /// Set where a calendar goes in listings, lowest first
+    Order(CalendarOrderCommand),
+    /// Delete a calendar and every reminder in it. This is PERMANENT.
+    Delete(CalendarDeleteCommand),
+}
+
+#[derive(Debug, Args)]
+pub struct CalendarCreateCommand {
+    pub name: String,
+    /// As #RRGGBB
+    #[arg(long)]
+    pub color: Option<String>,
+    #[arg(short, long)]
+    pub description: Option<String>,
+    #[arg(long, allow_hyphen_values = true)]
+    pub order: Option<i64>,
+}
+
+#[derive(Debug, Args)]
+pub struct CalendarRenameCommand {
+    pub calendar: String,
+    pub name: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CalendarColorCommand {
+    pub calendar: String,
+    /// As #RRGGBB
+    pub color: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CalendarDescribeCommand {
+    pub calendar: String,
+    pub description: String,
+}
+
+#[derive(Debug, Args)]
+pub struct CalendarOrderCommand {
+    pub calendar: String,
+    #[arg(allow_hyphen_values = true)]
+    pub order: i64,
+}
+
+#[derive(Debug, Args)]
+pub struct CalendarDeleteCommand {
+    pub calendar: String,
+    /// Don't ask for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct ServerInfoCommand {
 
 }
 
@@ -59,47 +193,189 @@ pub struct TestCommand {
     pub pong: Option<i32>
 }
 
+///which reminders a command changes, by id or `--where`
 #[derive(Debug, Args)]
-pub struct ActionCommand {
-    #[arg(num_args=1..)]
-    reminders: Vec<i32>
+pub struct Selection {
+    /// Ids as shown by `list`, or the start of one
+    #[arg(required_unless_present = "filter", conflicts_with = "filter")]
+    pub reminders: Vec<String>,
+    #[command(flatten)]
+    pub bulk: Bulk,
 }
 
+///`--where` and what goes with changing many reminders at once
 #[derive(Debug, Args)]
-pub struct EditCommand {
+pub struct Bulk {
+    /// Every reminder matching a filter instead, eg. 'cat:groceries and status:done'. Keys are cat, cal,
+    /// status, priority, due, start, summary, location and id, a word alone is looked for in the summary
+    #[arg(long = "where", value_parser = Filter::parse)]
+    pub filter: Option<Filter>,
+    /// Print what it would change and change nothing
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Don't ask for confirmation, which it does before deleting and for --where
     #[arg(short, long)]
-    reminder: i32
+    pub yes: bool,
 }
 
 #[derive(Debug, Args)]
-pub struct MoveCommand {
+pub struct ActionCommand {
+    #[command(flatten)]
+    pub selection: Selection,
+    /// Subtasks too, and theirs
     #[arg(short, long)]
-    calendar: String,
+    pub recursive: bool,
+}
 
-    #[arg(num_args=1..)]
-    reminders: Vec<i32>
+#[derive(Debug, Args)]
+pub struct InfoCommand {
+    /// Ids as shown by `list`, or the start of one
+    #[arg(num_args=1.., required = true)]
+    pub reminders: Vec<String>,
+    /// Print the iCalendar object as stored
+    #[arg(long, conflicts_with = "json")]
+    pub raw: bool,
+    /// Same as --format json
+    #[arg(long)]
+    pub json: bool,
 }
 
 #[derive(Debug, Args)]
-pub struct NewCommand {
+pub struct DeleteCommand {
+    #[command(flatten)]
+    pub selection: Selection,
+    /// Subtasks too, and theirs
     #[arg(short, long)]
-    calendar: Option<String>,
+    pub recursive: bool,
+    /// Keep a copy in the archive in the state directory, a vdir, rather than the archive calendar
+    #[arg(long, conflicts_with = "permanent")]
+    pub local_archive: bool,
+    /// Delete them even if there's an archive calendar
+    #[arg(long)]
+    pub permanent: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PurgeCommand {
+    /// Completed longer ago than this, eg. 90d or 8w, or before a date like 2026-01-01
+    #[arg(long, value_parser = archive::before)]
+    pub completed_before: DateTime<Utc>,
+    /// Only the ones in this calendar
     #[arg(short, long)]
-    start: Option<String>,
+    pub calendar: Option<String>,
+    /// The .ics file they're written to first. Defaults to purged-<time>.ics in the state directory
+    #[arg(long)]
+    pub export: Option<PathBuf>,
+    #[command(flatten)]
+    pub bulk: Bulk,
+}
+
+#[derive(Debug, Args)]
+pub struct SnoozeCommand {
+    #[command(flatten)]
+    pub bulk: Bulk,
+    /// Ids as shown by `list`, or the start of one, then where to move them, which is all it takes with
+    /// --where: +3d or +2h to move it by that much (-1d, after a --, for earlier), or a date like
+    /// tomorrow, friday or 2026-10-20, optionally followed by a time like 17:00. Moved to a day, it keeps
+    /// its time of day unless that's already past
+    #[arg(required = true, value_name = "REMINDERS... WHEN")]
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct UndoCommand {
+    /// Put them back even if they changed again since
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct LogCommand {
+    /// How many to show
+    #[arg(short = 'n', long, default_value_t = 20)]
+    pub limit: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct PostponeCommand {
+    /// Only the ones in this calendar
     #[arg(short, long)]
-    due: Option<String>,
+    pub calendar: Option<String>,
+    /// Same as for snooze, without +3d meaning 3 days after the due date
+    #[arg(long, default_value = "today", value_parser = when::parse)]
+    pub to: DateValue,
+}
+
+#[derive(Debug, Args)]
+pub struct EditCommand {
+    #[command(flatten)]
+    pub selection: Selection,
     #[arg(short, long)]
-    location: Option<String>,
+    pub summary: Option<String>,
+    #[command(flatten)]
+    pub fields: TodoFields,
+    /// Remove the alarms it has, before adding any --alarm
+    #[arg(long)]
+    pub no_alarms: bool,
+    /// Set a field, eg. priority=1 or due=friday, or remove it with nothing after the =. Fields are
+    /// summary, due, start, priority, location, description and categories (comma separated)
+    #[arg(long, value_parser = Setting::parse)]
+    pub set: Vec<Setting>,
     #[arg(short, long)]
-    priority: Option<i32>,
+    pub tui: bool
+}
+
+#[derive(Debug, Args)]
+pub struct MoveCommand {
+    /// Calendar to move them to. Subtasks go along
     #[arg(short, long)]
-    category: Option<String>,
+    pub calendar: String,
+    #[command(flatten)]
+    pub selection: Selection,
+}
+
+#[derive(Debug, Args)]
+pub struct NewCommand {
+    #[arg(required_unless_present = "tui")]
+    pub summary: Option<String>,
+    /// Defaults to the parent's calendar, or the first one
     #[arg(short, long)]
-    description: Option<String>,
+    pub calendar: Option<String>,
+    /// Make it a subtask of this reminder
+    #[arg(long)]
+    pub parent: Option<String>,
+    #[command(flatten)]
+    pub fields: TodoFields,
     #[arg(short, long)]
-    summary: Option<String>,
+    pub tui: bool
+}
+
+///what `new` and `edit` can set
+#[derive(Debug, Clone, Default, Args)]
+pub struct TodoFields {
+    /// A date like 2026-10-20, today, tomorrow, friday or +3d, optionally followed by a time like 17:00
+    #[arg(long, value_parser = when::parse)]
+    pub start: Option<DateValue>,
+    /// Same as --start
+    #[arg(short, long, value_parser = when::parse)]
+    pub due: Option<DateValue>,
     #[arg(short, long)]
-    tui: bool
+    pub location: Option<String>,
+    /// 1 is the highest, 9 the lowest
+    #[arg(short, long, value_parser = clap::value_parser!(u8).range(0..=9))]
+    pub priority: Option<u8>,
+    /// Can be given more than once. Replaces the ones it has
+    #[arg(long = "category")]
+    pub categories: Vec<String>,
+    #[arg(long)]
+    pub description: Option<String>,
+    /// -15m before due, start-15m or start+0m from the start, or a time like "2026-10-20 09:00".
+    /// Can be given more than once
+    #[arg(long = "alarm", value_parser = when::alarm, allow_hyphen_values = true)]
+    pub alarms: Vec<Trigger>,
+    /// Have each --alarm go off again, eg. 3x5m for three more times five minutes apart
+    #[arg(long, value_parser = when::repeat, requires = "alarms")]
+    pub alarm_repeat: Option<(u32, Duration)>,
 }
 
 #[derive(Debug, Args)]
@@ -125,6 +401,65 @@ pub struct SearchCommand {
 pub struct ListCommand {
     #[arg(short, long)]
     pub calendar: Option<String>,
+    /// Comma separated keys out of due, start, priority, created, modified, summary, calendar
+    /// and status, each with a leading - to reverse it. Reminders without one go last
+    /// [default: due,priority,summary]
+    #[arg(long, value_delimiter = ',', value_parser = SortKey::parse, allow_hyphen_values = true)]
+    pub sort: Option<Vec<SortKey>>,
+    /// All calendars are shown as one list unless grouped by calendar
+    #[arg(long, value_enum, default_value_t = GroupBy::None)]
+    pub group_by: GroupBy,
+    /// Don't put subtasks under their parent. Grouping by anything other than calendar doesn't either
+    #[arg(long)]
+    pub flat: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryCommand {
+    #[arg(short, long)]
+    pub calendar: Option<String>,
+    /// First day to include, as YYYY-MM-DD
+    #[arg(long)]
+    pub since: Option<NaiveDate>,
+    /// Last day to include, as YYYY-MM-DD
+    #[arg(long)]
+    pub until: Option<NaiveDate>,
+    /// Print how many were done and how many cancelled each day and week instead
+    #[arg(long)]
+    pub stats: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TodayCommand {
+
+}
+
+#[derive(Debug, Args)]
+pub struct AgendaCommand {
+    /// How many days to show, today included
+    #[arg(short, long, default_value_t = 7, value_parser = clap::value_parser!(u32).range(1..))]
+    pub days: u32,
+}
+
+#[derive(Debug, Args)]
+pub struct DaemonCommand {
+    /// What to run for each alarm, split into arguments like a shell would. {summary}, {description},
+    /// {due}, {time}, {calendar}, {location}, {id} and {uid} are filled in
+    /// [env: REMINDER_ALARM_COMMAND] [default: notify-send --app-name=reminder-rs {summary} {description}]
+    #[arg(long)]
+    pub command: Option<String>,
+    /// Seconds between checks
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: u64,
+    /// How many minutes back alarms missed while it wasn't running still go off
+    #[arg(long, default_value_t = 60)]
+    pub catch_up: u32,
+    /// Check once and exit, eg. from cron
+    #[arg(long)]
+    pub once: bool,
+    /// Don't mark alarms that are done going off as ACKNOWLEDGED on the server, so other clients still show them
+    #[arg(long)]
+    pub no_acknowledge: bool,
 }
 
 #[derive(Debug, Args)]
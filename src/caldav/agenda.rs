@@ -0,0 +1,52 @@
+use chrono::{DateTime, Duration, Local, NaiveDate};
+
+use super::datetime::DateValue;
+use super::details::TodoDetails;
+use super::todo::TodoState;
+
+///where a todo shows up in an agenda. ordered the way they're shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AgendaSlot {
+    Overdue,
+    ///due that day. today also gets what has started and important
+    ///todos without a due date
+    Day(NaiveDate),
+}
+
+///which slot of an agenda of `days` days, today being the first, `details`
+///goes in. None if it isn't on it at all
+pub fn agenda_slot(details: &TodoDetails, now: DateTime<Local>, days: u32) -> Option<AgendaSlot> {
+    if details.state != TodoState::Open {
+        return None;
+    }
+    let today = now.date_naive();
+    let last = today + Duration::days(days.max(1) as i64 - 1);
+    if let Some(due) = &details.due {
+        if is_overdue(due, now) {
+            return Some(AgendaSlot::Overdue);
+        }
+        let day = due.local_date().max(today);
+        if day <= last {
+            return Some(AgendaSlot::Day(day));
+        }
+    }
+    let started = details.start.as_ref().is_some_and(|start| has_started(start, now));
+    let important = details.due.is_none() && details.priority.is_some_and(|p| (1..=4).contains(&p));
+    (started || important).then_some(AgendaSlot::Day(today))
+}
+
+///a DUE that's a date lasts all day, so it's only overdue the day after
+pub fn is_overdue(due: &DateValue, now: DateTime<Local>) -> bool {
+    match due {
+        DateValue::Date(date) => *date < now.date_naive(),
+        _ => due.instant() < now,
+    }
+}
+
+///a DTSTART that's a date starts first thing that day
+pub fn has_started(start: &DateValue, now: DateTime<Local>) -> bool {
+    match start {
+        DateValue::Date(date) => *date <= now.date_naive(),
+        _ => start.instant() <= now,
+    }
+}
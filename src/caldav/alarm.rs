@@ -0,0 +1,242 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::component::{escape, Component, Prop};
+use super::datetime::{format_duration, parse_duration, DateValue};
+use super::todo::{format_utc, new_uid, set_property, split_line, CalendarTodo};
+
+///what a relative TRIGGER counts from. for a todo that's DTSTART or DUE
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Related {
+    Start,
+    Due,
+}
+
+///when an alarm goes off (RFC 5545 3.8.6.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    ///before DTSTART or DUE if negative, after if positive
+    Relative { offset: Duration, related: Related },
+    At(DateTime<Utc>),
+}
+
+///an alarm for [`add_alarms`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlarmSpec {
+    pub trigger: Trigger,
+    ///how many more times it goes off, and how far apart (REPEAT and DURATION)
+    pub repeat: Option<(u32, Duration)>,
+}
+
+///one time an alarm goes off, see [`firings`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Firing {
+    ///which VALARM of the VTODO it is, counting from 0. None for a
+    ///Thunderbird snooze, which is on the VTODO itself
+    pub alarm: Option<usize>,
+    ///the same from one fetch to the next: the alarm's UID, or where it is
+    ///and its TRIGGER for alarms without one
+    pub key: String,
+    pub at: DateTime<Utc>,
+    ///it doesn't repeat after this
+    pub last: bool,
+    pub description: Option<String>,
+}
+
+impl AlarmSpec {
+    ///the VALARM, one content line each
+    fn lines(&self, description: &str) -> Vec<String> {
+        let trigger = match self.trigger {
+            Trigger::Relative { offset, related: Related::Start } => format!("TRIGGER:{}", format_duration(offset)),
+            Trigger::Relative { offset, related: Related::Due } => format!("TRIGGER;RELATED=END:{}", format_duration(offset)),
+            Trigger::At(at) => format!("TRIGGER;VALUE=DATE-TIME:{}", format_utc(at)),
+        };
+        let mut lines = vec![
+            "BEGIN:VALARM".to_string(),
+            format!("UID:{}", new_uid()),
+            "ACTION:DISPLAY".to_string(),
+            format!("DESCRIPTION:{}", escape(description)),
+            trigger,
+        ];
+        if let Some((times, every)) = self.repeat {
+            lines.push(format!("REPEAT:{times}"));
+            lines.push(format!("DURATION:{}", format_duration(every)));
+        }
+        lines.push("END:VALARM".to_string());
+        lines
+    }
+}
+
+///every time `todo`'s display and audio alarms go off, repeats included,
+///leaving out the ones already acknowledged (ACKNOWLEDGED from RFC 9074,
+///or Thunderbird's X-MOZ-LASTACK). alarms relative to a DTSTART or DUE the
+///todo doesn't have never go off. a Thunderbird snooze (X-MOZ-SNOOZE-TIME)
+///goes off once more
+pub fn firings(todo: &CalendarTodo) -> Vec<Firing> {
+    let Some(root) = todo.root() else { return vec![] };
+    let Some(vtodo) = root.child("VTODO") else { return vec![] };
+    let date = |c: &Component, name| c.prop(name).and_then(|p| DateValue::from_prop(p, &root)).map(|d| d.instant());
+    let (start, due, last_ack) = (date(vtodo, "DTSTART"), date(vtodo, "DUE"), date(vtodo, "X-MOZ-LASTACK"));
+
+    let mut found = vec![];
+    for (i, alarm) in vtodo.children("VALARM").enumerate() {
+        let action = alarm.prop("ACTION").map(|p| p.value.trim().to_ascii_uppercase());
+        if !matches!(action.as_deref(), None | Some("DISPLAY" | "AUDIO")) {
+            continue;
+        }
+        let Some(trigger) = alarm.prop("TRIGGER") else { continue };
+        let Some(first) = trigger_time(trigger, &root, start, due) else { continue };
+        let acked = date(alarm, "ACKNOWLEDGED").max(last_ack);
+        //REPEAT without a DURATION doesn't say when, so it doesn't
+        let every = alarm.prop("DURATION").and_then(|p| parse_duration(&p.value)).filter(|d| *d > Duration::zero());
+        let repeat = match every {
+            Some(_) => alarm.prop("REPEAT").and_then(|p| p.value.trim().parse::<u32>().ok()).unwrap_or(0).min(1000),
+            None => 0,
+        };
+        let key = match alarm.prop("UID") {
+            Some(uid) => uid.value.trim().to_string(),
+            None => format!("{i}:{}", trigger.value.trim()),
+        };
+        for n in 0..=repeat {
+            //repeats past the last date there is don't go off
+            let offset = i32::try_from(n).ok().and_then(|n| every.unwrap_or_default().checked_mul(n));
+            let Some(at) = offset.and_then(|offset| first.checked_add_signed(offset)) else { break };
+            if acked.is_some_and(|acked| at <= acked) {
+                continue;
+            }
+            let description = alarm.prop("DESCRIPTION").map(Prop::text);
+            found.push(Firing { alarm: Some(i), key: key.clone(), at, last: n == repeat, description });
+        }
+    }
+    if let Some(at) = date(vtodo, "X-MOZ-SNOOZE-TIME").filter(|at| last_ack.is_none_or(|ack| *at > ack)) {
+        found.push(Firing { alarm: None, key: "snooze".to_string(), at, last: true, description: None });
+    }
+    found
+}
+
+///when TRIGGER first goes off. relative ones count from the start unless
+///RELATED=END, which for a todo is its DUE
+fn trigger_time(
+    trigger: &Prop,
+    root: &Component,
+    start: Option<DateTime<Utc>>,
+    due: Option<DateTime<Utc>>,
+) -> Option<DateTime<Utc>> {
+    if trigger.param("VALUE").is_some_and(|v| v.eq_ignore_ascii_case("DATE-TIME")) {
+        return DateValue::from_prop(trigger, root).map(|at| at.instant());
+    }
+    let anchor = match trigger.param("RELATED") {
+        Some(related) if related.eq_ignore_ascii_case("END") => due,
+        _ => start,
+    };
+    anchor?.checked_add_signed(parse_duration(&trigger.value)?)
+}
+
+///`ics` with a VALARM for each of `alarms` added to the first VTODO,
+///`description` being what they show
+pub fn add_alarms(ics: &str, alarms: &[AlarmSpec], description: &str) -> String {
+    rewrite(ics, |line, spot| match spot {
+        Spot::TodoEnd => alarms.iter().flat_map(|alarm| alarm.lines(description)).chain([line.to_string()]).collect(),
+        _ => vec![line.to_string()],
+    })
+}
+
+///`ics` without the first VTODO's VALARMs
+pub fn remove_alarms(ics: &str) -> String {
+    rewrite(ics, |line, spot| match spot {
+        Spot::Alarm(_) => vec![],
+        _ => vec![line.to_string()],
+    })
+}
+
+///`ics` with [`Firing::alarm`] acknowledged `at`, so no client fires it again.
+///a Thunderbird snooze is acknowledged the way Thunderbird does it
+pub fn acknowledged(ics: &str, alarm: Option<usize>, at: DateTime<Utc>) -> String {
+    let stamp = format_utc(at);
+    let Some(alarm) = alarm else {
+        let ics = set_property(ics, "X-MOZ-LASTACK", Some(&stamp));
+        return set_property(&ics, "X-MOZ-SNOOZE-TIME", None);
+    };
+    rewrite(ics, |line, spot| {
+        let key = split_line(line).map(|(key, _)| key.split(';').next().unwrap_or(key).to_ascii_uppercase());
+        match (spot, key.as_deref()) {
+            (Spot::Alarm(i), Some("ACKNOWLEDGED")) if i == alarm => vec![],
+            (Spot::Alarm(i), Some("END")) if i == alarm => vec![format!("ACKNOWLEDGED:{stamp}"), line.to_string()],
+            _ => vec![line.to_string()],
+        }
+    })
+}
+
+///where a content line is, for [`rewrite`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Spot {
+    ///anywhere but the first VTODO
+    Outside,
+    ///the VTODO's own lines, BEGIN:VTODO included
+    Todo,
+    ///its `n`th VALARM, from BEGIN to END
+    Alarm(usize),
+    ///its other nested components
+    Nested,
+    ///its END:VTODO
+    TodoEnd,
+}
+
+///`ics` rebuilt from what `f` makes of each content line and where it is.
+///a folded line comes in one piece, folding and all, and line endings stay
+///what they were
+fn rewrite(ics: &str, mut f: impl FnMut(&str, Spot) -> Vec<String>) -> String {
+    let newline = if ics.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<String> = vec![];
+    for line in ics.lines() {
+        match (line.starts_with([' ', '\t']), lines.last_mut()) {
+            (true, Some(last)) => {
+                last.push_str(newline);
+                last.push_str(line);
+            }
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut out = String::with_capacity(ics.len() + 256);
+    let (mut seen, mut in_todo, mut nested, mut alarms) = (false, false, 0, 0);
+    let mut alarm = None;
+    for line in &lines {
+        let (key, value) = split_line(line).unwrap_or((line, ""));
+        let key = key.split(';').next().unwrap_or(key).to_ascii_uppercase();
+        let value = value.trim().to_ascii_uppercase();
+        let spot = match (key.as_str(), value.as_str()) {
+            ("BEGIN", "VTODO") if !seen => {
+                (seen, in_todo) = (true, true);
+                Spot::Todo
+            }
+            ("END", "VTODO") if in_todo && nested == 0 => {
+                in_todo = false;
+                Spot::TodoEnd
+            }
+            _ if !in_todo => Spot::Outside,
+            ("BEGIN", component) => {
+                nested += 1;
+                if nested == 1 && component == "VALARM" {
+                    alarm = Some(alarms);
+                    alarms += 1;
+                }
+                alarm.map(Spot::Alarm).unwrap_or(Spot::Nested)
+            }
+            ("END", _) => {
+                nested -= 1;
+                let spot = alarm.map(Spot::Alarm).unwrap_or(Spot::Nested);
+                if nested == 0 {
+                    alarm = None;
+                }
+                spot
+            }
+            _ if nested == 0 => Spot::Todo,
+            _ => alarm.map(Spot::Alarm).unwrap_or(Spot::Nested),
+        };
+        for line in f(line, spot) {
+            out.push_str(&line);
+            out.push_str(newline);
+        }
+    }
+    out
+}
@@ -0,0 +1,425 @@
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bon::Builder;
+use md5::Md5;
+use reqwest::header::{HeaderMap, WWW_AUTHENTICATE};
+use reqwest::{Client, Method};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::error::{Error, Result};
+
+///what the user gave us to log in with. which of these actually gets
+///used is decided by the server's WWW-Authenticate challenge
+#[derive(Clone, Default, Builder)]
+pub struct Credentials {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    ///OAuth2 access token sent as `Authorization: Bearer`
+    pub token: Option<String>,
+    ///when set together with `token_url` an expired token is refreshed
+    pub refresh_token: Option<String>,
+    pub token_url: Option<String>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+impl Credentials {
+    fn has_password(&self) -> bool {
+        self.username.is_some() && self.password.is_some()
+    }
+
+    fn has_bearer(&self) -> bool {
+        self.token.is_some() || self.can_refresh()
+    }
+
+    fn can_refresh(&self) -> bool {
+        self.refresh_token.is_some() && self.token_url.is_some()
+    }
+}
+
+///a single challenge from a WWW-Authenticate header
+#[derive(Debug, Clone, PartialEq)]
+pub enum Challenge {
+    Basic,
+    Digest(DigestChallenge),
+    Bearer,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DigestChallenge {
+    pub realm: String,
+    pub nonce: String,
+    pub opaque: Option<String>,
+    pub algorithm: DigestAlgorithm,
+    ///true when the server offered qop=auth, otherwise legacy RFC 2069 digest
+    pub qop_auth: bool,
+    pub stale: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestAlgorithm {
+    Md5,
+    Md5Sess,
+    Sha256,
+    Sha256Sess,
+}
+
+impl DigestAlgorithm {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_uppercase().as_str() {
+            "MD5" => Some(Self::Md5),
+            "MD5-SESS" => Some(Self::Md5Sess),
+            "SHA-256" => Some(Self::Sha256),
+            "SHA-256-SESS" => Some(Self::Sha256Sess),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Md5 => "MD5",
+            Self::Md5Sess => "MD5-sess",
+            Self::Sha256 => "SHA-256",
+            Self::Sha256Sess => "SHA-256-sess",
+        }
+    }
+
+    ///higher is preferred when a server offers several
+    fn strength(&self) -> u8 {
+        match self {
+            Self::Md5 | Self::Md5Sess => 0,
+            Self::Sha256 | Self::Sha256Sess => 1,
+        }
+    }
+
+    fn is_sess(&self) -> bool {
+        matches!(self, Self::Md5Sess | Self::Sha256Sess)
+    }
+
+    fn hash(&self, data: &str) -> String {
+        match self {
+            Self::Md5 | Self::Md5Sess => format!("{:x}", Md5::digest(data.as_bytes())),
+            Self::Sha256 | Self::Sha256Sess => format!("{:x}", Sha256::digest(data.as_bytes())),
+        }
+    }
+}
+
+///which scheme we settled on after the last challenge
+enum Scheme {
+    ///haven't seen a challenge yet, so send nothing
+    Unknown,
+    Basic,
+    Digest { challenge: DigestChallenge, nc: u32 },
+    Bearer,
+}
+
+struct AuthState {
+    scheme: Scheme,
+    token: Option<String>,
+    refresh_token: Option<String>,
+}
+
+///picks and applies an auth scheme for each request. interior mutability
+///so that `CalDAVClient::request` can stay `&self`
+pub(crate) struct Authenticator {
+    creds: Credentials,
+    state: Mutex<AuthState>,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+}
+
+impl Authenticator {
+    pub fn new(creds: Credentials) -> Self {
+        let state = AuthState {
+            scheme: Scheme::Unknown,
+            token: creds.token.clone(),
+            refresh_token: creds.refresh_token.clone(),
+        };
+        Authenticator { creds, state: Mutex::new(state) }
+    }
+
+    ///value for the Authorization header, or None to send the request bare
+    pub fn header(&self, method: &Method, uri: &str) -> Option<String> {
+        let mut state = self.state.lock().unwrap();
+        let token = state.token.clone();
+        match &mut state.scheme {
+            //nothing goes out until a challenge says which scheme the server wants
+            Scheme::Unknown => None,
+            Scheme::Basic => Some(self.basic()),
+            Scheme::Bearer => token.map(|t| format!("Bearer {t}")),
+            Scheme::Digest { challenge, nc } => {
+                *nc += 1;
+                Some(self.digest(challenge, *nc, method, uri))
+            }
+        }
+    }
+
+    ///called on a 401. looks at the challenges and switches scheme,
+    ///refreshing the bearer token if needed. returns true if the
+    ///request is worth retrying
+    pub async fn on_unauthorized(&self, client: &Client, headers: &HeaderMap) -> Result<bool> {
+        let challenges: Vec<Challenge> = headers
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(parse_challenges)
+            .collect();
+
+        let (was_bearer, has_token) = {
+            let state = self.state.lock().unwrap();
+            (matches!(state.scheme, Scheme::Bearer), state.token.is_some())
+        };
+
+        //strongest scheme we have credentials for wins
+        let bearer = challenges.contains(&Challenge::Bearer) && self.creds.has_bearer();
+        let digest = challenges
+            .iter()
+            .filter_map(|c| match c {
+                Challenge::Digest(d) if self.creds.has_password() => Some(d),
+                _ => None,
+            })
+            .rev()
+            .max_by_key(|d| d.algorithm.strength())
+            .cloned();
+        let basic = challenges.contains(&Challenge::Basic) && self.creds.has_password();
+
+        //a bearer token that was just rejected (or never existed) needs refreshing
+        //first, and without a way to refresh it the password schemes are next
+        let needs_refresh = was_bearer || !has_token;
+        if bearer && (!needs_refresh || self.creds.can_refresh()) {
+            if needs_refresh {
+                self.refresh(client).await?;
+            }
+            self.state.lock().unwrap().scheme = Scheme::Bearer;
+            return Ok(true);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if let Some(challenge) = digest {
+            //a rejected digest is only worth retrying when the nonce went stale
+            let retry = match &state.scheme {
+                Scheme::Digest { .. } => challenge.stale,
+                _ => true,
+            };
+            state.scheme = Scheme::Digest { challenge, nc: 0 };
+            return Ok(retry);
+        }
+        if basic {
+            let retry = !matches!(state.scheme, Scheme::Basic);
+            state.scheme = Scheme::Basic;
+            return Ok(retry);
+        }
+        Ok(false)
+    }
+
+    async fn refresh(&self, client: &Client) -> Result<()> {
+        let token_url = self.creds.token_url.as_deref().unwrap_or_default();
+        let failed = |reason: String| Error::Auth { url: token_url.to_string(), reason: Some(reason) };
+        let refresh_token = self
+            .state
+            .lock()
+            .unwrap()
+            .refresh_token
+            .clone()
+            .ok_or(failed("No refresh token configured".to_string()))?;
+
+        let mut form = vec![
+            ("grant_type", "refresh_token".to_string()),
+            ("refresh_token", refresh_token),
+        ];
+        if let Some(id) = &self.creds.client_id {
+            form.push(("client_id", id.clone()));
+        }
+        if let Some(secret) = &self.creds.client_secret {
+            form.push(("client_secret", secret.clone()));
+        }
+
+        let res = client
+            .post(token_url)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| Error::transport(token_url, e))?;
+        let status = res.status();
+        let text = res.text().await.map_err(|e| Error::transport(token_url, e))?;
+        if !status.is_success() {
+            return Err(failed(format!("token endpoint returned {status}: {text}")));
+        }
+        let token: TokenResponse = serde_json::from_str(&text)
+            .map_err(|e| failed(format!("bad token response: {e}")))?;
+
+        let mut state = self.state.lock().unwrap();
+        state.token = Some(token.access_token);
+        if token.refresh_token.is_some() {
+            state.refresh_token = token.refresh_token;
+        }
+        Ok(())
+    }
+
+    fn basic(&self) -> String {
+        use base64::Engine;
+        let user = self.creds.username.as_deref().unwrap_or_default();
+        let pass = self.creds.password.as_deref().unwrap_or_default();
+        let encoded = base64::engine::general_purpose::STANDARD.encode(format!("{user}:{pass}"));
+        format!("Basic {encoded}")
+    }
+
+    fn digest(&self, c: &DigestChallenge, nc: u32, method: &Method, uri: &str) -> String {
+        let user = self.creds.username.as_deref().unwrap_or_default();
+        let pass = self.creds.password.as_deref().unwrap_or_default();
+        let alg = c.algorithm;
+        let cnonce = cnonce(alg);
+        let nc = format!("{nc:08x}");
+
+        let mut ha1 = alg.hash(&format!("{user}:{}:{pass}", c.realm));
+        if alg.is_sess() {
+            ha1 = alg.hash(&format!("{ha1}:{}:{cnonce}", c.nonce));
+        }
+        let ha2 = alg.hash(&format!("{method}:{uri}"));
+        let response = if c.qop_auth {
+            alg.hash(&format!("{ha1}:{}:{nc}:{cnonce}:auth:{ha2}", c.nonce))
+        } else {
+            alg.hash(&format!("{ha1}:{}:{ha2}", c.nonce))
+        };
+
+        let mut header = format!(
+            r#"Digest username="{}", realm="{}", nonce="{}", uri="{}", algorithm={}, response="{response}""#,
+            quoted(user),
+            quoted(&c.realm),
+            quoted(&c.nonce),
+            quoted(uri),
+            alg.name()
+        );
+        if c.qop_auth {
+            header += &format!(r#", qop=auth, nc={nc}, cnonce="{cnonce}""#);
+        }
+        if let Some(opaque) = &c.opaque {
+            header += &format!(r#", opaque="{}""#, quoted(opaque));
+        }
+        header
+    }
+}
+
+///`text` escaped for the inside of a quoted-string
+fn quoted(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn cnonce(alg: DigestAlgorithm) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    alg.hash(&format!("{nanos}:{}", std::process::id()))[..16].to_string()
+}
+
+///parse every challenge out of one WWW-Authenticate value, eg.
+///`Digest realm="a", qop="auth,auth-int", nonce="x", Basic realm="b"`
+pub fn parse_challenges(header: &str) -> Vec<Challenge> {
+    let mut challenges = vec![];
+    for (scheme, params) in split_challenges(header) {
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.clone())
+        };
+        match scheme.to_ascii_lowercase().as_str() {
+            "basic" => challenges.push(Challenge::Basic),
+            "bearer" => challenges.push(Challenge::Bearer),
+            "digest" => {
+                let algorithm = match param("algorithm") {
+                    Some(a) => match DigestAlgorithm::parse(&a) {
+                        Some(a) => a,
+                        //unsupported algorithm, skip this challenge
+                        None => continue,
+                    },
+                    None => DigestAlgorithm::Md5,
+                };
+                let qop = param("qop");
+                //auth-int only would need the body hashed, which we don't do
+                if qop.as_ref().is_some_and(|q| !q.split(',').any(|q| q.trim() == "auth")) {
+                    continue;
+                }
+                let Some(nonce) = param("nonce") else { continue };
+                challenges.push(Challenge::Digest(DigestChallenge {
+                    realm: param("realm").unwrap_or_default(),
+                    nonce,
+                    opaque: param("opaque"),
+                    algorithm,
+                    qop_auth: qop.is_some(),
+                    stale: param("stale").is_some_and(|s| s.eq_ignore_ascii_case("true")),
+                }));
+            }
+            _ => {}
+        }
+    }
+    challenges
+}
+
+pub(crate) fn split_challenges(header: &str) -> Vec<(String, Vec<(String, String)>)> {
+    let mut out: Vec<(String, Vec<(String, String)>)> = vec![];
+    let mut chars = header.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ',') {
+            chars.next();
+        }
+        let mut token = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == ',' || c == '=' {
+                break;
+            }
+            token.push(c);
+            chars.next();
+        }
+        if token.is_empty() {
+            break;
+        }
+        while chars.peek().is_some_and(|c| *c == ' ') {
+            chars.next();
+        }
+
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            //token68 (eg. base64) ends with padding, not a key=value pair
+            if chars.peek().is_none_or(|c| *c == ',' || *c == '=' || c.is_whitespace()) {
+                while chars.peek() == Some(&'=') {
+                    chars.next();
+                }
+                continue;
+            }
+            let mut value = String::new();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => value.extend(chars.next()),
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(&c) = chars.peek() {
+                    if c == ',' || c.is_whitespace() {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+            }
+            if let Some((_, params)) = out.last_mut() {
+                params.push((token, value));
+            }
+        } else {
+            //no '=' so this token starts a new challenge
+            out.push((token, vec![]));
+        }
+    }
+    out
+}
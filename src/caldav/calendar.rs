@@ -1,9 +1,18 @@
-use std::rc::Rc;
-use anyhow::anyhow;
+use std::collections::HashMap;
+use std::sync::Arc;
 
-use minidom::Element;
+use bon::Builder;
+use tokio::sync::RwLock;
+use url::Url;
 
-use super::{parser::{follow_tree, NS_C, NS_CS, NS_D, NS_I}, todo::CalendarTodo};
+use super::{
+    capabilities::CalendarCapabilities,
+    client::CalDAVClient,
+    error::{Error, Result},
+    parser::{escape_xml, DavResponse, Partial, NS_C, NS_CS, NS_D, NS_I},
+    todo::CalendarTodo,
+};
+use crate::store::Query;
 
 pub struct Calendar {
     pub url: String,
@@ -11,50 +20,74 @@ pub struct Calendar {
     pub ctag: String,
     pub color: Option<String>,
     pub description: Option<String>,
+    ///where the user wants it in listings, lowest first
+    pub order: Option<i64>,
     pub supports_todo: bool,
-    pub(crate) cache_current_todos: Rc<Vec<CalendarTodo>>,
-    pub(crate) cache_past_todos: Rc<Vec<CalendarTodo>>,
+    pub capabilities: CalendarCapabilities,
+    ///the last answer to each query, good until the ctag changes
+    pub(crate) cache: HashMap<Query, Arc<Partial<CalendarTodo>>>,
 }
 
 impl Calendar {
-    pub fn parse(el: &Element) -> Option<Self> {
-        let url = follow_tree(el, "href", NS_D)?;
-        let prop = follow_tree(el, "propstat.prop", NS_D)?;
-        let name = prop.get_child("displayname", NS_D)?;
-        let ctag = prop.get_child("getctag", NS_CS)?;
-
-        let color = prop
-            .get_child("calendar-color", NS_I)
-            .and_then(|c| Some(c.text()));
-        let description = prop
-            .get_child("calendar-description", NS_C)
-            .and_then(|c| Some(c.text()));
-
-        let supports_todo = prop
-            .get_child("supported-calendar-component-set", NS_C)?
-            .nodes()
-            .filter_map(|node| node.as_element()?.attr("name"))
-            .any(|name| name.contains("VTODO"));
-
-        let is_calendar = prop
-            .get_child("resourcetype", NS_D)?
+    ///None if the response isn't a calendar. properties are only read
+    ///from 2xx propstats, so ones the server doesn't have are just missing
+    pub fn parse(res: &DavResponse) -> Option<Self> {
+        //the home itself, scheduling inbox/outbox, trash bins etc. are all skipped
+        let is_calendar = res
+            .prop("resourcetype", NS_D)?
             .nodes()
             .filter_map(|node| node.as_element())
-            .any(|elem| elem.name() == "calendar");
+            .any(|elem| elem.is("calendar", NS_C));
 
         if !is_calendar {
             return None;
         }
 
+        //Radicale leaves it out when whoever made the calendar didn't set one
+        let name = match res.prop("displayname", NS_D).map(|n| n.text()) {
+            Some(name) if !name.trim().is_empty() => name,
+            _ => last_segment(&res.href),
+        };
+
+        //getctag is a CalendarServer extension. servers without it still have the
+        //RFC 6578 sync-token or an ETag on the collection, which change just as
+        //often. empty if there's none of them, which means always refetch
+        let ctag = [("getctag", NS_CS), ("sync-token", NS_D), ("getetag", NS_D)]
+            .iter()
+            .find_map(|(name, ns)| res.prop(name, ns))
+            .map(|c| c.text())
+            .unwrap_or_default();
+
+        //Apple's extension, which everyone else copied
+        let color = res
+            .prop("calendar-color", NS_I)
+            .and_then(|c| parse_color(&c.text()));
+        let description = res
+            .prop("calendar-description", NS_C)
+            .map(|c| c.text());
+        let order = res
+            .prop("calendar-order", NS_I)
+            .and_then(|o| o.text().trim().parse().ok());
+
+        //leaving the set out means every component type is fine (RFC 4791 5.2.3)
+        let supports_todo = match res.prop("supported-calendar-component-set", NS_C) {
+            Some(set) => set
+                .nodes()
+                .filter_map(|node| node.as_element()?.attr("name"))
+                .any(|name| name.eq_ignore_ascii_case("VTODO")),
+            None => true,
+        };
+
         Some(Calendar {
-            url: url.text(),
-            name: name.text(),
-            ctag: ctag.text(),
+            url: res.href.clone(),
+            name,
+            ctag,
             color,
             description,
+            order,
             supports_todo,
-            cache_current_todos: Rc::new(vec![]),
-            cache_past_todos: Rc::new(vec![]),
+            capabilities: CalendarCapabilities::parse(res),
+            cache: HashMap::new(),
         })
     }
 
@@ -65,15 +98,116 @@ impl Calendar {
         }
     }
 
-    pub fn fancy_name(&self) -> String {
+    pub fn rgb(&self) -> (u8, u8, u8) {
         let color = self.get_color();
-        format!(
-            "\x1B[48;2;{};{};{}m  \x1B[0m {}",
-            // Convert hex color to RGB
-            u8::from_str_radix(&color[1..3], 16).unwrap_or(255),
-            u8::from_str_radix(&color[3..5], 16).unwrap_or(255),
-            u8::from_str_radix(&color[5..7], 16).unwrap_or(255),
-            self.name
-        )
+        let channel = |range| color.get(range).and_then(|c| u8::from_str_radix(c, 16).ok()).unwrap_or(255);
+        (channel(1..3), channel(3..5), channel(5..7))
+    }
+
+    ///the name after a swatch of its color, in ANSI truecolor escapes.
+    ///only for terminals, see `name` otherwise
+    pub fn fancy_name(&self) -> String {
+        let (r, g, b) = self.rgb();
+        format!("\x1B[48;2;{r};{g};{b}m  \x1B[0m {}", self.name)
+    }
+}
+
+///properties to set on a calendar, the ones left None are left alone
+#[derive(Builder, Debug, Clone, Default)]
+pub struct CalendarProps {
+    pub name: Option<String>,
+    ///`#RRGGBB`
+    pub color: Option<String>,
+    pub description: Option<String>,
+    pub order: Option<i64>,
+}
+
+impl CalendarProps {
+    ///as children of a `<d:prop>`
+    fn to_xml(&self) -> String {
+        let mut xml = String::new();
+        let mut push = |tag: &str, value: &Option<String>| {
+            if let Some(value) = value {
+                xml.push_str(&format!("<{tag}>{}</{tag}>", escape_xml(value)));
+            }
+        };
+        push("d:displayname", &self.name);
+        push("i:calendar-color", &self.color);
+        push("c:calendar-description", &self.description);
+        push("i:calendar-order", &self.order.map(|o| o.to_string()));
+        xml
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.color.is_none() && self.description.is_none() && self.order.is_none()
+    }
+
+    ///copies the ones that are set onto `cal`
+    pub fn apply(&self, cal: &mut Calendar) {
+        if let Some(name) = &self.name {
+            cal.name = name.clone();
+        }
+        if let Some(color) = &self.color {
+            cal.color = Some(color.clone());
+        }
+        if let Some(description) = &self.description {
+            cal.description = Some(description.clone());
+        }
+        if let Some(order) = self.order {
+            cal.order = Some(order);
+        }
+    }
+}
+
+impl CalDAVClient {
+    ///makes a calendar for todos at `<home>/<id>/` and returns it as the
+    ///server has it
+    pub async fn create_calendar(&self, id: &str, props: &CalendarProps) -> Result<Calendar> {
+        let mut url = Url::parse(&self.home).map_err(|source| Error::Url { url: self.home.clone(), source })?;
+        url.path_segments_mut()
+            .map_err(|_| Error::invalid(&self.home, "home url can't have a path"))?
+            .pop_if_empty()
+            .push(id)
+            .push("");
+        //only todos, so nobody's events end up in it
+        let props = props.to_xml()
+            + r#"<c:supported-calendar-component-set><c:comp name="VTODO"/></c:supported-calendar-component-set>"#;
+        self.mkcalendar(url.as_str(), &props).await?;
+        self.fetch_calendar(url.as_str()).await
+    }
+
+    pub async fn update_calendar(&self, cal_ref: &RwLock<Calendar>, props: &CalendarProps) -> Result<()> {
+        let url = cal_ref.read().await.url.clone();
+        self.proppatch(&url, &props.to_xml()).await?;
+        props.apply(&mut *cal_ref.write().await);
+        Ok(())
     }
+
+    ///deletes `cal` and every todo in it
+    pub async fn delete_calendar(&self, cal: &Calendar) -> Result<()> {
+        self.delete_collection(&cal.url).await
+    }
+}
+
+///calendars with an order first, lowest first. the rest keep their order
+pub(crate) fn sort_by_order(cals: &mut [Calendar]) {
+    cals.sort_by_key(|cal| cal.order.unwrap_or(i64::MAX));
+}
+
+///`#RRGGBB`. servers send `#RRGGBBAA` (Apple, Nextcloud) or sometimes `#RGB`
+pub fn parse_color(text: &str) -> Option<String> {
+    let hex = text.trim().strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        6 | 8 => Some(format!("#{}", &hex[..6])),
+        3 => Some(hex.chars().fold("#".to_string(), |color, c| format!("{color}{c}{c}"))),
+        _ => None,
+    }
+}
+
+///`/calendars/alice/work/` => `work`
+fn last_segment(href: &str) -> String {
+    href.trim_end_matches('/').rsplit('/').next().unwrap_or(href).to_string()
 }
@@ -0,0 +1,104 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::header::{HeaderMap, ALLOW};
+use serde::Serialize;
+
+use super::error::{Error, Result};
+use super::parser::{DavResponse, NS_C, NS_D};
+
+///what the server said about itself in answer to OPTIONS
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ServerInfo {
+    ///compliance classes from the DAV header, eg. `1`, `calendar-access`,
+    ///`calendar-auto-schedule`. empty if OPTIONS didn't work
+    pub dav: Vec<String>,
+    ///methods from the Allow header
+    pub allow: Vec<String>,
+}
+
+impl ServerInfo {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        //both can be repeated, and each one is a comma separated list
+        let list = |name| -> Vec<String> {
+            headers
+                .get_all(name)
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .flat_map(|v| v.split(','))
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect()
+        };
+        ServerInfo { dav: list("dav"), allow: list(ALLOW.as_str()) }
+    }
+}
+
+///a media type a calendar stores objects in (RFC 4791 5.2.4)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DataType {
+    pub content_type: String,
+    pub version: Option<String>,
+}
+
+///limits and features of one calendar collection (RFC 4791 5.2, RFC 3253 3.1.5).
+///anything the server didn't say is empty or None
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CalendarCapabilities {
+    ///local names of the REPORTs it supports, eg. `calendar-query`, `sync-collection`
+    pub reports: Vec<String>,
+    ///in bytes, for one iCalendar object
+    pub max_resource_size: Option<u64>,
+    pub data_types: Vec<DataType>,
+    ///the earliest and latest date-time it accepts
+    pub min_date_time: Option<DateTime<Utc>>,
+    pub max_date_time: Option<DateTime<Utc>>,
+}
+
+impl CalendarCapabilities {
+    pub(crate) fn parse(res: &DavResponse) -> Self {
+        let reports = res
+            .prop("supported-report-set", NS_D)
+            .map(|set| {
+                set.children()
+                    .filter_map(|r| r.get_child("report", NS_D)?.children().next())
+                    .map(|r| r.name().to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let data_types = res
+            .prop("supported-calendar-data", NS_C)
+            .map(|set| {
+                set.children()
+                    .filter(|d| d.is("calendar-data", NS_C))
+                    .map(|d| DataType {
+                        content_type: d.attr("content-type").unwrap_or("text/calendar").to_string(),
+                        version: d.attr("version").map(str::to_string),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let date_time = |name| res.prop(name, NS_C).and_then(|d| parse_utc(&d.text()));
+
+        CalendarCapabilities {
+            reports,
+            max_resource_size: res.prop("max-resource-size", NS_C).and_then(|s| s.text().trim().parse().ok()),
+            data_types,
+            min_date_time: date_time("min-date-time"),
+            max_date_time: date_time("max-date-time"),
+        }
+    }
+
+    ///fails without asking the server if `ics` is over `max-resource-size`
+    pub(crate) fn check_size(&self, url: &str, ics: &str) -> Result<()> {
+        match self.max_resource_size {
+            Some(max) if ics.len() as u64 > max => Err(Error::Unsupported {
+                feature: format!("storing {} bytes at {url} (the limit is {max})", ics.len()),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+///`19000101T000000Z`, the only form RFC 4791 allows here
+fn parse_utc(text: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(text.trim(), "%Y%m%dT%H%M%SZ").ok().map(|d| d.and_utc())
+}
@@ -1,147 +1,518 @@
-use std::cell::RefCell;
+use std::sync::Arc;
 
-use super::calendar::Calendar;
-use super::parser::{add_path, go_back, follow_tree, format_ns_attrs, NS_C, NS_D};
+use super::auth::{Authenticator, Credentials};
+use super::calendar::{self, Calendar};
+use super::capabilities::ServerInfo;
+use super::discovery::{self, DnsResolver, Resolver};
+use super::error::{Error, Result};
+use super::parser::{format_ns_attrs, resolve_href, MultiStatus, NS_C, NS_D};
+use bon::bon;
 use minidom::Element;
-use reqwest::header::CONTENT_TYPE;
-use reqwest::{Client, Method};
-use anyhow::{Context, anyhow};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, LOCATION, RETRY_AFTER};
+use reqwest::redirect::Policy;
+use reqwest::{Client, Method, Response, StatusCode};
+use std::time::Duration;
+use tokio::sync::RwLock;
+use url::Url;
+
+const MAX_REDIRECTS: usize = 10;
+///one to pick a scheme, one more to refresh a rejected token or renew a stale nonce
+const MAX_CHALLENGES: usize = 2;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const RETRY_BASE: Duration = Duration::from_millis(500);
+const RETRY_MAX: Duration = Duration::from_secs(30);
+///how many calendars to fetch at once by default
+pub const DEFAULT_CONCURRENCY: usize = 4;
+
+pub type CalendarRef = Arc<RwLock<Calendar>>;
 
 pub struct CalDAVClient {
     client: Client,
-    username: String,
-    password: String,
+    auth: Authenticator,
+    verbose: bool,
+    retries: u32,
     pub home: String,
-    pub calendars: Vec<RefCell<Calendar>>,
+    pub server: ServerInfo,
+    pub calendars: Vec<CalendarRef>,
 }
 
+//the client gets shared with background tasks, so keep it that way
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<CalDAVClient>();
+};
+
 const CALENDAR_PROPS: &str = r#"
     <d:prop>
         <d:displayname />
         <c:supported-calendar-component-set />
         <cs:getctag />
+        <d:sync-token />
+        <d:getetag />
         <c:calendar-description />
         <i:calendar-color />
+        <i:calendar-order />
         <d:resourcetype />
+        <d:supported-report-set />
+        <c:supported-calendar-data />
+        <c:max-resource-size />
+        <c:min-date-time />
+        <c:max-date-time />
     </d:prop>
 "#;
 
+#[bon]
 impl CalDAVClient {
-    pub async fn new(base_url: &str, username: &str, password: &str) -> anyhow::Result<Self> {
+    ///`base_url` is either the principal discovery url, or just a domain or
+    ///email address to bootstrap from (RFC 6764). a site like
+    ///`http://example.com` is bootstrapped from if its root isn't the url
+    #[builder]
+    pub async fn new(
+        base_url: &str,
+        mut credentials: Credentials,
+        ///print each request and discovery attempt to stderr
+        #[builder(default)]
+        verbose: bool,
+        ///DNS used for SRV/TXT discovery, defaults to the system resolver
+        resolver: Option<&dyn Resolver>,
+        ///connect and whole-request timeout
+        #[builder(default = DEFAULT_TIMEOUT)]
+        timeout: Duration,
+        ///how many times to retry idempotent requests on 5xx or connection errors
+        #[builder(default = 2)]
+        retries: u32,
+    ) -> Result<Self> {
+        //most providers use the email address as the login
+        if credentials.username.is_none() && base_url.contains('@') && !discovery::is_url(base_url) {
+            credentials.username = Some(base_url.to_string());
+        }
         let mut client = CalDAVClient {
-            client: Client::new(),
-            username: username.to_string(),
-            password: password.to_string(),
+            client: Client::builder()
+                .redirect(Policy::none())
+                .timeout(timeout)
+                .connect_timeout(timeout)
+                .build()
+                .map_err(|e| Error::transport(base_url, e))?,
+            auth: Authenticator::new(credentials),
+            verbose,
+            retries,
             home: "".to_string(),
+            server: ServerInfo::default(),
             calendars: vec![],
         };
-        let principal = client.get_principal(base_url).await.context("Get principal failed")?;
-        client.home = client.get_homeset(&principal).await.context("Get homeset failed")?;
-        client.calendars = client.get_calendars().await.context("Get calendars failed")?;
+        let principal = match discovery::is_url(base_url) {
+            true => match client.get_principal(base_url).await {
+                Err(e) if discovery::is_site(base_url) && !matches!(e, Error::Auth { .. }) => {
+                    client.report(&format!("{base_url}: {e}"));
+                    client.discover(base_url, resolver).await?
+                }
+                result => result?,
+            },
+            false => client.discover(base_url, resolver).await?,
+        };
+        client.home = client.get_homeset(&principal).await?;
+        client.server = client.get_server_info().await;
+        client.calendars = client.get_calendars().await?;
         Ok(client)
     }
+}
 
-    async fn request(
+impl CalDAVClient {
+    fn report(&self, msg: &str) {
+        if self.verbose {
+            eprintln!("{msg}");
+        }
+    }
+
+    ///try every discovery candidate until one answers with a principal
+    async fn discover(&self, input: &str, resolver: Option<&dyn Resolver>) -> Result<String> {
+        let system;
+        let resolver = match resolver {
+            Some(r) => Some(r),
+            None => match DnsResolver::new() {
+                Ok(r) => {
+                    system = r;
+                    Some(&system as &dyn Resolver)
+                }
+                Err(e) => {
+                    self.report(&format!("no DNS resolver, skipping SRV lookups: {e}"));
+                    None
+                }
+            },
+        };
+
+        for url in discovery::candidates(input, resolver, &|msg| self.report(msg)).await {
+            self.report(&format!("trying {url}"));
+            match self.get_principal(&url).await {
+                Ok(principal) => {
+                    self.report(&format!("found principal {principal}"));
+                    return Ok(principal);
+                }
+                Err(e) => {
+                    self.report(&format!("{url}: {e}"));
+                    //we don't send credentials to other sites, so say where to point CALDAV_URL instead
+                    if let (Error::Auth { url: other, .. }, Ok(from)) = (&e, Url::parse(&url)) {
+                        if Url::parse(other).is_ok_and(|to| to.origin() != from.origin() && !discovery_redirect(&from, &to)) {
+                            self.report(&format!("redirected to another site, use {other} directly to trust it"));
+                        }
+                    }
+                }
+            }
+        }
+        Err(Error::Discovery { input: input.to_string() })
+    }
+
+    ///sends the request with auth, retrying idempotent requests on 5xx and
+    ///connection errors and following redirects without changing method.
+    ///credentials are only sent to the origin of `url`, or where its
+    ///.well-known redirect points on the same site. non-2xx statuses come
+    ///back as an `Error`. `headers` override the defaults
+    async fn execute(
         &self,
         method: Method,
         url: &str,
         depth: i32,
         body: String,
-    ) -> anyhow::Result<String> {
-        let full_url = if url.contains("http") { url.to_string() } else { add_path(&self.home, url) };
-        println!("REQ {full_url}");
-        let text = self
-            .client
-            .request(method.clone(), full_url)
-            .header("Depth", depth)
-            .header(CONTENT_TYPE, "application/xml")
-            .basic_auth(&self.username, Some(&self.password))
-            .body(body.clone())
-            .send()
-            .await.context("Request failed")?
-            .text()
-            .await.context("Request did not return text")?;
-        if text == "Bad Request" {
-            return Err(anyhow!("Bad Request"));
+        headers: HeaderMap,
+    ) -> Result<Response> {
+        let mut full_url = match Url::parse(url) {
+            Ok(absolute) => absolute.to_string(),
+            Err(_) => resolve_href(&self.home, url)?,
+        };
+        let start = parse_url(&full_url)?;
+        //a conditional write that went through before timing out would fail
+        //its precondition the second time, so those only get one try
+        let conditional = headers.contains_key(IF_MATCH) || headers.contains_key(IF_NONE_MATCH);
+        let idempotent = is_idempotent(&method) && !conditional;
+        let mut redirects = 0;
+        let mut attempt = 0;
+        let mut challenges = 0;
+        loop {
+            self.report(&format!("{method} {full_url}"));
+            let parsed = parse_url(&full_url)?;
+            let trusted = parsed.origin() == start.origin() || discovery_redirect(&start, &parsed);
+            //digest auth signs the request-target, not the full url
+            let uri = match parsed.query() {
+                Some(q) => format!("{}?{q}", parsed.path()),
+                None => parsed.path().to_string(),
+            };
+
+            let mut req = self
+                .client
+                .request(method.clone(), &full_url)
+                .header("Depth", depth)
+                .header(CONTENT_TYPE, "application/xml")
+                .headers(headers.clone())
+                .body(body.clone());
+            if trusted {
+                if let Some(auth) = self.auth.header(&method, &uri) {
+                    req = req.header(AUTHORIZATION, auth);
+                }
+            }
+            let res = match req.send().await {
+                Ok(res) => res,
+                Err(e) if idempotent && attempt < self.retries && (e.is_connect() || e.is_timeout()) => {
+                    attempt += 1;
+                    self.report(&format!("{e}, retrying ({attempt}/{})", self.retries));
+                    backoff(attempt, None).await;
+                    continue;
+                }
+                Err(e) => return Err(Error::transport(&full_url, e)),
+            };
+            let status = res.status();
+
+            if status.is_server_error() && idempotent && attempt < self.retries {
+                attempt += 1;
+                self.report(&format!("{status} from {full_url}, retrying ({attempt}/{})", self.retries));
+                let retry_after = res
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|v| v.to_str().ok()?.parse().ok());
+                backoff(attempt, retry_after).await;
+                continue;
+            }
+
+            //negotiate the scheme from the challenge and try again
+            if status == StatusCode::UNAUTHORIZED
+                && trusted
+                && challenges < MAX_CHALLENGES
+                && self.auth.on_unauthorized(&self.client, res.headers()).await?
+            {
+                challenges += 1;
+                continue;
+            }
+
+            //reqwest would turn a redirected PROPFIND into a GET, so follow them here
+            if status.is_redirection() {
+                if let Some(location) = res.headers().get(LOCATION).and_then(|l| l.to_str().ok()) {
+                    if redirects == MAX_REDIRECTS {
+                        return Err(Error::TooManyRedirects { url: url.to_string() });
+                    }
+                    full_url = res
+                        .url()
+                        .join(location)
+                        .map_err(|source| Error::Url { url: location.to_string(), source })?
+                        .to_string();
+                    redirects += 1;
+                    continue;
+                }
+            }
+
+            if status.is_success() {
+                return Ok(res);
+            }
+            let etag = res.headers().get(ETAG).and_then(|e| e.to_str().ok()).map(str::to_string);
+            let body = res.text().await.unwrap_or_default();
+            return Err(Error::from_status(status, &full_url, &body, etag)
+                .unwrap_or(Error::invalid(&full_url, format!("unexpected {status}"))));
         }
-        Ok(text)
     }
 
-    async fn propfind(&self, url: &str, depth: i32, body: &str) -> anyhow::Result<Element> {
+    async fn request(
+        &self,
+        method: Method,
+        url: &str,
+        depth: i32,
+        body: String,
+    ) -> Result<String> {
+        let res = self.execute(method, url, depth, body, HeaderMap::new()).await?;
+        let final_url = res.url().to_string();
+        res.text().await.map_err(|e| Error::transport(&final_url, e))
+    }
+
+    async fn propfind(&self, url: &str, depth: i32, body: &str) -> Result<Element> {
+        Ok(self.propfind_from(url, depth, body).await?.0)
+    }
+
+    ///PROPFIND that also returns the url which answered, after redirects
+    async fn propfind_from(&self, url: &str, depth: i32, body: &str) -> Result<(Element, String)> {
         let method = Method::from_bytes(b"PROPFIND").unwrap();
         let body_start = format!("<d:propfind {}>", format_ns_attrs());
         let body = body_start + body + "</d:propfind>";
-        // println!("PROPFIND {}", url);
-        let res = self.request(method, url, depth, body).await.context("PROPFIND request failed")?;
-        Ok(res.parse().context("Parsing PROPFIND XML failed")?)
+        let res = self.execute(method, url, depth, body, HeaderMap::new()).await?;
+        let final_url = res.url().to_string();
+        let text = res.text().await.map_err(|e| Error::transport(&final_url, e))?;
+        Ok((parse_xml(&final_url, &text)?, final_url))
     }
 
-    pub(crate) async fn calquery(&self, url: &str, depth: i32, body: &str) -> anyhow::Result<Element> {
+    pub(crate) async fn calquery(&self, url: &str, depth: i32, body: &str) -> Result<Element> {
         let method = Method::from_bytes(b"REPORT").unwrap();
         let body_start = format!("<d:calendar-query {}>", format_ns_attrs());
         let body = body_start + body + "</d:calendar-query>";
-        let res = self.request(method, url, depth, body).await.context("CALENDAR-QUERY request failed")?;
-        Ok(res.parse().context("Parsing CALENDAR-QUERY failed")?)
+        let res = self.request(method, url, depth, body).await?;
+        parse_xml(url, &res)
     }
 
-    async fn get_principal(&self, url: &str) -> anyhow::Result<String> {
-        let root = self
-            .propfind(url, 0, "<d:prop><d:current-user-principal /></d:prop>")
-            .await?;
-        let href = follow_tree( &root, "response.propstat.prop.current-user-principal.href", NS_D)
-            .ok_or(anyhow!("Principal response did not contain href"))?
-            .text();
-        if href.contains("http") {
-            return Ok(href);
+    ///PUT an iCalendar object, only if `precondition` holds (`If-Match` or
+    ///`If-None-Match`). returns the new ETag
+    pub(crate) async fn put(&self, url: &str, ics: &str, precondition: HeaderName, value: &str) -> Result<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/calendar; charset=utf-8"));
+        headers.insert(precondition, header_value(url, value)?);
+        let res = self.execute(Method::PUT, url, 0, ics.to_string(), headers).await?;
+        //servers leave out the ETag (or make it weak) when they changed what we sent
+        match res.headers().get(ETAG).and_then(|e| e.to_str().ok()) {
+            Some(etag) if !etag.starts_with("W/") => Ok(etag.to_string()),
+            _ => self.get_etag(url).await,
         }
-        Ok(add_path(url, &href))
     }
 
-    async fn get_homeset(&self, url: &str) -> anyhow::Result<String> {
-        let root = self
-            .propfind(url, 0, "<d:prop><c:calendar-home-set /></d:prop>")
+    ///DELETE, only if the resource is still at `etag`
+    pub(crate) async fn delete(&self, url: &str, etag: &str) -> Result<()> {
+        let mut headers = HeaderMap::new();
+        headers.insert(IF_MATCH, header_value(url, etag)?);
+        self.execute(Method::DELETE, url, 0, String::new(), headers).await?;
+        Ok(())
+    }
+
+    ///MKCALENDAR (RFC 4791 5.3.1), `props` being the children of its `<d:prop>`
+    pub(crate) async fn mkcalendar(&self, url: &str, props: &str) -> Result<()> {
+        let method = Method::from_bytes(b"MKCALENDAR").unwrap();
+        let body = format!(
+            "<c:mkcalendar {}><d:set><d:prop>{props}</d:prop></d:set></c:mkcalendar>",
+            format_ns_attrs()
+        );
+        self.execute(method, url, 0, body, HeaderMap::new()).await?;
+        Ok(())
+    }
+
+    ///PROPPATCH setting `props`, the children of a `<d:prop>`
+    pub(crate) async fn proppatch(&self, url: &str, props: &str) -> Result<()> {
+        let method = Method::from_bytes(b"PROPPATCH").unwrap();
+        let body = format!(
+            "<d:propertyupdate {}><d:set><d:prop>{props}</d:prop></d:set></d:propertyupdate>",
+            format_ns_attrs()
+        );
+        let text = self.request(method, url, 0, body).await?;
+        let root = parse_xml(url, &text)?;
+        //it's all or nothing: the props that would have worked come back as
+        //424 Failed Dependency, so report one that actually failed
+        let multistatus = MultiStatus::parse(&root);
+        let failed = multistatus
+            .responses
+            .iter()
+            .flat_map(|res| res.propstats.iter().map(move |ps| (res, ps.status)))
+            .filter(|(_, status)| !(200..300).contains(status))
+            .min_by_key(|(_, status)| *status == 424);
+        match failed {
+            Some((res, status)) => {
+                let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                Err(Error::from_status(status, &res.href, res.description.as_deref().unwrap_or_default(), None)
+                    .unwrap_or(Error::invalid(url, format!("PROPPATCH failed with {status}"))))
+            }
+            None => Ok(()),
+        }
+    }
+
+    ///DELETE a collection and everything in it
+    pub(crate) async fn delete_collection(&self, url: &str) -> Result<()> {
+        //the only Depth allowed on a collection (RFC 4918 9.6.1)
+        let mut headers = HeaderMap::new();
+        headers.insert("depth", HeaderValue::from_static("infinity"));
+        self.execute(Method::DELETE, url, 0, String::new(), headers).await?;
+        Ok(())
+    }
+
+    async fn get_etag(&self, url: &str) -> Result<String> {
+        let (root, final_url) = self.propfind_from(url, 0, "<d:prop><d:getetag /></d:prop>").await?;
+        MultiStatus::parse(&root)
+            .responses
+            .first()
+            .and_then(|res| res.prop("getetag", NS_D))
+            .map(|etag| etag.text())
+            .ok_or(Error::invalid(&final_url, "response did not contain getetag"))
+    }
+
+    ///OPTIONS on the home. plenty of servers get it wrong, so failing only
+    ///means we know nothing
+    async fn get_server_info(&self) -> ServerInfo {
+        match self.execute(Method::OPTIONS, &self.home, 0, String::new(), HeaderMap::new()).await {
+            Ok(res) => ServerInfo::from_headers(res.headers()),
+            Err(e) => {
+                self.report(&format!("OPTIONS {}: {e}", self.home));
+                ServerInfo::default()
+            }
+        }
+    }
+
+    async fn get_principal(&self, url: &str) -> Result<String> {
+        let (root, final_url) = self
+            .propfind_from(url, 0, "<d:prop><d:current-user-principal /></d:prop>")
             .await?;
-        let prop = follow_tree(&root, "response.propstat.prop", NS_D)
-            .ok_or(anyhow!("Homeset response did not contain prop"))?;
-        let home_set = follow_tree(&prop, "calendar-home-set", NS_C)
-            .ok_or(anyhow!("Homeset response did not contain calendar-home-set"))?;
-        let href = follow_tree(&home_set, "href", NS_D)
-            .ok_or(anyhow!("Homeset response did not contain href"))?
+        let href = first_prop(&root, "current-user-principal", NS_D)
+            .and_then(|p| p.get_child("href", NS_D).map(|h| h.text()))
+            .ok_or(Error::invalid(&final_url, "principal response did not contain href"))?;
+        resolve_href(&final_url, href.trim())
+    }
+
+    async fn get_homeset(&self, url: &str) -> Result<String> {
+        let (root, final_url) = self
+            .propfind_from(url, 0, "<d:prop><c:calendar-home-set /></d:prop>")
+            .await?;
+        let home_set = first_prop(&root, "calendar-home-set", NS_C)
+            .ok_or(Error::invalid(&final_url, "homeset response did not contain calendar-home-set"))?;
+        let href = home_set
+            .get_child("href", NS_D)
+            .ok_or(Error::invalid(&final_url, "homeset response did not contain href"))?
             .text();
-        if href.contains("http") {
-            return Ok(href);
+        resolve_href(&final_url, href.trim())
+    }
+
+    async fn get_calendars(&self) -> Result<Vec<CalendarRef>> {
+        let (root, final_url) = self.propfind_from(&self.home, 1, CALENDAR_PROPS).await?;
+        let mut cals = vec![];
+        for response in MultiStatus::parse(&root).responses {
+            if let Some(mut cal) = Calendar::parse(&response) {
+                cal.url = resolve_href(&final_url, &cal.url)?;
+                cals.push(cal);
+            }
         }
-        Ok(add_path(&go_back(url), &href))
+        calendar::sort_by_order(&mut cals);
+        Ok(cals.into_iter().map(|cal| Arc::new(RwLock::new(cal))).collect())
     }
 
-    async fn get_calendars(&self) -> anyhow::Result<Vec<RefCell<Calendar>>> {
-        let root = self.propfind(&self.home, 1, CALENDAR_PROPS).await?;
-        let cals: Vec<RefCell<Calendar>> = root
-            .children()
-            .filter_map(|response| Some(RefCell::new(Calendar::parse(response)?)))
-            .collect();
-        Ok(cals)
+    ///the calendar at `url`, as it is now
+    pub(crate) async fn fetch_calendar(&self, url: &str) -> Result<Calendar> {
+        //scoped so the XML is gone before the next await, minidom elements aren't Send
+        let mut cal = {
+            let root = self.propfind(url, 0, CALENDAR_PROPS).await?;
+            let multistatus = MultiStatus::parse(&root);
+            let response = multistatus.responses.first().ok_or(Error::invalid(url, "calendar PROPFIND returned nothing"))?;
+            if let Some(e) = response.failure() {
+                return Err(e);
+            }
+            Calendar::parse(response)
+                .ok_or(Error::invalid(url, "not a calendar"))?
+        };
+        cal.url = url.to_string();
+        Ok(cal)
     }
 
-    pub(crate) async fn refresh_calendar(&self, cal_ref: &RefCell<Calendar>) -> anyhow::Result<bool> {
-        let root = self.propfind(&self.home, 1, CALENDAR_PROPS).await?;
-        let child = root.children().next().ok_or(anyhow!("Refresh calendar returned nothing!"))?;
-        let new_calendar = Calendar::parse(child)
-            .ok_or(anyhow!("Refresh calendar failed because it is no longer a calendar?!"))?;
-        let changed = new_calendar.ctag != cal_ref.borrow().ctag;
+    pub(crate) async fn refresh_calendar(&self, cal_ref: &RwLock<Calendar>) -> Result<bool> {
+        let url = cal_ref.read().await.url.clone();
+        let new_calendar = self.fetch_calendar(&url).await?;
+        let mut cal = cal_ref.write().await;
+        //no ctag at all means we can't tell, so assume it did
+        let changed = new_calendar.ctag.is_empty() || new_calendar.ctag != cal.ctag;
         if changed {
-            *cal_ref.borrow_mut() = new_calendar;
+            *cal = new_calendar;
         }
         Ok(changed)
     }
 
-    pub fn get_calendar(&self, name: &str) -> Option<&RefCell<Calendar>> {
+    pub async fn get_calendar(&self, name: &str) -> Option<CalendarRef> {
         for cal in &self.calendars {
-            if cal.borrow().name == name {
-                return Some(cal);
+            if cal.read().await.name == name {
+                return Some(cal.clone());
             }
         }
         None
     }
 }
 
+///`name` from the first response, whichever 2xx propstat it's in. some
+///servers put a 404 propstat for other properties first
+fn first_prop(root: &Element, name: &str, ns: &str) -> Option<Element> {
+    MultiStatus::parse(root).responses.first()?.prop(name, ns).cloned()
+}
+
+fn parse_url(url: &str) -> Result<Url> {
+    Url::parse(url).map_err(|source| Error::Url { url: url.to_string(), source })
+}
+
+fn header_value(url: &str, value: &str) -> Result<HeaderValue> {
+    HeaderValue::from_str(value).map_err(|_| Error::invalid(url, format!("can't send {value} as a header")))
+}
+
+fn parse_xml(url: &str, text: &str) -> Result<Element> {
+    text.parse().map_err(|source| Error::Xml { url: url.to_string(), source })
+}
+
+///true if a redirect from `from` to `to` can keep the credentials: only the
+///discovery redirect, from a .well-known/caldav to https on the same host or
+///a subdomain at the default port, eg. example.com pointing at
+///caldav.example.com. anywhere else has to be given as the url directly
+fn discovery_redirect(from: &Url, to: &Url) -> bool {
+    if !from.path().starts_with("/.well-known/caldav") || to.scheme() != "https" || to.port().is_some() {
+        return false;
+    }
+    match (from.host_str(), to.host_str()) {
+        (Some(from), Some(host)) => host == from || host.ends_with(&format!(".{from}")),
+        _ => false,
+    }
+}
+
+///methods that are safe to send twice. PUT and DELETE would be too, but
+///every write here is conditional, and those aren't
+fn is_idempotent(method: &Method) -> bool {
+    matches!(method.as_str(), "GET" | "HEAD" | "OPTIONS" | "PROPFIND" | "REPORT")
+}
+
+///exponential backoff, or whatever Retry-After asked for (capped)
+async fn backoff(attempt: u32, retry_after: Option<u64>) {
+    let delay = match retry_after {
+        Some(secs) => Duration::from_secs(secs),
+        None => RETRY_BASE * 2u32.pow(attempt.saturating_sub(1)),
+    };
+    tokio::time::sleep(delay.min(RETRY_MAX)).await;
+}
@@ -0,0 +1,142 @@
+use super::todo::{split_line, unfold};
+
+///an iCalendar component with its properties and nested components, as
+///written. values aren't interpreted beyond splitting off parameters
+#[derive(Debug, Clone)]
+pub struct Component {
+    ///uppercase, eg. `VTODO`
+    pub name: String,
+    pub props: Vec<Prop>,
+    pub children: Vec<Component>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prop {
+    ///uppercase, eg. `DUE`
+    pub name: String,
+    ///uppercase names, values without quotes
+    pub params: Vec<(String, String)>,
+    ///raw value, still escaped
+    pub value: String,
+}
+
+impl Component {
+    ///the outermost component in `ics`, usually the VCALENDAR. None if the
+    ///BEGIN/END lines don't match up
+    pub fn parse(ics: &str) -> Option<Component> {
+        let mut stack: Vec<Component> = vec![];
+        for line in unfold(ics) {
+            let Some((key, value)) = split_line(&line) else { continue };
+            let mut parts = key.split(';');
+            let name = parts.next().unwrap_or_default().to_ascii_uppercase();
+            match name.as_str() {
+                "BEGIN" => stack.push(Component {
+                    name: value.trim().to_ascii_uppercase(),
+                    props: vec![],
+                    children: vec![],
+                }),
+                "END" => {
+                    let done = stack.pop()?;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(done),
+                        None => return Some(done),
+                    }
+                }
+                _ => {
+                    let params = parts
+                        .filter_map(|p| p.split_once('='))
+                        .map(|(k, v)| (k.to_ascii_uppercase(), v.trim_matches('"').to_string()))
+                        .collect();
+                    stack.last_mut()?.props.push(Prop { name, params, value: value.to_string() });
+                }
+            }
+        }
+        None
+    }
+
+    ///the first `name` property
+    pub fn prop(&self, name: &str) -> Option<&Prop> {
+        self.props.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    ///every `name` property, for ones that can repeat like CATEGORIES
+    pub fn props<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Prop> + 'a {
+        self.props.iter().filter(move |p| p.name.eq_ignore_ascii_case(name))
+    }
+
+    ///the first nested `name` component
+    pub fn child(&self, name: &str) -> Option<&Component> {
+        self.children.iter().find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Component> + 'a {
+        self.children.iter().filter(move |c| c.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Prop {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+
+    ///the value as TEXT, unescaped
+    pub fn text(&self) -> String {
+        unescape(&self.value)
+    }
+
+    ///a comma separated TEXT list like CATEGORIES, each unescaped
+    pub fn list(&self) -> Vec<String> {
+        let mut items = vec![];
+        let mut start = 0;
+        let mut escaped = false;
+        for (i, c) in self.value.char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                ',' => {
+                    items.push(unescape(&self.value[start..i]));
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        items.push(unescape(&self.value[start..]));
+        items.retain(|item| !item.trim().is_empty());
+        items
+    }
+}
+
+///undoes RFC 5545 TEXT escaping (3.3.11)
+pub fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(escaped) => text.push(escaped),
+            None => {}
+        }
+    }
+    text
+}
+
+///RFC 5545 TEXT escaping (3.3.11), the other way from [`unescape`]
+pub fn escape(text: &str) -> String {
+    let mut value = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                value.push('\\');
+                value.push(c);
+            }
+            '\n' => value.push_str("\\n"),
+            '\r' => {}
+            _ => value.push(c),
+        }
+    }
+    value
+}
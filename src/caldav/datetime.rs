@@ -0,0 +1,299 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use serde::{Serialize, Serializer};
+
+use super::component::{Component, Prop};
+use super::recur::RRule;
+
+///a DATE or DATE-TIME value as written, with the instant it stands for
+///where that's known (RFC 5545 3.3.4, 3.3.5)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DateValue {
+    ///all day
+    Date(NaiveDate),
+    Utc(DateTime<Utc>),
+    ///the same wall clock time wherever you are
+    Floating(NaiveDateTime),
+    ///wall clock time in `tzid`. `utc` is None if the calendar has no
+    ///VTIMEZONE for it
+    Zoned { time: NaiveDateTime, tzid: String, utc: Option<DateTime<Utc>> },
+}
+
+impl DateValue {
+    ///`prop` of a component inside `root`, which has the VTIMEZONEs
+    pub fn from_prop(prop: &Prop, root: &Component) -> Option<DateValue> {
+        let value = prop.value.trim();
+        if prop.param("VALUE").is_some_and(|v| v.eq_ignore_ascii_case("DATE")) || value.len() == 8 {
+            return NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(DateValue::Date);
+        }
+        if let Some(utc) = value.strip_suffix('Z') {
+            return parse_naive(utc).map(|time| DateValue::Utc(time.and_utc()));
+        }
+        let time = parse_naive(value)?;
+        match prop.param("TZID") {
+            None => Some(DateValue::Floating(time)),
+            Some(tzid) => Some(DateValue::Zoned { time, tzid: tzid.to_string(), utc: zone_to_utc(root, tzid, time) }),
+        }
+    }
+
+    ///the instant, taking dates and floating times as local time
+    pub fn utc(&self) -> Option<DateTime<Utc>> {
+        match self {
+            DateValue::Date(date) => local_to_utc(date.and_time(NaiveTime::MIN)),
+            DateValue::Utc(utc) => Some(*utc),
+            DateValue::Floating(time) => local_to_utc(*time),
+            DateValue::Zoned { utc, .. } => *utc,
+        }
+    }
+
+    ///like [`utc`](Self::utc), with the wall clock time taken as UTC for
+    ///zones the calendar doesn't describe. for sorting and bucketing, where
+    ///an hour or two off beats not placing it at all
+    pub fn instant(&self) -> DateTime<Utc> {
+        match self {
+            DateValue::Zoned { time, utc: None, .. } => time.and_utc(),
+            _ => self.utc().unwrap_or_default(),
+        }
+    }
+
+    ///the day it falls on locally. dates are their own day wherever you are
+    pub fn local_date(&self) -> NaiveDate {
+        match self {
+            DateValue::Date(date) => *date,
+            DateValue::Floating(time) => time.date(),
+            _ => self.instant().with_timezone(&Local).date_naive(),
+        }
+    }
+
+    ///`name` with the parameters it needs, and the value, for
+    ///[`set_property`](super::todo::set_property)
+    pub fn property(&self, name: &str) -> (String, String) {
+        match self {
+            DateValue::Date(date) => (format!("{name};VALUE=DATE"), date.format("%Y%m%d").to_string()),
+            DateValue::Utc(utc) => (name.to_string(), utc.format("%Y%m%dT%H%M%SZ").to_string()),
+            DateValue::Floating(time) => (name.to_string(), time.format("%Y%m%dT%H%M%S").to_string()),
+            DateValue::Zoned { time, tzid, .. } => (format!("{name};TZID={tzid}"), time.format("%Y%m%dT%H%M%S").to_string()),
+        }
+    }
+
+    ///as written, eg. `2024-01-05 17:00 Europe/Berlin`
+    pub fn original(&self) -> String {
+        match self {
+            DateValue::Date(date) => date.format("%Y-%m-%d").to_string(),
+            DateValue::Utc(utc) => utc.format("%Y-%m-%d %H:%M UTC").to_string(),
+            DateValue::Floating(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+            DateValue::Zoned { time, tzid, .. } => format!("{} {tzid}", time.format("%Y-%m-%d %H:%M")),
+        }
+    }
+
+    ///in the local zone. dates, floating times and zones the calendar
+    ///doesn't describe stay as written
+    pub fn local(&self) -> String {
+        match (self, self.utc()) {
+            (DateValue::Date(_) | DateValue::Floating(_), _) | (_, None) => self.original(),
+            (_, Some(utc)) => utc.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string(),
+        }
+    }
+}
+
+impl Serialize for DateValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Json<'a> {
+            ///ISO 8601, with a Z only if it was written in UTC
+            value: String,
+            tzid: Option<&'a str>,
+            all_day: bool,
+            utc: Option<DateTime<Utc>>,
+        }
+        let (value, tzid) = match self {
+            DateValue::Date(date) => (date.format("%Y-%m-%d").to_string(), None),
+            DateValue::Utc(utc) => (utc.format("%Y-%m-%dT%H:%M:%SZ").to_string(), None),
+            DateValue::Floating(time) => (time.format("%Y-%m-%dT%H:%M:%S").to_string(), None),
+            DateValue::Zoned { time, tzid, .. } => (time.format("%Y-%m-%dT%H:%M:%S").to_string(), Some(tzid.as_str())),
+        };
+        Json { value, tzid, all_day: matches!(self, DateValue::Date(_)), utc: self.utc() }.serialize(serializer)
+    }
+}
+
+///`20240105T170000`, without a zone
+pub(crate) fn parse_naive(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim(), "%Y%m%dT%H%M%S").ok()
+}
+
+fn local_to_utc(time: NaiveDateTime) -> Option<DateTime<Utc>> {
+    Local.from_local_datetime(&time).earliest().map(|t| t.with_timezone(&Utc))
+}
+
+fn zone_to_utc(root: &Component, tzid: &str, time: NaiveDateTime) -> Option<DateTime<Utc>> {
+    if matches!(tzid, "UTC" | "Etc/UTC" | "GMT" | "Etc/GMT") {
+        return Some(time.and_utc());
+    }
+    let zone = root
+        .children("VTIMEZONE")
+        .find(|zone| zone.prop("TZID").is_some_and(|id| id.value.trim() == tzid))?;
+    Some((time - zone_offset(zone, time)?).and_utc())
+}
+
+///UTC offset in `zone` at local `time`: TZOFFSETTO of the observance that
+///started last before it, or TZOFFSETFROM of the first one if none had yet
+fn zone_offset(zone: &Component, time: NaiveDateTime) -> Option<Duration> {
+    let mut latest: Option<(NaiveDateTime, Duration)> = None;
+    let mut earliest: Option<(NaiveDateTime, Duration)> = None;
+    for observance in zone.children.iter().filter(|c| c.name == "STANDARD" || c.name == "DAYLIGHT") {
+        let Some(start) = observance.prop("DTSTART").and_then(|p| parse_naive(&p.value)) else { continue };
+        let offset = |name| observance.prop(name).and_then(|p| parse_offset(&p.value));
+        let (Some(from), Some(to)) = (offset("TZOFFSETFROM"), offset("TZOFFSETTO")) else { continue };
+        if earliest.is_none_or(|(first, _)| start < first) {
+            earliest = Some((start, from));
+        }
+        let onset = onsets(observance, start, time.year()).into_iter().filter(|onset| *onset <= time).max();
+        if let Some(onset) = onset {
+            if latest.is_none_or(|(last, _)| onset > last) {
+                latest = Some((onset, to));
+            }
+        }
+    }
+    latest.or(earliest).map(|(_, offset)| offset)
+}
+
+///when an observance started in `year` and the year before. RRULEs are
+///only followed as far as zones use them: yearly, by month and weekday
+fn onsets(observance: &Component, start: NaiveDateTime, year: i32) -> Vec<NaiveDateTime> {
+    let mut onsets = vec![start];
+    onsets.extend(
+        observance
+            .props("RDATE")
+            .flat_map(|p| p.value.split(','))
+            .filter_map(parse_naive),
+    );
+    let Some(rule) = observance.prop("RRULE").map(|p| RRule::parse(&p.value)) else { return onsets };
+    for year in [year - 1, year] {
+        if year < start.year() {
+            continue;
+        }
+        let month = rule.by_month.first().copied().unwrap_or(start.month());
+        let date = match rule.by_day.first() {
+            Some(&(n, weekday)) => nth_weekday(year, month, weekday, n),
+            None => NaiveDate::from_ymd_opt(year, month, start.day()),
+        };
+        let Some(onset) = date.map(|date| date.and_time(start.time())) else { continue };
+        if rule.until.is_none_or(|until| onset <= until) {
+            onsets.push(onset);
+        }
+    }
+    onsets
+}
+
+///`+0100`, `-0530` or `+013045`
+fn parse_offset(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (sign, digits) = match value.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if !matches!(digits.len(), 4 | 6) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let part = |range: std::ops::Range<usize>| digits.get(range).and_then(|d| d.parse::<i64>().ok()).unwrap_or(0);
+    Some(Duration::seconds(sign * (part(0..2) * 3600 + part(2..4) * 60 + part(4..6))))
+}
+
+///the `n`th `weekday` of the month, counting from the end if `n` is negative
+pub(crate) fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: i8) -> Option<NaiveDate> {
+    if n >= 0 {
+        return NaiveDate::from_weekday_of_month_opt(year, month, weekday, n.max(1) as u8);
+    }
+    let next_month = match month {
+        12 => NaiveDate::from_ymd_opt(year + 1, 1, 1),
+        _ => NaiveDate::from_ymd_opt(year, month + 1, 1),
+    }?;
+    let last = next_month.pred_opt()?;
+    let back = (last.weekday().num_days_from_monday() + 7 - weekday.num_days_from_monday()) % 7;
+    let date = last - Duration::days(back as i64 + 7 * (-(n as i64) - 1));
+    (date.month() == month).then_some(date)
+}
+
+///a DURATION like `-PT15M` or `P1W` (RFC 5545 3.3.6)
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (sign, rest) = match value.split_at_checked(1)? {
+        ("-", rest) => (-1, rest),
+        ("+", rest) => (1, rest),
+        _ => (1, value),
+    };
+    let rest = rest.strip_prefix(['P', 'p'])?;
+    let mut total: i64 = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    let mut any = false;
+    for c in rest.chars() {
+        match c.to_ascii_uppercase() {
+            '0'..='9' => number.push(c),
+            'T' if number.is_empty() && !in_time => in_time = true,
+            unit => {
+                let n: i64 = number.parse().ok()?;
+                number.clear();
+                any = true;
+                let unit = match (unit, in_time) {
+                    ('W', false) => 604800,
+                    ('D', false) => 86400,
+                    ('H', true) => 3600,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+                //a server can send anything, too long for chrono is as bad as a typo
+                total = total.checked_add(n.checked_mul(unit)?)?;
+            }
+        }
+    }
+    if !any || !number.is_empty() {
+        return None;
+    }
+    Duration::try_seconds(sign * total)
+}
+
+///a DURATION the other way from [`parse_duration`], in weeks if it's whole
+///weeks, otherwise days and time
+pub fn format_duration(duration: Duration) -> String {
+    let sign = if duration < Duration::zero() { "-" } else { "" };
+    let secs = duration.num_seconds().abs();
+    if secs == 0 {
+        return "PT0S".to_string();
+    }
+    if secs % 604800 == 0 {
+        return format!("{sign}P{}W", secs / 604800);
+    }
+    let mut value = format!("{sign}P");
+    if secs >= 86400 {
+        value.push_str(&format!("{}D", secs / 86400));
+    }
+    let time = secs % 86400;
+    if time > 0 {
+        value.push('T');
+        for (unit, size, rest) in [('H', 3600, 86400), ('M', 60, 3600), ('S', 1, 60)] {
+            if time % rest / size > 0 {
+                value.push_str(&format!("{}{unit}", time % rest / size));
+            }
+        }
+    }
+    value
+}
+
+///eg. `1 day 2 hours`, leaving out the sign
+pub fn describe_duration(duration: Duration) -> String {
+    let mut secs = duration.num_seconds().abs();
+    let mut parts = vec![];
+    for (unit, size) in [("week", 604800), ("day", 86400), ("hour", 3600), ("minute", 60), ("second", 1)] {
+        let n = secs / size;
+        secs %= size;
+        if n > 0 {
+            parts.push(format!("{n} {unit}{}", if n == 1 { "" } else { "s" }));
+        }
+    }
+    match parts.is_empty() {
+        true => "0 minutes".to_string(),
+        false => parts.join(" "),
+    }
+}
@@ -0,0 +1,166 @@
+use serde::Serialize;
+
+use super::component::{Component, Prop};
+use super::datetime::{describe_duration, parse_duration, DateValue};
+use super::recur::RRule;
+use super::todo::{CalendarTodo, TodoState};
+
+///everything there is to show about a todo, read from its iCalendar object.
+///the field names are what `info --json` prints, so they stay put
+#[derive(Debug, Clone, Serialize)]
+pub struct TodoDetails {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub state: TodoState,
+    ///STATUS as written, eg. `NEEDS-ACTION`
+    pub status: Option<String>,
+    pub percent_complete: Option<u8>,
+    ///1 is the highest, 9 the lowest, 0 or None undefined
+    pub priority: Option<u8>,
+    pub start: Option<DateValue>,
+    pub due: Option<DateValue>,
+    pub completed: Option<DateValue>,
+    pub created: Option<DateValue>,
+    pub last_modified: Option<DateValue>,
+    pub categories: Vec<String>,
+    pub location: Option<String>,
+    pub geo: Option<Geo>,
+    pub url: Option<String>,
+    pub alarms: Vec<Alarm>,
+    pub recurrence: Option<Recurrence>,
+    ///UID of the todo this is a subtask of
+    pub parent: Option<String>,
+    ///UIDs of the todos that have this one as their parent
+    pub children: Vec<String>,
+    pub attachments: Vec<String>,
+    pub href: String,
+    pub etag: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Geo {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alarm {
+    ///eg. `DISPLAY` or `AUDIO`
+    pub action: String,
+    ///TRIGGER as written, eg. `-PT15M`
+    pub trigger: String,
+    ///in words, eg. `15 minutes before due`
+    pub when: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Recurrence {
+    ///RRULE as written
+    pub rule: String,
+    ///in words, eg. `every 2nd Tuesday`
+    pub text: String,
+}
+
+impl TodoDetails {
+    ///`subtasks` are the todos right under it in its calendar
+    pub fn new(todo: &CalendarTodo, subtasks: &[&CalendarTodo]) -> TodoDetails {
+        let root = todo.root().unwrap_or_else(|| Component { name: "VCALENDAR".to_string(), props: vec![], children: vec![] });
+        let empty = Component { name: "VTODO".to_string(), props: vec![], children: vec![] };
+        let vtodo = root.child("VTODO").unwrap_or(&empty);
+        let text = |name| vtodo.prop(name).map(Prop::text);
+        let date = |name| vtodo.prop(name).and_then(|p| DateValue::from_prop(p, &root));
+        let uid = vtodo.prop("UID").map(|p| p.value.trim().to_string());
+        let children = subtasks.iter().filter_map(|subtask| subtask.uid()).collect();
+
+        TodoDetails {
+            summary: text("SUMMARY"),
+            description: text("DESCRIPTION"),
+            state: todo.state(),
+            status: vtodo.prop("STATUS").map(|p| p.value.trim().to_ascii_uppercase()),
+            percent_complete: vtodo.prop("PERCENT-COMPLETE").and_then(|p| p.value.trim().parse().ok()),
+            priority: vtodo.prop("PRIORITY").and_then(|p| p.value.trim().parse().ok()),
+            start: date("DTSTART"),
+            due: date("DUE"),
+            completed: date("COMPLETED"),
+            created: date("CREATED"),
+            last_modified: date("LAST-MODIFIED"),
+            categories: vtodo.props("CATEGORIES").flat_map(Prop::list).collect(),
+            location: text("LOCATION"),
+            geo: vtodo.prop("GEO").and_then(|p| {
+                let (lat, lon) = p.value.split_once(';')?;
+                Some(Geo { lat: lat.trim().parse().ok()?, lon: lon.trim().parse().ok()? })
+            }),
+            url: vtodo.prop("URL").map(|p| p.value.trim().to_string()),
+            alarms: vtodo.children("VALARM").map(|alarm| Alarm::new(alarm, &root)).collect(),
+            recurrence: vtodo.prop("RRULE").map(|p| Recurrence {
+                rule: p.value.trim().to_string(),
+                text: RRule::parse(&p.value).describe(),
+            }),
+            parent: todo.parent(),
+            children,
+            attachments: vtodo.props("ATTACH").map(attachment).collect(),
+            href: todo.url.clone(),
+            etag: todo.etag.clone(),
+            uid,
+        }
+    }
+}
+
+impl Alarm {
+    fn new(alarm: &Component, root: &Component) -> Alarm {
+        let trigger = alarm.prop("TRIGGER");
+        let when = match trigger {
+            Some(p) if p.param("VALUE").is_some_and(|v| v.eq_ignore_ascii_case("DATE-TIME")) => {
+                match DateValue::from_prop(p, root) {
+                    Some(at) => format!("at {}", at.local()),
+                    None => p.value.trim().to_string(),
+                }
+            }
+            Some(p) => {
+                //relative to the start unless it says otherwise, which for a todo is DTSTART or DUE
+                let anchor = match p.param("RELATED") {
+                    Some(related) if related.eq_ignore_ascii_case("END") => "due",
+                    _ => "start",
+                };
+                match parse_duration(&p.value) {
+                    Some(d) if d.is_zero() => format!("at {anchor}"),
+                    Some(d) if d < chrono::Duration::zero() => format!("{} before {anchor}", describe_duration(d)),
+                    Some(d) => format!("{} after {anchor}", describe_duration(d)),
+                    None => p.value.trim().to_string(),
+                }
+            }
+            None => "never".to_string(),
+        };
+        Alarm {
+            action: alarm.prop("ACTION").map(|p| p.value.trim().to_ascii_uppercase()).unwrap_or_default(),
+            trigger: trigger.map(|p| p.value.trim().to_string()).unwrap_or_default(),
+            when,
+            description: alarm.prop("DESCRIPTION").map(Prop::text),
+        }
+    }
+}
+
+///the uri, or for inline ones what kind and how big
+fn attachment(prop: &Prop) -> String {
+    if !prop.param("ENCODING").is_some_and(|e| e.eq_ignore_ascii_case("BASE64")) {
+        return prop.value.trim().to_string();
+    }
+    let name = prop
+        .param("FILENAME")
+        .or(prop.param("X-FILENAME"))
+        .or(prop.param("FMTTYPE"))
+        .unwrap_or("attachment");
+    format!("{name} ({} bytes inline)", prop.value.trim().len() / 4 * 3)
+}
+
+///`high`, `medium` or `low`, as RFC 5545 3.8.1.9 splits them up
+pub fn priority_label(priority: u8) -> &'static str {
+    match priority {
+        0 => "none",
+        1..=4 => "high",
+        5 => "medium",
+        _ => "low",
+    }
+}
@@ -0,0 +1,160 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use hickory_resolver::TokioAsyncResolver;
+use url::Url;
+
+use super::error::{Error, Result};
+
+pub type ResolveFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+///DNS lookups used by discovery. swap this out to point tests at a stub
+pub trait Resolver: Send + Sync {
+    fn srv<'a>(&'a self, name: &'a str) -> ResolveFuture<'a, Vec<SrvRecord>>;
+    fn txt<'a>(&'a self, name: &'a str) -> ResolveFuture<'a, Vec<String>>;
+}
+
+///system DNS resolver
+pub struct DnsResolver {
+    inner: TokioAsyncResolver,
+}
+
+impl DnsResolver {
+    pub fn new() -> Result<Self> {
+        let inner = TokioAsyncResolver::tokio_from_system_conf().map_err(|e| Error::Dns {
+            name: "system config".to_string(),
+            reason: e.to_string(),
+        })?;
+        Ok(DnsResolver { inner })
+    }
+}
+
+impl Resolver for DnsResolver {
+    fn srv<'a>(&'a self, name: &'a str) -> ResolveFuture<'a, Vec<SrvRecord>> {
+        Box::pin(async move {
+            let lookup = self.inner.srv_lookup(name).await.map_err(|e| dns_error(name, e))?;
+            Ok(lookup
+                .iter()
+                .map(|srv| SrvRecord {
+                    priority: srv.priority(),
+                    weight: srv.weight(),
+                    port: srv.port(),
+                    target: srv.target().to_utf8(),
+                })
+                .collect())
+        })
+    }
+
+    fn txt<'a>(&'a self, name: &'a str) -> ResolveFuture<'a, Vec<String>> {
+        Box::pin(async move {
+            let lookup = self.inner.txt_lookup(name).await.map_err(|e| dns_error(name, e))?;
+            Ok(lookup
+                .iter()
+                .map(|txt| {
+                    txt.txt_data()
+                        .iter()
+                        .map(|part| String::from_utf8_lossy(part).into_owned())
+                        .collect::<String>()
+                })
+                .collect())
+        })
+    }
+}
+
+fn dns_error(name: &str, e: impl std::fmt::Display) -> Error {
+    Error::Dns { name: name.to_string(), reason: e.to_string() }
+}
+
+///paths popular servers use when there's no SRV record or well-known redirect
+const FALLBACK_PATHS: [&str; 5] = ["/", "/remote.php/dav/", "/dav.php/", "/caldav/", "/dav/"];
+
+///true if the user gave us a url rather than a domain or email
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("http://") || input.starts_with("https://")
+}
+
+///the domain part of `user@example.com`, or the input itself
+pub fn domain_of(input: &str) -> &str {
+    let domain = input.rsplit_once('@').map(|(_, d)| d).unwrap_or(input);
+    domain.trim_end_matches('/')
+}
+
+///true for a url that's just a site, eg. `http://example.com`, which can be
+///discovered from like a domain when nothing answers at its root
+pub fn is_site(input: &str) -> bool {
+    is_url(input) && Url::parse(input).is_ok_and(|url| url.domain().is_some() && url.path() == "/" && url.query().is_none())
+}
+
+///RFC 6764 bootstrapping: every url worth trying for principal discovery,
+///best first (SRV+TXT, then well-known, then common paths). calls `report`
+///for each lookup. `input` is a domain, an email or a site (see [`is_site`]).
+///plain http ones only come up if it's an http site, anything else is
+///https so the password never goes out in the clear
+pub async fn candidates(
+    input: &str,
+    resolver: Option<&dyn Resolver>,
+    report: &(dyn Fn(&str) + Sync),
+) -> Vec<String> {
+    let site = Url::parse(input).ok().filter(|_| is_url(input));
+    let (domain, origin) = match &site {
+        Some(url) => (url.host_str().unwrap_or_default().to_string(), url.origin().ascii_serialization()),
+        None => (domain_of(input).to_string(), format!("https://{}", domain_of(input))),
+    };
+    let services = match site.as_ref().is_some_and(|url| url.scheme() == "http") {
+        true => &[("_caldavs._tcp", "https"), ("_caldav._tcp", "http")][..],
+        false => &[("_caldavs._tcp", "https")][..],
+    };
+    let mut urls = vec![];
+
+    //SRV/TXT lookups, skipped if there's no resolver (eg. no system DNS config)
+    if let Some(resolver) = resolver {
+        for &(service, scheme) in services {
+            let name = format!("{service}.{domain}");
+            let mut records = match resolver.srv(&name).await {
+                Ok(records) => records,
+                Err(e) => {
+                    report(&format!("SRV {name}: {e}"));
+                    continue;
+                }
+            };
+            //"." means the service is explicitly not offered
+            records.retain(|r| !r.target.trim_end_matches('.').is_empty());
+            if records.is_empty() {
+                report(&format!("SRV {name}: no records"));
+                continue;
+            }
+            //lower priority first, then higher weight
+            records.sort_by(|a, b| a.priority.cmp(&b.priority).then(b.weight.cmp(&a.weight)));
+
+            let path = match resolver.txt(&name).await {
+                Ok(txts) => txts.iter().find_map(|t| t.strip_prefix("path=").map(str::to_string)),
+                Err(e) => {
+                    report(&format!("TXT {name}: {e}"));
+                    None
+                }
+            };
+            let path = path.unwrap_or("/.well-known/caldav".to_string());
+
+            for r in records {
+                let host = r.target.trim_end_matches('.');
+                let url = format!("{scheme}://{host}:{}{path}", r.port);
+                report(&format!("SRV {name}: {url}"));
+                urls.push(url);
+            }
+        }
+    }
+
+    urls.push(format!("{origin}/.well-known/caldav"));
+    for path in FALLBACK_PATHS {
+        urls.push(format!("{origin}{path}"));
+    }
+    urls
+}
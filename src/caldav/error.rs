@@ -0,0 +1,128 @@
+use reqwest::StatusCode;
+use thiserror::Error;
+
+use super::parser::DavError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+///everything the caldav module can fail with
+#[derive(Debug, Error)]
+pub enum Error {
+    ///couldn't talk to the server at all
+    #[error("Request to {url} failed: {source}")]
+    Transport { url: String, source: reqwest::Error },
+    #[error("Request to {url} timed out")]
+    Timeout { url: String },
+    ///credentials were rejected or couldn't be refreshed
+    #[error("Authentication failed for {url}{}", fmt_reason(reason))]
+    Auth { url: String, reason: Option<String> },
+    #[error("Forbidden: {url}{}", fmt_condition(error))]
+    Forbidden { url: String, error: Option<DavError> },
+    #[error("Not found: {url}")]
+    NotFound { url: String },
+    ///412/409: the resource changed on the server since we last saw it
+    #[error("Conflict: {url} was changed on the server{}{}", fmt_etag(etag), fmt_condition(error))]
+    Conflict { url: String, etag: Option<String>, error: Option<DavError> },
+    ///any other non-2xx status, including 5xx after retries
+    #[error("{status} from {url}{}", fmt_detail(error, body))]
+    Status { status: StatusCode, url: String, body: String, error: Option<DavError> },
+    #[error("Too many redirects from {url}")]
+    TooManyRedirects { url: String },
+    #[error("Invalid XML from {url}: {source}")]
+    Xml { url: String, source: minidom::Error },
+    ///the server answered, but not with what we asked for
+    #[error("Unexpected response from {url}: {message}")]
+    InvalidResponse { url: String, message: String },
+    #[error("Invalid iCalendar in {href}{}: {message}", fmt_line(line))]
+    ICal { href: String, line: Option<usize>, message: String },
+    #[error("{feature} is not supported by the server")]
+    Unsupported { feature: String },
+    #[error("Invalid url {url}: {source}")]
+    Url { url: String, source: url::ParseError },
+    #[error("DNS lookup of {name} failed: {reason}")]
+    Dns { name: String, reason: String },
+    #[error("Service discovery for {input} failed, try giving the full CalDAV url")]
+    Discovery { input: String },
+    ///reading or writing a local store
+    #[error("{path}: {source}")]
+    Io { path: String, source: std::io::Error },
+}
+
+fn fmt_reason(reason: &Option<String>) -> String {
+    match reason {
+        Some(r) => format!(": {r}"),
+        None => String::new(),
+    }
+}
+
+fn fmt_condition(error: &Option<DavError>) -> String {
+    match error {
+        Some(e) => format!(" ({e})"),
+        None => String::new(),
+    }
+}
+
+fn fmt_etag(etag: &Option<String>) -> String {
+    match etag {
+        Some(e) => format!(", current ETag is {e}"),
+        None => String::new(),
+    }
+}
+
+fn fmt_detail(error: &Option<DavError>, body: &str) -> String {
+    match error {
+        Some(e) => format!(" ({e})"),
+        None if body.is_empty() => String::new(),
+        None => format!(": {body}"),
+    }
+}
+
+fn fmt_line(line: &Option<usize>) -> String {
+    match line {
+        Some(l) => format!(" at line {l}"),
+        None => String::new(),
+    }
+}
+
+impl Error {
+    ///None for success statuses. `body` may be an RFC 4918 `<d:error>`
+    pub fn from_status(status: StatusCode, url: &str, body: &str, etag: Option<String>) -> Option<Self> {
+        let url = url.to_string();
+        let error = DavError::from_body(body);
+        Some(match status {
+            s if s.is_success() => return None,
+            StatusCode::UNAUTHORIZED => Error::Auth { url, reason: None },
+            StatusCode::FORBIDDEN => Error::Forbidden { url, error },
+            StatusCode::NOT_FOUND | StatusCode::GONE => Error::NotFound { url },
+            StatusCode::PRECONDITION_FAILED | StatusCode::CONFLICT => Error::Conflict { url, etag, error },
+            _ => {
+                //keep the error readable if the server sends back a whole page
+                let body = body.chars().take(200).collect();
+                Error::Status { status, url, body, error }
+            }
+        })
+    }
+
+    pub(crate) fn invalid(url: &str, message: impl Into<String>) -> Self {
+        Error::InvalidResponse { url: url.to_string(), message: message.into() }
+    }
+
+    pub(crate) fn transport(url: &str, source: reqwest::Error) -> Self {
+        if source.is_timeout() {
+            return Error::Timeout { url: url.to_string() };
+        }
+        Error::Transport { url: url.to_string(), source }
+    }
+
+    ///the HTTP status behind this error, if there was one
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Auth { .. } => Some(StatusCode::UNAUTHORIZED),
+            Error::Forbidden { .. } => Some(StatusCode::FORBIDDEN),
+            Error::NotFound { .. } => Some(StatusCode::NOT_FOUND),
+            Error::Conflict { .. } => Some(StatusCode::PRECONDITION_FAILED),
+            Error::Status { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+}
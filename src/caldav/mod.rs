@@ -1,4 +1,25 @@
-mod parser;
+pub mod parser;
+pub mod auth;
+pub mod discovery;
+pub mod error;
 pub mod client;
 pub mod todo;
 pub mod calendar;
+pub mod capabilities;
+pub mod component;
+pub mod datetime;
+pub mod recur;
+pub mod details;
+pub mod agenda;
+pub mod alarm;
+pub mod tree;
+
+pub use auth::Credentials;
+pub use calendar::{Calendar, CalendarProps};
+pub use details::TodoDetails;
+pub use capabilities::{CalendarCapabilities, ServerInfo};
+pub use client::{CalDAVClient, CalendarRef};
+pub use error::{Error, Result};
+pub use parser::{ItemError, Partial};
+pub use todo::{CalendarTodo, TodoState};
+pub use tree::TodoTree;
@@ -1,6 +1,9 @@
 use minidom::Element;
+use reqwest::StatusCode;
 use url::Url;
 
+use super::error::{Error, Result};
+
 pub const NS_D: &str = "DAV:";
 pub const NS_C: &str = "urn:ietf:params:xml:ns:caldav";
 pub const NS_CS: &str = "http://calendarserver.org/ns/";
@@ -10,6 +13,11 @@ pub fn format_ns_attrs() -> String {
     format!("xmlns:d=\"{NS_D}\" xmlns:c=\"{NS_C}\" xmlns:cs=\"{NS_CS}\" xmlns:i=\"{NS_I}\"")
 }
 
+///for text going into an XML body
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
 pub fn follow_tree(el: &Element, tree: &str, namespace: &str) -> Option<Element> {
     let parts = tree.split(".");
     let mut cur_el = el;
@@ -19,27 +27,144 @@ pub fn follow_tree(el: &Element, tree: &str, namespace: &str) -> Option<Element>
     Some(cur_el.clone())
 }
 
+///a parsed 207 Multi-Status body (RFC 4918 13)
+pub struct MultiStatus {
+    pub responses: Vec<DavResponse>,
+}
+
+///one `<d:response>`: either a status for the whole href, or propstats
+pub struct DavResponse {
+    pub href: String,
+    pub status: Option<u16>,
+    pub propstats: Vec<PropStat>,
+    pub error: Option<DavError>,
+    pub description: Option<String>,
+}
+
+pub struct PropStat {
+    pub status: u16,
+    pub prop: Element,
+}
+
+///`<d:error>` body naming the pre/postconditions that failed (RFC 4918 16),
+///eg. `no-uid-conflict` or `supported-calendar-component`
+#[derive(Debug, Clone)]
+pub struct DavError {
+    pub conditions: Vec<String>,
+}
+
+///something that failed for one href, while the rest of the request worked
+#[derive(Debug)]
+pub struct ItemError {
+    pub href: String,
+    pub error: Error,
+}
+
+///results for every item that worked, plus what went wrong with the rest
+pub struct Partial<T> {
+    pub items: Vec<T>,
+    pub errors: Vec<ItemError>,
+}
+
+impl<T> Default for Partial<T> {
+    fn default() -> Self {
+        Partial { items: vec![], errors: vec![] }
+    }
+}
+
+impl MultiStatus {
+    pub fn parse(root: &Element) -> Self {
+        let responses = root
+            .children()
+            .filter(|c| c.is("response", NS_D))
+            .map(DavResponse::parse)
+            .collect();
+        MultiStatus { responses }
+    }
+}
+
+impl DavResponse {
+    fn parse(el: &Element) -> Self {
+        let propstats = el
+            .children()
+            .filter(|c| c.is("propstat", NS_D))
+            .filter_map(|ps| {
+                Some(PropStat {
+                    status: parse_status(&ps.get_child("status", NS_D)?.text())?,
+                    prop: ps.get_child("prop", NS_D)?.clone(),
+                })
+            })
+            .collect();
+        DavResponse {
+            href: el.get_child("href", NS_D).map(|h| h.text().trim().to_string()).unwrap_or_default(),
+            status: el.get_child("status", NS_D).and_then(|s| parse_status(&s.text())),
+            propstats,
+            error: el.get_child("error", NS_D).map(DavError::parse),
+            description: el.get_child("responsedescription", NS_D).map(|d| d.text()),
+        }
+    }
+
+    ///a property the server actually has, from any 2xx propstat. properties
+    ///in 404 propstats are missing so they come back as None
+    pub fn prop(&self, name: &str, namespace: &str) -> Option<&Element> {
+        self.propstats
+            .iter()
+            .filter(|ps| (200..300).contains(&ps.status))
+            .find_map(|ps| ps.prop.get_child(name, namespace))
+    }
+
+    ///the failure for this href, if the whole response or every propstat failed
+    pub fn failure(&self) -> Option<Error> {
+        let status = match self.status {
+            Some(s) => s,
+            None if self.propstats.iter().any(|ps| (200..300).contains(&ps.status)) => return None,
+            None => self.propstats.first().map(|ps| ps.status).unwrap_or(500),
+        };
+        let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut err = Error::from_status(status, &self.href, self.description.as_deref().unwrap_or_default(), None)?;
+        //the condition is in the response, not the body
+        if let Error::Forbidden { error, .. } | Error::Conflict { error, .. } | Error::Status { error, .. } = &mut err {
+            *error = self.error.clone();
+        }
+        Some(err)
+    }
+}
+
+impl DavError {
+    fn parse(el: &Element) -> Self {
+        DavError {
+            conditions: el.children().map(|c| c.name().to_string()).collect(),
+        }
+    }
+
+    ///parse the body of a failed (non-207) request, if it is a `<d:error>`
+    pub fn from_body(body: &str) -> Option<Self> {
+        let root: Element = body.parse().ok()?;
+        root.is("error", NS_D).then(|| DavError::parse(&root))
+    }
+}
+
+impl std::fmt::Display for DavError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.conditions.join(", "))
+    }
+}
+
+///`HTTP/1.1 404 Not Found` => 404
+fn parse_status(line: &str) -> Option<u16> {
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
 pub fn add_path(url: &str, path: &str) -> String {
     let base = url.trim_end_matches('/');
     let path = path.trim_start_matches('/');
     format!("{}/{}", base, path)
 }
 
-pub fn go_back(url: &str) -> String {
-    let url = url.trim_end_matches('/');
-    match Url::parse(url) {
-        Ok(mut parsed_url) => {
-            let segments: Vec<_> = parsed_url.path_segments()
-                .map(|segments| segments.collect::<Vec<_>>())
-                .unwrap_or_default();
-            if !segments.is_empty() {
-                let new_path = segments[..segments.len() - 1].join("/");
-                parsed_url.set_path(&new_path);
-            } else {
-                parsed_url.set_path("/");
-            }
-            parsed_url.to_string()
-        },
-        Err(_) => url.to_string(),
-    }
+///resolve a DAV:href (absolute url, absolute path or relative path)
+///against the url of the response it came from
+pub fn resolve_href(base: &str, href: &str) -> Result<String> {
+    let url_error = |source| Error::Url { url: href.to_string(), source };
+    let base = Url::parse(base).map_err(url_error)?;
+    Ok(base.join(href).map_err(url_error)?.to_string())
 }
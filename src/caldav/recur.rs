@@ -0,0 +1,174 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+
+use super::datetime::parse_naive;
+
+///the parts of an RRULE that get described or followed (RFC 5545 3.3.10)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RRule {
+    ///uppercase, eg. `WEEKLY`
+    pub freq: String,
+    pub interval: u32,
+    pub count: Option<u32>,
+    ///dates are taken as midnight
+    pub until: Option<NaiveDateTime>,
+    ///which one in the month or year (negative from the end, 0 for all of them) and the day
+    pub by_day: Vec<(i8, Weekday)>,
+    ///negative from the end of the month
+    pub by_month_day: Vec<i8>,
+    pub by_month: Vec<u32>,
+}
+
+impl RRule {
+    ///parts it doesn't know or can't read are left out
+    pub fn parse(value: &str) -> RRule {
+        let mut rule = RRule { interval: 1, ..Default::default() };
+        for part in value.trim().split(';') {
+            let Some((key, value)) = part.split_once('=') else { continue };
+            let list = || value.split(',').map(str::trim);
+            match key.trim().to_ascii_uppercase().as_str() {
+                "FREQ" => rule.freq = value.trim().to_ascii_uppercase(),
+                "INTERVAL" => rule.interval = value.trim().parse().unwrap_or(1).max(1),
+                "COUNT" => rule.count = value.trim().parse().ok(),
+                "UNTIL" => {
+                    let value = value.trim().trim_end_matches('Z');
+                    rule.until = parse_naive(value)
+                        .or_else(|| NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(|d| d.and_time(NaiveTime::MIN)))
+                }
+                "BYDAY" => rule.by_day = list().filter_map(parse_by_day).collect(),
+                "BYMONTHDAY" => rule.by_month_day = list().filter_map(|d| d.parse().ok()).collect(),
+                "BYMONTH" => rule.by_month = list().filter_map(|m| m.parse().ok()).collect(),
+                _ => {}
+            }
+        }
+        rule
+    }
+
+    ///in words, eg. `every 2nd Tuesday` or `every 2 weeks on Monday and Friday, 10 times`
+    pub fn describe(&self) -> String {
+        let (unit, units) = match self.freq.as_str() {
+            "SECONDLY" => ("second", "seconds"),
+            "MINUTELY" => ("minute", "minutes"),
+            "HOURLY" => ("hour", "hours"),
+            "DAILY" => ("day", "days"),
+            "WEEKLY" => ("week", "weeks"),
+            "MONTHLY" => ("month", "months"),
+            "YEARLY" => ("year", "years"),
+            _ => return format!("repeats ({})", self.freq.to_lowercase()),
+        };
+        let every = match self.interval {
+            1 => format!("every {unit}"),
+            n => format!("every {n} {units}"),
+        };
+
+        let weekdays = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+        let only_weekdays = self.by_day.len() == 5 && weekdays.iter().all(|d| self.by_day.contains(&(0, *d)));
+        let days: Vec<String> = self
+            .by_day
+            .iter()
+            .map(|&(n, day)| match n {
+                0 => day_name(day).to_string(),
+                n => format!("{} {}", ordinal(n.into()), day_name(day)),
+            })
+            .collect();
+        let numbered = self.by_day.iter().any(|&(n, _)| n != 0);
+        let mut text = match (self.interval, self.freq.as_str()) {
+            _ if days.is_empty() => every,
+            (1, "DAILY" | "WEEKLY") if only_weekdays => "every weekday".to_string(),
+            (1, "WEEKLY" | "MONTHLY") => format!("every {}", join(&days)),
+            _ if numbered => format!("{every} on the {}", join(&days)),
+            _ => format!("{every} on {}", join(&days)),
+        };
+
+        if !self.by_month_day.is_empty() {
+            let month_days: Vec<String> = self
+                .by_month_day
+                .iter()
+                .map(|&d| match d {
+                    -1 => "last day".to_string(),
+                    d if d < 0 => format!("{} day", ordinal(d.into())),
+                    d => ordinal(d.into()),
+                })
+                .collect();
+            text = format!("{text} on the {}", join(&month_days));
+        }
+        if !self.by_month.is_empty() {
+            let months: Vec<String> = self.by_month.iter().filter_map(|&m| month_name(m)).map(str::to_string).collect();
+            text = format!("{text} in {}", join(&months));
+        }
+        match (self.count, self.until) {
+            (Some(1), _) => text + ", once",
+            (Some(count), _) => format!("{text}, {count} times"),
+            (None, Some(until)) => format!("{text}, until {}", until.format("%Y-%m-%d")),
+            (None, None) => text,
+        }
+    }
+}
+
+///`2TU`, `-1FR` or just `MO`
+fn parse_by_day(value: &str) -> Option<(i8, Weekday)> {
+    let split = value.len().checked_sub(2)?;
+    let (n, day) = value.split_at_checked(split)?;
+    let n = match n {
+        "" => 0,
+        n => n.trim_start_matches('+').parse().ok()?,
+    };
+    let day = match day.to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+    Some((n, day))
+}
+
+fn day_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+fn month_name(month: u32) -> Option<&'static str> {
+    let names = [
+        "January", "February", "March", "April", "May", "June",
+        "July", "August", "September", "October", "November", "December",
+    ];
+    names.get(month.checked_sub(1)? as usize).copied()
+}
+
+///`1st`, `2nd`, `last`, `2nd to last`
+fn ordinal(n: i32) -> String {
+    if n == -1 {
+        return "last".to_string();
+    }
+    let abs = n.abs();
+    let suffix = match (abs % 10, abs % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    match n < 0 {
+        true => format!("{abs}{suffix} to last"),
+        false => format!("{abs}{suffix}"),
+    }
+}
+
+///`a`, `a and b`, `a, b and c`
+fn join(items: &[String]) -> String {
+    match items {
+        [] => String::new(),
+        [one] => one.clone(),
+        [rest @ .., last] => format!("{} and {last}", rest.join(", ")),
+    }
+}
@@ -1,24 +1,48 @@
-use std::{cell::RefCell, rc::Rc};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use minidom::Element;
+use chrono::{DateTime, Utc};
+use reqwest::header::{IF_MATCH, IF_NONE_MATCH};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use url::Url;
 
 use crate::ical::objects::{generics::{ICalObject, VCalendar}, vtodo::VTodo};
+use crate::store::Query;
 
-use super::{calendar::Calendar, client::CalDAVClient, parser::{follow_tree, NS_C, NS_D}};
-
-use anyhow::{anyhow, Context};
+use super::component::{escape, unescape, Component};
+use super::datetime::DateValue;
+use super::{calendar::Calendar, client::CalDAVClient, error::{Error, Result}, parser::{resolve_href, DavResponse, ItemError, MultiStatus, Partial, NS_C, NS_D}};
 
 ///represents the entire VTODO REPORT
 pub struct CalendarTodo {
+    ///version token, the ETag for CalDAV. writes fail if it changed
     pub etag: String,
     pub url: String,
+    ///the calendar object as it was stored
+    pub ics: String,
     ///has VTODO removed
     pub vcal: VCalendar,
     pub vtodo: VTodo
 }
 
+///where a todo is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TodoState {
+    Open,
+    Completed,
+    Cancelled,
+}
+
+const NOT_COMPLETED: &str = r#"<c:prop-filter name="COMPLETED"><c:is-not-defined/></c:prop-filter>"#;
+const CANCELLED: &str = r#"<c:prop-filter name="STATUS"><c:text-match>CANCELLED</c:text-match></c:prop-filter>"#;
+
 impl CalDAVClient {
-    async fn get_todos(&self, cal: &Calendar, filter: &str) -> anyhow::Result<Vec<CalendarTodo>> {
+    ///one broken todo doesn't fail the rest, it ends up in `errors` instead
+    async fn get_todos(&self, cal: &Calendar, filter: &str) -> Result<Partial<CalendarTodo>> {
         let body = format!(
             r#"
             <d:prop>
@@ -33,66 +57,158 @@ impl CalDAVClient {
                 </c:comp-filter>
             </c:filter>
         "#);
-        let root = self.calquery(&cal.url, 1, &body).await
-            .context("Get todos")?;
-        let mut todos = vec![];
-        for child in root.children() {
-            todos.push(CalendarTodo::parse(child)?);
+        let root = self.calquery(&cal.url, 1, &body).await?;
+        let mut todos = Partial::default();
+        for response in MultiStatus::parse(&root).responses {
+            let todo = CalendarTodo::parse(&response).and_then(|mut todo| {
+                todo.url = resolve_href(&cal.url, &todo.url)?;
+                Ok(todo)
+            });
+            match todo {
+                Ok(todo) => todos.items.push(todo),
+                Err(error) => todos.errors.push(ItemError { href: response.href, error }),
+            }
         }
         Ok(todos)
     }
 
-    pub async fn get_current_todos(&self, cal_ref: &RefCell<Calendar>) -> anyhow::Result<Rc<Vec<CalendarTodo>>> {
-        //have cache & ctag did not change => use cache
-        if cal_ref.borrow().cache_current_todos.len() > 0 && !self.refresh_calendar(cal_ref).await? {
-            //TODO check ctag
-            return Ok(cal_ref.borrow().cache_current_todos.clone());
+    pub async fn get_current_todos(&self, cal_ref: &RwLock<Calendar>) -> Result<Arc<Partial<CalendarTodo>>> {
+        self.get_todos_matching(cal_ref, Query::Current).await
+    }
+
+    pub async fn get_past_todos(&self, cal_ref: &RwLock<Calendar>) -> Result<Arc<Partial<CalendarTodo>>> {
+        self.get_todos_matching(cal_ref, Query::Completed).await
+    }
+
+    ///the calendar's todos that match `query`, from the cache if the ctag didn't change
+    pub async fn get_todos_matching(&self, cal_ref: &RwLock<Calendar>, query: Query) -> Result<Arc<Partial<CalendarTodo>>> {
+        let cached = cal_ref.read().await.cache.contains_key(&query);
+        if cached && !self.refresh_calendar(cal_ref).await? {
+            if let Some(todos) = cal_ref.read().await.cache.get(&query) {
+                return Ok(todos.clone());
+            }
         }
 
-        let mut todos1 = self.get_todos(&cal_ref.borrow(), r#"
-            <c:prop-filter name="PERCENT-COMPLETE">
-                <c:text-match collation="i;ascii-numeric" negate-condition="yes">100</c:text-match>
-            </c:prop-filter>
-        "#).await?;
-        let mut todos2 = self.get_todos(&cal_ref.borrow(), r#"
-            <c:prop-filter name="PERCENT-COMPLETE">
-                <c:is-not-defined/>
-            </c:prop-filter>
-        "#,).await?;
-        todos1.append(&mut todos2);
-        cal_ref.borrow_mut().cache_current_todos = todos1.into();
-        Ok(cal_ref.borrow().cache_current_todos.clone())
+        //the server narrows it down as far as a filter can, the rest is done here.
+        //text-match never matches a property that isn't there, so STATUS and
+        //PERCENT-COMPLETE can't be tested by the server without a REPORT each
+        let filter = match query {
+            Query::Current => NOT_COMPLETED,
+            Query::Cancelled => CANCELLED,
+            //a prop-filter with nothing in it only asks for the property to be there
+            Query::Subtasks => r#"<c:prop-filter name="RELATED-TO"/>"#,
+            Query::Completed | Query::All | Query::History { .. } => "",
+        };
+        let cal = cal_ref.read().await;
+        let mut todos = match query {
+            Query::History { since, until } if since.is_some() || until.is_some() => {
+                self.get_finished(&cal, since, until).await?
+            }
+            _ => self.get_todos(&cal, filter).await?,
+        };
+        drop(cal);
+        todos.items.retain(|todo| query.matches(todo));
+
+        let todos = Arc::new(todos);
+        cal_ref.write().await.cache.insert(query, todos.clone());
+        Ok(todos)
     }
 
-    pub async fn get_past_todos(&self, cal_ref: &RefCell<Calendar>) -> anyhow::Result<Rc<Vec<CalendarTodo>>> {
-        //have cache & ctag did not change => use cache
-        if cal_ref.borrow().cache_past_todos.len() > 0  && !self.refresh_calendar(cal_ref).await? {
-            return Ok(cal_ref.borrow().cache_past_todos.clone());
+    ///todos completed between `since` and `until` by a time-range on COMPLETED,
+    ///plus every todo without a COMPLETED, which is how most cancelled ones and
+    ///some done ones are. servers that won't take the time-range get asked for
+    ///everything instead
+    async fn get_finished(
+        &self,
+        cal: &Calendar,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> Result<Partial<CalendarTodo>> {
+        let attr = |name, at: Option<DateTime<Utc>>| at.map(|at| format!(r#" {name}="{}""#, format_utc(at)));
+        let range = format!(
+            r#"<c:prop-filter name="COMPLETED"><c:time-range{}{}/></c:prop-filter>"#,
+            attr("start", since).unwrap_or_default(),
+            attr("end", until).unwrap_or_default()
+        );
+        let mut todos = match self.get_todos(cal, &range).await {
+            //403 with CALDAV:supported-filter is what RFC 4791 7.7 asks for, the rest is what servers do
+            Err(e) if matches!(e.status().map(|s| s.as_u16()), Some(400 | 403 | 422 | 501)) => {
+                return self.get_todos(cal, "").await;
+            }
+            result => result?,
+        };
+        let undated = self.get_todos(cal, NOT_COMPLETED).await?;
+        for todo in undated.items {
+            if !todos.items.iter().any(|t| t.url == todo.url) {
+                todos.items.push(todo);
+            }
+        }
+        for error in undated.errors {
+            if !todos.errors.iter().any(|e| e.href == error.href) {
+                todos.errors.push(error);
+            }
         }
+        Ok(todos)
+    }
 
-        let todos = self.get_todos(&cal_ref.borrow(), r#"
-            <c:prop-filter name="PERCENT-COMPLETE">
-                <c:text-match collation="i;ascii-numeric">100</c:text-match>
-            </c:prop-filter>
-        "#).await?;
+    ///stores a new todo as `<uid>.ics` in `cal`. fails with `Error::Conflict`
+    ///if there's already something there, and with `Error::Unsupported`
+    ///if the calendar doesn't take todos or one this big
+    pub async fn create_todo(&self, cal: &Calendar, uid: &str, ics: &str) -> Result<CalendarTodo> {
+        if !cal.supports_todo {
+            return Err(Error::Unsupported { feature: format!("storing todos in {} (it's for events only)", cal.name) });
+        }
+        let mut url = Url::parse(&cal.url).map_err(|source| Error::Url { url: cal.url.clone(), source })?;
+        url.path_segments_mut()
+            .map_err(|_| Error::invalid(&cal.url, "calendar url can't have a path"))?
+            .pop_if_empty()
+            .push(&format!("{uid}.ics"));
+        cal.capabilities.check_size(url.as_str(), ics)?;
+        let etag = self.put(url.as_str(), ics, IF_NONE_MATCH, "*").await?;
+        CalendarTodo::from_ics(url.as_str(), etag, ics)
+    }
+
+    ///overwrites `todo`, as long as nobody else changed it since it was fetched.
+    ///fails with `Error::Unsupported` if its calendar doesn't take one this big
+    pub async fn update_todo(&self, todo: &CalendarTodo, ics: &str) -> Result<CalendarTodo> {
+        for cal in &self.calendars {
+            let cal = cal.read().await;
+            if todo.url.starts_with(&cal.url) {
+                cal.capabilities.check_size(&todo.url, ics)?;
+            }
+        }
+        let etag = self.put(&todo.url, ics, IF_MATCH, &todo.etag).await?;
+        CalendarTodo::from_ics(&todo.url, etag, ics)
+    }
 
-        cal_ref.borrow_mut().cache_past_todos = todos.into();
-        Ok(cal_ref.borrow().cache_past_todos.clone())
+    ///deletes `todo`, as long as nobody else changed it since it was fetched
+    pub async fn delete_todo(&self, todo: &CalendarTodo) -> Result<()> {
+        self.delete(&todo.url, &todo.etag).await
     }
 }
 
 impl CalendarTodo {
-    pub fn parse(el: &Element) -> anyhow::Result<CalendarTodo> {
-        let url = follow_tree(el, "href", NS_D)
-            .ok_or(anyhow!("Todo response did not contain href"))?;
-        let prop = follow_tree(el, "propstat.prop", NS_D)
-            .ok_or(anyhow!("Todo response did not contain prop"))?;
-        let etag = prop.get_child("getetag", NS_D)
-            .ok_or(anyhow!("Todo response did not contain getetag"))?;
-        let ics = prop.get_child("calendar-data", NS_C)
-            .ok_or(anyhow!("Todo response did not contain calendar-data"))?;
-
-        let mut vcal = VCalendar::parse(&ics.text())?;
+    pub fn parse(res: &DavResponse) -> Result<CalendarTodo> {
+        if res.href.is_empty() {
+            return Err(Error::invalid("", "todo response did not contain href"));
+        }
+        if let Some(e) = res.failure() {
+            return Err(e);
+        }
+        let etag = res.prop("getetag", NS_D)
+            .ok_or(Error::invalid(&res.href, "todo response did not contain getetag"))?;
+        let ics = res.prop("calendar-data", NS_C)
+            .ok_or(Error::invalid(&res.href, "todo response did not contain calendar-data"))?
+            .text();
+        CalendarTodo::from_ics(&res.href, etag.text(), &ics)
+    }
+
+    pub fn from_ics(href: &str, etag: String, ics: &str) -> Result<CalendarTodo> {
+        let mut vcal = VCalendar::parse(ics).map_err(|e| Error::ICal {
+            href: href.to_string(),
+            line: find_bad_line(ics),
+            message: format!("{e:#}"),
+        })?;
 
         //pop vtodo
         let vtodo = vcal
@@ -108,10 +224,240 @@ impl CalendarTodo {
             });
 
         Ok(CalendarTodo {
-            etag: etag.text(),
-            url: url.text(),
+            etag,
+            url: href.to_string(),
+            ics: ics.to_string(),
             vcal,
-            vtodo: vtodo.ok_or(anyhow!("Todo response did not contain VTODO"))?,
+            vtodo: vtodo.ok_or(Error::ICal {
+                href: href.to_string(),
+                line: None,
+                message: "no VTODO component".to_string(),
+            })?,
         })
     }
+
+    ///STATUS:CANCELLED is cancelled. STATUS:COMPLETED, PERCENT-COMPLETE:100
+    ///or a COMPLETED date is completed, clients don't agree on which to set
+    pub fn state(&self) -> TodoState {
+        let status = self.property("STATUS").map(|s| s.trim().to_ascii_uppercase());
+        let percent = self.property("PERCENT-COMPLETE").and_then(|p| p.trim().parse::<u32>().ok());
+        match status.as_deref() {
+            Some("CANCELLED") => TodoState::Cancelled,
+            Some("COMPLETED") => TodoState::Completed,
+            _ if percent == Some(100) || self.property("COMPLETED").is_some() => TodoState::Completed,
+            _ => TodoState::Open,
+        }
+    }
+
+    pub fn uid(&self) -> Option<String> {
+        self.property("UID").map(|uid| uid.trim().to_string())
+    }
+
+    ///UID of the todo this is a subtask of (RELATED-TO, RFC 5545 3.8.4.5)
+    pub fn parent(&self) -> Option<String> {
+        let root = self.root()?;
+        let parent = root.child("VTODO")?.props("RELATED-TO").find(|p| {
+            p.param("RELTYPE").is_none_or(|reltype| reltype.eq_ignore_ascii_case("PARENT"))
+        })?;
+        Some(parent.value.trim().to_string())
+    }
+
+    ///when it was completed or cancelled. cancelled todos rarely have a
+    ///COMPLETED, so for them it's when they were last changed. None while open
+    pub fn finished(&self) -> Option<DateTime<Utc>> {
+        if self.state() == TodoState::Open {
+            return None;
+        }
+        let root = self.root()?;
+        let vtodo = root.child("VTODO")?;
+        ["COMPLETED", "LAST-MODIFIED", "DTSTAMP"]
+            .into_iter()
+            .find_map(|name| DateValue::from_prop(vtodo.prop(name)?, &root))
+            .map(|date| date.instant())
+    }
+
+    ///the whole iCalendar object, VTIMEZONEs and all
+    pub fn root(&self) -> Option<Component> {
+        Component::parse(&self.ics)
+    }
+
+    ///value of a TEXT property like SUMMARY, unescaped (RFC 5545 3.3.11)
+    pub fn text(&self, name: &str) -> Option<String> {
+        self.property(name).map(|value| unescape(&value))
+    }
+
+    ///the iCalendar object marked completed `at`
+    pub fn completed(&self, at: DateTime<Utc>) -> String {
+        let stamp = format_utc(at);
+        let ics = set_property(&self.ics, "STATUS", Some("COMPLETED"));
+        let ics = set_property(&ics, "PERCENT-COMPLETE", Some("100"));
+        touched(&set_property(&ics, "COMPLETED", Some(&stamp)), at)
+    }
+
+    ///the iCalendar object marked cancelled `at`
+    pub fn cancelled(&self, at: DateTime<Utc>) -> String {
+        touched(&set_property(&self.ics, "STATUS", Some("CANCELLED")), at)
+    }
+
+    ///the iCalendar object open again, as of `at`
+    pub fn reopened(&self, at: DateTime<Utc>) -> String {
+        let ics = set_property(&self.ics, "STATUS", Some("NEEDS-ACTION"));
+        let ics = set_property(&ics, "PERCENT-COMPLETE", None);
+        touched(&set_property(&ics, "COMPLETED", None), at)
+    }
+
+    ///raw value of the VTODO's first `name` property, eg. `PERCENT-COMPLETE`.
+    ///properties of nested components like VALARM are skipped
+    pub fn property(&self, name: &str) -> Option<String> {
+        let mut in_todo = false;
+        let mut nested = 0;
+        for line in unfold(&self.ics) {
+            let Some((key, value)) = split_line(&line) else { continue };
+            let key = key.split(';').next().unwrap_or(key);
+            match (key.to_ascii_uppercase().as_str(), value.trim().to_ascii_uppercase().as_str()) {
+                ("BEGIN", "VTODO") => in_todo = true,
+                ("END", "VTODO") => return None,
+                ("BEGIN", _) if in_todo => nested += 1,
+                ("END", _) if in_todo => nested -= 1,
+                _ if in_todo && nested == 0 && key.eq_ignore_ascii_case(name) => return Some(value.to_string()),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+///`ics` with the first VTODO's own `name` properties replaced by one with
+///`value`, or just removed for None. everything else is left as it was,
+///folding and line endings included. `name` can have parameters, eg.
+///`DUE;VALUE=DATE`, which are written but not matched on
+pub fn set_property(ics: &str, name: &str, value: Option<&str>) -> String {
+    let bare = name.split(';').next().unwrap_or(name);
+    let newline = if ics.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut out = String::with_capacity(ics.len() + 64);
+    let mut seen = false;
+    let mut in_todo = false;
+    let mut nested = 0;
+    let mut skipping = false;
+    for line in ics.lines() {
+        //a folded line belongs to whatever the previous one was
+        if line.starts_with([' ', '\t']) {
+            if !skipping {
+                out.push_str(line);
+                out.push_str(newline);
+            }
+            continue;
+        }
+        skipping = false;
+        let (key, val) = split_line(line).unwrap_or((line, ""));
+        let key = key.split(';').next().unwrap_or(key);
+        match (key.to_ascii_uppercase().as_str(), val.trim().to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VTODO") if !seen => (seen, in_todo) = (true, true),
+            ("END", "VTODO") if in_todo && nested == 0 => {
+                if let Some(value) = value {
+                    out.push_str(&format!("{name}:{value}{newline}"));
+                }
+                in_todo = false;
+            }
+            ("BEGIN", _) if in_todo => nested += 1,
+            ("END", _) if in_todo => nested -= 1,
+            _ if in_todo && nested == 0 && key.eq_ignore_ascii_case(bare) => {
+                skipping = true;
+                continue;
+            }
+            _ => {}
+        }
+        out.push_str(line);
+        out.push_str(newline);
+    }
+    out
+}
+
+///a VCALENDAR with a new VTODO in it that has only what every todo needs.
+///the rest goes in with [`set_property`]
+pub fn new_todo(uid: &str, summary: &str, at: DateTime<Utc>) -> String {
+    let stamp = format_utc(at);
+    [
+        "BEGIN:VCALENDAR",
+        "VERSION:2.0",
+        "PRODID:-//reminder-rs//reminder-rs//EN",
+        "BEGIN:VTODO",
+        &format!("UID:{uid}"),
+        &format!("DTSTAMP:{stamp}"),
+        &format!("CREATED:{stamp}"),
+        &format!("LAST-MODIFIED:{stamp}"),
+        &format!("SUMMARY:{}", escape(summary)),
+        "STATUS:NEEDS-ACTION",
+        "END:VTODO",
+        "END:VCALENDAR",
+        "",
+    ]
+    .join("\r\n")
+}
+
+///a random looking UID in the usual 8-4-4-4-12 form, different every call
+pub fn new_uid() -> String {
+    static COUNT: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or_default();
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    let hash = format!("{:x}", Sha256::digest(format!("{nanos}:{}:{count}", std::process::id())));
+    format!("{}-{}-{}-{}-{}", &hash[..8], &hash[8..12], &hash[12..16], &hash[16..20], &hash[20..32])
+}
+
+///with DTSTAMP and LAST-MODIFIED set to `at`, for any change
+pub fn touched(ics: &str, at: DateTime<Utc>) -> String {
+    let stamp = format_utc(at);
+    let ics = set_property(ics, "DTSTAMP", Some(&stamp));
+    set_property(&ics, "LAST-MODIFIED", Some(&stamp))
+}
+
+///a UTC DATE-TIME, eg. `20240105T170000Z`
+pub fn format_utc(at: DateTime<Utc>) -> String {
+    at.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+///content lines with folding undone (RFC 5545 3.1)
+pub(crate) fn unfold(ics: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+///`NAME;PARAM="a:b":value` into name+params and value, ignoring colons in quotes
+pub(crate) fn split_line(line: &str) -> Option<(&str, &str)> {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ':' if !quoted => return Some((&line[..i], &line[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+///line number of the first content line that is obviously broken (no
+///name:value, or a BEGIN/END that doesn't match), to point at in errors
+fn find_bad_line(ics: &str) -> Option<usize> {
+    let mut stack = vec![];
+    for (i, line) in ics.lines().enumerate() {
+        //folded continuation of the previous line
+        if line.starts_with(' ') || line.starts_with('\t') || line.trim().is_empty() {
+            continue;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Some(i + 1);
+        };
+        match name.to_ascii_uppercase().as_str() {
+            "BEGIN" => stack.push(value.trim().to_ascii_uppercase()),
+            "END" if stack.pop().as_deref() != Some(&value.trim().to_ascii_uppercase()) => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
 }
@@ -0,0 +1,80 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+
+use super::todo::CalendarTodo;
+
+///which todos are subtasks of which, going by RELATED-TO (see
+///[`CalendarTodo::parent`]). todos are their index in what it was made from.
+///one whose parent isn't there is a root, and so is one todo of every cycle,
+///so walking it always ends
+pub struct TodoTree {
+    parents: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+}
+
+impl TodoTree {
+    pub fn new<'a>(todos: impl IntoIterator<Item = &'a CalendarTodo>) -> TodoTree {
+        let todos: Vec<&CalendarTodo> = todos.into_iter().collect();
+        let mut by_uid = HashMap::new();
+        for (i, todo) in todos.iter().enumerate() {
+            if let Some(uid) = todo.uid() {
+                by_uid.entry(uid).or_insert(i);
+            }
+        }
+        let mut parents: Vec<Option<usize>> =
+            todos.iter().map(|todo| todo.parent().and_then(|uid| by_uid.get(&uid).copied())).collect();
+
+        //a todo only has one parent, so going up from anywhere runs into one cycle at most.
+        //it's broken at its lowest index, to come out the same every time
+        for start in 0..parents.len() {
+            let mut path = vec![start];
+            let mut seen = HashSet::from([start]);
+            while let Some(parent) = parents[*path.last().unwrap_or(&start)] {
+                if !seen.insert(parent) {
+                    let cycle = path.iter().skip_while(|&&i| i != parent);
+                    if let Some(&first) = cycle.min() {
+                        parents[first] = None;
+                    }
+                    break;
+                }
+                path.push(parent);
+            }
+        }
+
+        let mut children = vec![vec![]; parents.len()];
+        for (i, parent) in parents.iter().enumerate() {
+            if let Some(parent) = parent {
+                children[*parent].push(i);
+            }
+        }
+        TodoTree { parents, children }
+    }
+
+    pub fn parent(&self, todo: usize) -> Option<usize> {
+        self.parents.get(todo).copied().flatten()
+    }
+
+    pub fn children(&self, todo: usize) -> &[usize] {
+        self.children.get(todo).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    ///the subtasks of `todo` out of `todos`, what the tree was made from
+    pub fn subtasks<'a, T: Borrow<CalendarTodo>>(&self, todos: &'a [T], todo: usize) -> Vec<&'a CalendarTodo> {
+        self.children(todo).iter().map(|&i| todos[i].borrow()).collect()
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.parents.len()).filter(|&i| self.parents[i].is_none())
+    }
+
+    ///everything under `todo`, each followed by its own subtasks
+    pub fn descendants(&self, todo: usize) -> Vec<usize> {
+        let mut found = vec![];
+        let mut stack: Vec<usize> = self.children(todo).iter().rev().copied().collect();
+        while let Some(next) = stack.pop() {
+            found.push(next);
+            stack.extend(self.children(next).iter().rev());
+        }
+        found
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use reminder_rs::caldav::calendar::parse_color;
+use reminder_rs::caldav::CalendarProps;
+use reminder_rs::store::TodoStore;
+
+use crate::args::*;
+use crate::exit::CliError;
+use crate::output::{CalendarJson, Output};
+use crate::{find_calendar, prompt};
+
+///prints the calendar it made or changed, the way `calendars` would
+pub async fn manage(store: &dyn TodoStore, action: &CalendarsAction, output: &Output) -> anyhow::Result<()> {
+    match action {
+        CalendarsAction::Create(CalendarCreateCommand { name, color, description, order }) => {
+            let props = CalendarProps::builder()
+                .name(name.clone())
+                .maybe_color(color.as_deref().map(color_arg).transpose()?)
+                .maybe_description(description.clone())
+                .maybe_order(*order)
+                .build();
+            let cal = store.create_calendar(&calendar_id(name), &props).await?;
+            match output.is_json() {
+                true => output.json_object(&CalendarJson::new(&cal))?,
+                false => println!("Created {}", output.calendar_name(&cal)),
+            }
+        }
+        CalendarsAction::Rename(CalendarRenameCommand { calendar, name }) => {
+            let props = CalendarProps::builder().name(name.clone()).build();
+            update(store, calendar, &props, output).await?;
+        }
+        CalendarsAction::Color(CalendarColorCommand { calendar, color }) => {
+            let props = CalendarProps::builder().color(color_arg(color)?).build();
+            update(store, calendar, &props, output).await?;
+        }
+        CalendarsAction::Describe(CalendarDescribeCommand { calendar, description }) => {
+            let props = CalendarProps::builder().description(description.clone()).build();
+            update(store, calendar, &props, output).await?;
+        }
+        CalendarsAction::Order(CalendarOrderCommand { calendar, order }) => {
+            let props = CalendarProps::builder().order(*order).build();
+            update(store, calendar, &props, output).await?;
+        }
+        CalendarsAction::Delete(CalendarDeleteCommand { calendar, yes }) => {
+            let cal_ref = find_calendar(store, calendar).await?;
+            let cal = cal_ref.read().await;
+            let question = format!("Delete {} and every reminder in it?", cal.name);
+            if !yes && !prompt::confirm(&question) {
+                return Err(CliError::Declined.into());
+            }
+            store.delete_calendar(&cal).await?;
+            match output.is_json() {
+                true => output.json_object(&CalendarJson::new(&cal))?,
+                false => println!("Deleted {}", cal.name),
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn update(store: &dyn TodoStore, name: &str, props: &CalendarProps, output: &Output) -> anyhow::Result<()> {
+    let cal = find_calendar(store, name).await?;
+    store.update_calendar(&cal, props).await?;
+    let cal = cal.read().await;
+    match output.is_json() {
+        true => output.json_object(&CalendarJson::new(&cal))?,
+        false => println!("Updated {}", output.calendar_name(&cal)),
+    }
+    Ok(())
+}
+
+fn color_arg(color: &str) -> anyhow::Result<String> {
+    let hex = format!("#{}", color.trim_start_matches('#'));
+    Ok(parse_color(&hex).ok_or(CliError::InvalidColor(color.to_string()))?)
+}
+
+///a url and file name friendly version of `name`, eg. `Work stuff` => `work-stuff`
+pub fn calendar_id(name: &str) -> String {
+    let id = name
+        .to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if !id.is_empty() {
+        return id;
+    }
+    //nothing ascii in the name at all
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    format!("calendar-{secs}")
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+use std::process::Stdio;
+
+use anyhow::Context;
+use chrono::{DateTime, Duration, Local, Utc};
+use reminder_rs::caldav::alarm::{self, Firing};
+use reminder_rs::caldav::{Calendar, CalendarTodo, TodoDetails};
+use reminder_rs::store::{self, TodoStore};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::process::Command;
+
+use crate::actions::short_id;
+use crate::args::DaemonCommand;
+use crate::state_dir;
+
+const DEFAULT_COMMAND: &str = "notify-send --app-name=reminder-rs {summary} {description}";
+
+///which alarms went off, kept between runs so a restart doesn't fire them again
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    ///`<todo uid>/<alarm key>/<time>` to when it was due to go off
+    fired: BTreeMap<String, DateTime<Utc>>,
+}
+
+impl State {
+    async fn load(path: &Path) -> anyhow::Result<State> {
+        match fs::read_to_string(path).await {
+            Ok(json) => serde_json::from_str(&json).with_context(|| format!("can't read {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(State::default()),
+            Err(e) => Err(e).with_context(|| format!("can't read {}", path.display())),
+        }
+    }
+
+    ///a rename, so it's never half written
+    async fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?).await?;
+        fs::rename(&tmp, path).await.with_context(|| format!("can't write {}", path.display()))
+    }
+}
+
+///checks for alarms every `--interval` until interrupted, or once with `--once`.
+///errors while checking are reported and tried again next time
+pub async fn run(store: &dyn TodoStore, command: &DaemonCommand, jobs: usize) -> anyhow::Result<()> {
+    let template = command
+        .command
+        .clone()
+        .or(env::var("REMINDER_ALARM_COMMAND").ok())
+        .unwrap_or(DEFAULT_COMMAND.to_string());
+    let path = state_dir()?.join("alarms.json");
+    let mut state = State::load(&path).await?;
+    loop {
+        let checked = check(store, command, &template, &mut state, &path, jobs).await;
+        if command.once {
+            return checked;
+        }
+        if let Err(e) = checked {
+            eprintln!("Error: {e:#}");
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(std::time::Duration::from_secs(command.interval)) => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+///fires what went off since `--catch-up` minutes ago and hasn't been fired
+///yet. the current todos come through the store's cache, so a calendar
+///that didn't change isn't fetched again
+async fn check(
+    store: &dyn TodoStore,
+    command: &DaemonCommand,
+    template: &str,
+    state: &mut State,
+    path: &Path,
+    jobs: usize,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let since = now - Duration::minutes(command.catch_up as i64);
+    let cals = store::todo_calendars(store).await;
+    let results = store::current_todos_many(store, &cals, jobs).await;
+    for (cal, todos) in cals.iter().zip(results) {
+        let cal = cal.read().await;
+        //one calendar that can't be fetched doesn't hold up the others
+        let todos = match todos {
+            Ok(todos) => todos,
+            Err(e) => {
+                eprintln!("Couldn't check the alarms in {}: {e:#}", cal.name);
+                continue;
+            }
+        };
+        for todo in &todos.items {
+            let uid = todo.uid().unwrap_or(todo.url.clone());
+            let mut done = vec![];
+            for firing in alarm::firings(todo) {
+                if firing.at > now || firing.at <= since {
+                    continue;
+                }
+                let key = format!("{uid}/{}/{}", firing.key, firing.at.to_rfc3339());
+                if state.fired.contains_key(&key) {
+                    continue;
+                }
+                //saved first: an alarm that goes off twice is worse than one that didn't
+                state.fired.insert(key, firing.at);
+                state.save(path).await?;
+                fire(template, &cal, todo, &firing).await;
+                if firing.last {
+                    done.push(firing.alarm);
+                }
+            }
+            if !done.is_empty() && !command.no_acknowledge {
+                let ics = done.into_iter().fold(todo.ics.clone(), |ics, alarm| alarm::acknowledged(&ics, alarm, now));
+                if let Err(e) = store.update(todo, &ics).await {
+                    eprintln!("Couldn't mark the alarm of {} as seen: {e:#}", short_id(todo));
+                }
+            }
+        }
+    }
+    state.fired.retain(|_, at| *at > since);
+    state.save(path).await
+}
+
+///runs the command for `firing`, saying so on stdout
+async fn fire(template: &str, cal: &Calendar, todo: &CalendarTodo, firing: &Firing) {
+    let details = TodoDetails::new(todo, &[]);
+    let summary = details.summary.clone().unwrap_or_default();
+    println!("Alarm for {}: {summary}", short_id(todo));
+    let values = [
+        ("summary", summary),
+        ("description", firing.description.clone().or(details.description).unwrap_or_default()),
+        ("due", details.due.map(|due| due.local()).unwrap_or_default()),
+        ("time", firing.at.with_timezone(&Local).format("%H:%M").to_string()),
+        ("calendar", cal.name.clone()),
+        ("location", details.location.unwrap_or_default()),
+        ("id", short_id(todo)),
+        ("uid", details.uid.unwrap_or_default()),
+    ];
+    let args = command_line(template, &values);
+    let Some((program, args)) = args.split_first() else {
+        eprintln!("The alarm command is empty");
+        return;
+    };
+    match Command::new(program).args(args).stdin(Stdio::null()).status().await {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("{program} failed with {status}"),
+        Err(e) => eprintln!("Couldn't run {program}: {e}"),
+    }
+}
+
+///`template` split into arguments the way a shell would with quotes and
+///backslashes, then each `{name}` in them filled in. filling in after
+///splitting keeps a summary in one argument whatever is in it, and in one
+///pass keeps a `{uid}` in the summary as it is
+fn command_line(template: &str, values: &[(&str, String)]) -> Vec<String> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                if let Some(next) = chars.next() {
+                    word.get_or_insert_default().push(next);
+                }
+            }
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (_, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    words
+        .into_iter()
+        .map(|word| fill(&word, values))
+        .collect()
+}
+
+fn fill(word: &str, values: &[(&str, String)]) -> String {
+    let mut filled = String::new();
+    let mut rest = word;
+    while let Some(open) = rest.find('{') {
+        filled.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let value = rest.find('}').and_then(|close| {
+            let (_, value) = values.iter().find(|(name, _)| *name == &rest[1..close])?;
+            Some((close, value))
+        });
+        match value {
+            Some((close, value)) => {
+                filled.push_str(value);
+                rest = &rest[close + 1..];
+            }
+            //not a placeholder, so the brace is just a brace
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
@@ -0,0 +1,81 @@
+use std::process::ExitCode;
+
+use reminder_rs::caldav::Error;
+use thiserror::Error;
+
+///errors from the CLI itself rather than the server
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("Missing environment variable {0}")]
+    MissingConfig(&'static str),
+    #[error("No calendar named {0}")]
+    NoSuchCalendar(String),
+    #[error("Invalid color {0}, expected #RRGGBB")]
+    InvalidColor(String),
+    #[error("Cancelled")]
+    Declined,
+    #[error("No reminder with id {0}")]
+    NoSuchReminder(String),
+    #[error("{count} reminders have ids starting with {id}, give more of it")]
+    AmbiguousReminder { id: String, count: usize },
+    #[error("No calendar holds reminders, make one with `calendars create`")]
+    NoCalendars,
+    #[error("Subtasks go in the same calendar as their parent, which is {0}")]
+    ParentElsewhere(String),
+    #[error("A relative alarm needs a {0} date, set one with --{0} or give the alarm a time")]
+    NoAlarmAnchor(&'static str),
+    #[error("{0}")]
+    Usage(String),
+    #[error("{0} changed again since, undo --force puts it back anyway")]
+    ChangedSince(String),
+    #[error("{0}")]
+    Unsupported(String),
+}
+
+///exit codes scripts can rely on. 2 is clap's usage error
+pub const GENERIC: u8 = 1;
+pub const USAGE: u8 = 2;
+pub const CONFIG: u8 = 3;
+pub const AUTH: u8 = 4;
+pub const NOT_FOUND: u8 = 5;
+pub const FORBIDDEN: u8 = 6;
+pub const CONFLICT: u8 = 7;
+pub const NETWORK: u8 = 8;
+pub const SERVER: u8 = 9;
+pub const INVALID_DATA: u8 = 10;
+pub const UNSUPPORTED: u8 = 11;
+
+pub fn exit_code(err: &anyhow::Error) -> ExitCode {
+    let code = if let Some(err) = err.downcast_ref::<Error>() {
+        match err {
+            Error::Auth { .. } => AUTH,
+            Error::NotFound { .. } => NOT_FOUND,
+            Error::Forbidden { .. } => FORBIDDEN,
+            Error::Conflict { .. } => CONFLICT,
+            Error::Transport { .. } | Error::Timeout { .. } | Error::Dns { .. } => NETWORK,
+            Error::Status { .. } | Error::TooManyRedirects { .. } => SERVER,
+            Error::Xml { .. } | Error::InvalidResponse { .. } | Error::ICal { .. } => INVALID_DATA,
+            Error::Unsupported { .. } => UNSUPPORTED,
+            Error::Url { .. } | Error::Discovery { .. } => CONFIG,
+            Error::Io { .. } => GENERIC,
+        }
+    } else if let Some(err) = err.downcast_ref::<CliError>() {
+        match err {
+            CliError::MissingConfig(_) => CONFIG,
+            CliError::NoSuchCalendar(_) => NOT_FOUND,
+            CliError::InvalidColor(_) => USAGE,
+            CliError::Declined => GENERIC,
+            CliError::NoSuchReminder(_) => NOT_FOUND,
+            CliError::AmbiguousReminder { .. } => USAGE,
+            CliError::NoCalendars => NOT_FOUND,
+            CliError::ParentElsewhere(_) => USAGE,
+            CliError::NoAlarmAnchor(_) => USAGE,
+            CliError::Usage(_) => USAGE,
+            CliError::ChangedSince(_) => CONFLICT,
+            CliError::Unsupported(_) => UNSUPPORTED,
+        }
+    } else {
+        GENERIC
+    };
+    ExitCode::from(code)
+}
@@ -0,0 +1,287 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Local, NaiveDate};
+use reminder_rs::caldav::agenda::{has_started, is_overdue};
+use reminder_rs::caldav::datetime::DateValue;
+use reminder_rs::caldav::details::priority_label;
+use reminder_rs::caldav::TodoState;
+
+use crate::output::TodoRow;
+use crate::when;
+
+const KEYS: &str = "cat, cal, status, priority, due, start, summary, location and id";
+
+///which reminders `--where` is about, eg. `cat:groceries and status:completed`.
+///terms next to each other all have to match, as with `and`. `or`, `not`
+///and brackets work as usual
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    ///in the summary, ignoring case. what a word without a key is
+    Summary(String),
+    Location(String),
+    Category(String),
+    Calendar(String),
+    ///`open`, `done` or `cancelled`
+    State(TodoState),
+    ///STATUS as written, eg. `IN-PROCESS`
+    Status(String),
+    ///`high`, `medium`, `low` or `none`
+    PriorityLabel(&'static str),
+    Priority(Op, u8),
+    Due(DateTest),
+    Start(DateTest),
+    ///the start of the UID, like the ids `list` shows
+    Id(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Lt,
+    Le,
+    Eq,
+    Ge,
+    Gt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateTest {
+    None,
+    Any,
+    ///overdue for `due`, started for `start`
+    Past,
+    ///on the day it falls on locally
+    Day(Op, NaiveDate),
+}
+
+impl Op {
+    fn test(&self, ordering: Ordering) -> bool {
+        match self {
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Eq => ordering.is_eq(),
+            Op::Ge => ordering.is_ge(),
+            Op::Gt => ordering.is_gt(),
+        }
+    }
+
+    ///a leading `<`, `<=`, `=`, `>=` or `>`, and the rest
+    fn split(value: &str) -> (Op, &str) {
+        for (prefix, op) in [("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt), ("=", Op::Eq)] {
+            if let Some(rest) = value.strip_prefix(prefix) {
+                return (op, rest);
+            }
+        }
+        (Op::Eq, value)
+    }
+}
+
+///a word of the filter. quoted ones are never `and`, `or` or `not`
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+impl Filter {
+    ///for clap
+    pub fn parse(text: &str) -> Result<Filter, String> {
+        Filter::parse_at(text, Local::now())
+    }
+
+    ///[`parse`](Self::parse) with dates like `today` going by `now`
+    pub fn parse_at(text: &str, now: DateTime<Local>) -> Result<Filter, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0, now };
+        let filter = parser.or()?;
+        match tokens.get(parser.pos) {
+            None => Ok(filter),
+            Some(token) => Err(format!("unexpected {} in the filter", token.text)),
+        }
+    }
+
+    pub fn matches(&self, row: &TodoRow, now: DateTime<Local>) -> bool {
+        match self {
+            Filter::And(a, b) => a.matches(row, now) && b.matches(row, now),
+            Filter::Or(a, b) => a.matches(row, now) || b.matches(row, now),
+            Filter::Not(a) => !a.matches(row, now),
+            Filter::Term(term) => term.matches(row, now),
+        }
+    }
+}
+
+impl Term {
+    fn matches(&self, row: &TodoRow, now: DateTime<Local>) -> bool {
+        let details = &row.details;
+        let contains = |text: &Option<String>, part: &str| text.as_ref().is_some_and(|t| t.to_lowercase().contains(part));
+        match self {
+            Term::Summary(part) => contains(&details.summary, part),
+            Term::Location(part) => contains(&details.location, part),
+            Term::Category(name) => details.categories.iter().any(|c| c.eq_ignore_ascii_case(name)),
+            Term::Calendar(name) => row.calendar.eq_ignore_ascii_case(name),
+            Term::State(state) => details.state == *state,
+            Term::Status(status) => details.status.as_ref() == Some(status),
+            Term::PriorityLabel(label) => priority_label(details.priority.unwrap_or(0)) == *label,
+            Term::Priority(op, n) => details.priority.filter(|p| *p > 0).is_some_and(|p| op.test(p.cmp(n))),
+            Term::Due(test) => test.matches(details.due.as_ref(), |due| is_overdue(due, now)),
+            Term::Start(test) => test.matches(details.start.as_ref(), |start| has_started(start, now)),
+            Term::Id(id) => details.uid.as_ref().is_some_and(|uid| uid.starts_with(id.as_str())),
+        }
+    }
+
+    ///`key:value`, or a word to look for in the summary
+    fn parse(token: &Token, now: DateTime<Local>) -> Result<Term, String> {
+        let Some((key, value)) = token.text.split_once(':').filter(|_| !token.quoted) else {
+            return Ok(Term::Summary(token.text.to_lowercase()));
+        };
+        let invalid = |expected: &str| format!("invalid {key}:{value}, expected {expected}");
+        Ok(match key.to_ascii_lowercase().as_str() {
+            "summary" => Term::Summary(value.to_lowercase()),
+            "loc" | "location" => Term::Location(value.to_lowercase()),
+            "cat" | "category" => Term::Category(value.to_string()),
+            "cal" | "calendar" => Term::Calendar(value.to_string()),
+            "id" => Term::Id(value.to_string()),
+            "status" => match value.to_ascii_lowercase().as_str() {
+                "open" => Term::State(TodoState::Open),
+                "done" | "completed" => Term::State(TodoState::Completed),
+                "cancelled" | "canceled" => Term::State(TodoState::Cancelled),
+                "" => return Err(invalid("open, done, cancelled or a STATUS like in-process")),
+                status => Term::Status(status.to_ascii_uppercase()),
+            },
+            "priority" => match value.to_ascii_lowercase().as_str() {
+                "high" => Term::PriorityLabel("high"),
+                "medium" => Term::PriorityLabel("medium"),
+                "low" => Term::PriorityLabel("low"),
+                "none" => Term::PriorityLabel("none"),
+                _ => {
+                    let (op, n) = Op::split(value);
+                    Term::Priority(op, n.parse().map_err(|_| invalid("high, medium, low, none or 1 to 9, eg. <=4"))?)
+                }
+            },
+            "due" => Term::Due(DateTest::parse(value, now).map_err(|_| invalid("none, any, overdue or a date like <today"))?),
+            "start" => Term::Start(DateTest::parse(value, now).map_err(|_| invalid("none, any, started or a date like >=monday"))?),
+            _ => return Err(format!("unknown filter key {key}, expected {KEYS}")),
+        })
+    }
+}
+
+impl DateTest {
+    fn parse(value: &str, now: DateTime<Local>) -> Result<DateTest, String> {
+        Ok(match value.to_ascii_lowercase().as_str() {
+            "none" => DateTest::None,
+            "any" => DateTest::Any,
+            "overdue" | "started" | "past" => DateTest::Past,
+            _ => {
+                let (op, date) = Op::split(value);
+                DateTest::Day(op, when::parse_at(date, now)?.local_date())
+            }
+        })
+    }
+
+    fn matches(&self, date: Option<&DateValue>, past: impl Fn(&DateValue) -> bool) -> bool {
+        match (self, date) {
+            (DateTest::None, date) => date.is_none(),
+            (DateTest::Any, date) => date.is_some(),
+            (_, None) => false,
+            (DateTest::Past, Some(date)) => past(date),
+            (DateTest::Day(op, day), Some(date)) => op.test(date.local_date().cmp(day)),
+        }
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    now: DateTime<Local>,
+}
+
+impl Parser<'_> {
+    fn keyword(&self, word: &str) -> bool {
+        self.tokens.get(self.pos).is_some_and(|t| !t.quoted && t.text.eq_ignore_ascii_case(word))
+    }
+
+    fn or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.and()?;
+        while self.keyword("or") {
+            self.pos += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.not()?;
+        loop {
+            if self.keyword("and") {
+                self.pos += 1;
+            } else if self.pos >= self.tokens.len() || self.keyword("or") || self.keyword(")") {
+                return Ok(filter);
+            }
+            filter = Filter::And(Box::new(filter), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Filter, String> {
+        if self.keyword("not") {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.not()?)));
+        }
+        if self.keyword("(") {
+            self.pos += 1;
+            let filter = self.or()?;
+            if !self.keyword(")") {
+                return Err("missing ) in the filter".to_string());
+            }
+            self.pos += 1;
+            return Ok(filter);
+        }
+        match self.tokens.get(self.pos) {
+            None => Err("the filter ends too early".to_string()),
+            Some(token) if ["and", "or", ")"].iter().any(|word| self.keyword(word)) => {
+                Err(format!("unexpected {} in the filter", token.text))
+            }
+            Some(token) => {
+                self.pos += 1;
+                Term::parse(token, self.now).map(Filter::Term)
+            }
+        }
+    }
+}
+
+///words split on whitespace, with brackets on their own and quotes keeping
+///spaces in
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut word: Option<Token> = None;
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            //only a word that starts with one is quoted, `cat:"big shop"` is still a key and value
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert(Token { text: String::new(), quoted: true });
+            }
+            _ if quoted => word.get_or_insert(Token { text: String::new(), quoted: true }).text.push(c),
+            '(' | ')' => {
+                tokens.extend(word.take());
+                tokens.push(Token { text: c.to_string(), quoted: false });
+            }
+            c if c.is_whitespace() => tokens.extend(word.take()),
+            c => word.get_or_insert(Token { text: String::new(), quoted: false }).text.push(c),
+        }
+    }
+    if quoted {
+        return Err("missing \" in the filter".to_string());
+    }
+    tokens.extend(word);
+    match tokens.is_empty() {
+        true => Err("the filter is empty".to_string()),
+        false => Ok(tokens),
+    }
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use reminder_rs::caldav::{TodoState, TodoTree};
+use reminder_rs::store::{self, Query, TodoStore};
+use serde::Serialize;
+
+use crate::args::HistoryCommand;
+use crate::exit::CliError;
+use crate::find_calendar;
+use crate::output::{Column, Group, Output, TodoRow};
+
+///what `history` shows unless given --columns
+pub const COLUMNS: [Column; 5] = [Column::Id, Column::Calendar, Column::Summary, Column::Status, Column::Completed];
+
+///how it went over one day or week
+#[derive(Serialize)]
+struct Stats {
+    ///`day` or `week`
+    period: &'static str,
+    ///the day, or the Monday the week starts on
+    start: NaiveDate,
+    done: usize,
+    cancelled: usize,
+    ///done out of everything finished, from 0 to 1
+    rate: f64,
+}
+
+///completed and cancelled todos, oldest first under a heading per day, or
+///with --stats how many of them each day and week were done
+pub async fn show(store: &dyn TodoStore, command: &HistoryCommand, output: &Output, jobs: usize) -> anyhow::Result<()> {
+    let cals = match &command.calendar {
+        Some(name) => vec![find_calendar(store, name).await?],
+        None => store::todo_calendars(store).await,
+    };
+    //--until is the last day shown, so the range ends when the day after starts
+    let since = command.since.map(start_of).transpose()?;
+    let until = match command.until {
+        Some(day) => Some(start_of(day.succ_opt().ok_or(CliError::Usage(format!("--until {day} is too late")))?)?),
+        None => None,
+    };
+    let results = store::todos_many(store, &cals, Query::History { since, until }, jobs).await;
+
+    let mut finished: Vec<(Option<DateTime<Utc>>, TodoRow)> = vec![];
+    for (cal, todos) in cals.iter().zip(results) {
+        let todos = todos?;
+        for e in &todos.errors {
+            eprintln!("Skipped {}: {:#}", e.href, e.error);
+        }
+        let cal = cal.read().await;
+        let tree = TodoTree::new(&todos.items);
+        for (i, todo) in todos.items.iter().enumerate() {
+            finished.push((todo.finished(), TodoRow::new(&cal, todo, &tree.subtasks(&todos.items, i))));
+        }
+    }
+    finished.sort_by_key(|(at, _)| (at.is_none(), *at));
+
+    match command.stats {
+        true => print_stats(&finished, output),
+        false => {
+            let mut groups: Vec<Group> = vec![];
+            for (at, row) in finished {
+                let title = match at {
+                    Some(at) => at.with_timezone(&Local).format("%A %Y-%m-%d").to_string(),
+                    None => "No date".to_string(),
+                };
+                match groups.last_mut() {
+                    Some(group) if group.title.as_ref() == Some(&title) => group.rows.push(row),
+                    _ => groups.push(Group { title: Some(title), rows: vec![row] }),
+                }
+            }
+            output.groups(&groups)
+        }
+    }
+}
+
+fn print_stats(finished: &[(Option<DateTime<Utc>>, TodoRow)], output: &Output) -> anyhow::Result<()> {
+    let mut stats: Vec<Stats> = vec![];
+    for period in ["day", "week"] {
+        for (at, row) in finished {
+            let Some(at) = at else { continue };
+            let day = at.with_timezone(&Local).date_naive();
+            let start = match period {
+                "day" => day,
+                _ => day - Duration::days(day.weekday().num_days_from_monday() as i64),
+            };
+            let i = match stats.iter().position(|s| s.period == period && s.start == start) {
+                Some(i) => i,
+                None => {
+                    stats.push(Stats { period, start, done: 0, cancelled: 0, rate: 0.0 });
+                    stats.len() - 1
+                }
+            };
+            match row.details.state {
+                TodoState::Cancelled => stats[i].cancelled += 1,
+                _ => stats[i].done += 1,
+            }
+        }
+    }
+    for s in &mut stats {
+        s.rate = s.done as f64 / (s.done + s.cancelled) as f64;
+    }
+
+    if output.is_json() {
+        return output.json(&stats);
+    }
+    let rows: Vec<Vec<String>> = stats
+        .iter()
+        .map(|s| {
+            vec![
+                s.period.to_string(),
+                s.start.to_string(),
+                s.done.to_string(),
+                s.cancelled.to_string(),
+                format!("{:.0}%", s.rate * 100.0),
+            ]
+        })
+        .collect();
+    output.rows(&["PERIOD", "START", "DONE", "CANCELLED", "RATE"], &rows);
+    Ok(())
+}
+
+///when `day` starts here: midnight, or the first minute after it that
+///exists when the clocks go forward at midnight
+fn start_of(day: NaiveDate) -> Result<DateTime<Utc>, CliError> {
+    (0..24 * 60)
+        .map(|minutes| day.and_time(NaiveTime::MIN) + Duration::minutes(minutes))
+        .find_map(|time| Local.from_local_datetime(&time).earliest())
+        .map(|t| t.with_timezone(&Utc))
+        .ok_or(CliError::Usage(format!("{day} is out of range")))
+}
@@ -0,0 +1,157 @@
+use reminder_rs::caldav::datetime::DateValue;
+use reminder_rs::caldav::details::priority_label;
+use reminder_rs::caldav::TodoDetails;
+use reminder_rs::store::TodoStore;
+
+use crate::actions::{self, Found};
+use crate::args::InfoCommand;
+use crate::output::{state_name, Output, TodoRow};
+
+///where the description wraps
+const WIDTH: usize = 72;
+
+pub async fn show(store: &dyn TodoStore, command: &InfoCommand, output: &Output, jobs: usize) -> anyhow::Result<()> {
+    let resolved = actions::resolve(store, &command.reminders, jobs).await?;
+    let total = resolved.len();
+    let mut found = vec![];
+    let mut failed = 0;
+    let mut last_error = None;
+    for (id, result) in command.reminders.iter().zip(resolved) {
+        match result {
+            Ok(result) => found.push(result),
+            Err(e) => {
+                eprintln!("{id}: {e:#}");
+                failed += 1;
+                last_error = Some(e);
+            }
+        }
+    }
+
+    if command.json || output.is_json() {
+        let mut rows = vec![];
+        for found in &found {
+            rows.push(TodoRow::new(&*found.cal.read().await, found.todo(), &found.subtasks()));
+        }
+        output.json(&rows)?;
+    } else {
+        for (i, found) in found.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            match command.raw {
+                true => print!("{}", found.todo().ics),
+                false => print_details(found).await,
+            }
+        }
+    }
+    actions::failures(failed, total, last_error)
+}
+
+async fn print_details(found: &Found) {
+    let details = TodoDetails::new(found.todo(), &found.subtasks());
+    let field = |label: &str, value: &str| println!("  {label:<13}{value}");
+    let lines = |label: &str, values: &[String]| {
+        for (i, value) in values.iter().enumerate() {
+            field(if i == 0 { label } else { "" }, value);
+        }
+    };
+
+    println!("{}", details.summary.as_deref().unwrap_or("(no summary)"));
+    field("Id:", details.uid.as_deref().unwrap_or_default());
+    field("Calendar:", &found.cal.read().await.name);
+
+    let mut status = state_name(details.state).to_string();
+    if let Some(written) = &details.status {
+        status = format!("{status} ({written})");
+    }
+    if let Some(percent) = details.percent_complete {
+        status = format!("{status}, {percent}% done");
+    }
+    field("Status:", &status);
+    if let Some(priority) = details.priority.filter(|p| *p > 0) {
+        field("Priority:", &format!("{priority} ({})", priority_label(priority)));
+    }
+
+    for (label, date) in [("Start:", &details.start), ("Due:", &details.due), ("Completed:", &details.completed)] {
+        if let Some(date) = date {
+            field(label, &when(date));
+        }
+    }
+    if let Some(recurrence) = &details.recurrence {
+        field("Repeats:", &recurrence.text);
+    }
+    if !details.categories.is_empty() {
+        field("Categories:", &details.categories.join(", "));
+    }
+    if let Some(location) = &details.location {
+        field("Location:", location);
+    }
+    if let Some(geo) = &details.geo {
+        field("Geo:", &format!("{:.5}, {:.5}", geo.lat, geo.lon));
+    }
+    if let Some(url) = &details.url {
+        field("URL:", url);
+    }
+
+    let alarms: Vec<String> = details
+        .alarms
+        .iter()
+        .map(|alarm| {
+            let mut text = format!("{} ({})", alarm.when, alarm.action.to_lowercase());
+            if let Some(description) = &alarm.description {
+                text = format!("{text}: {description}");
+            }
+            text
+        })
+        .collect();
+    lines("Alarms:", &alarms);
+
+    let describe = |uid: &String| match found.siblings().iter().find(|t| t.uid().as_ref() == Some(uid)) {
+        Some(todo) => format!("{}  {}", actions::short_id(todo), actions::summary(todo)),
+        None => uid.clone(),
+    };
+    if let Some(parent) = &details.parent {
+        field("Parent:", &describe(parent));
+    }
+    let children: Vec<String> = details.children.iter().map(describe).collect();
+    lines("Subtasks:", &children);
+    lines("Attachments:", &details.attachments);
+    field("Href:", &details.href);
+    field("ETag:", &details.etag);
+
+    if let Some(description) = &details.description {
+        println!();
+        for line in wrap(description, WIDTH) {
+            println!("  {line}");
+        }
+    }
+}
+
+///local time, and the time as written if that's different
+fn when(date: &DateValue) -> String {
+    let (local, original) = (date.local(), date.original());
+    match local == original {
+        true => local,
+        false => format!("{local} ({original})"),
+    }
+}
+
+///breaks `text` into lines of at most `width` characters at spaces,
+///keeping the line breaks it already has. longer words get a line each
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        for word in paragraph.split_whitespace() {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        lines.push(line);
+    }
+    lines
+}
@@ -0,0 +1,397 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use chrono::{DateTime, Local, Utc};
+use reminder_rs::caldav::{Calendar, CalendarProps, CalendarRef, CalendarTodo, Partial, ServerInfo};
+use reminder_rs::store::{FileLock, Query, StoreFuture, TodoStore};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::RwLock;
+
+use crate::actions::failures;
+use crate::args::{LogCommand, UndoCommand};
+use crate::exit::CliError;
+use crate::output::Output;
+use crate::state_dir;
+
+///how many operations are kept, oldest going first
+const KEEP: usize = 100;
+
+///one command that changed something
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Operation {
+    at: DateTime<Utc>,
+    ///what it was run with, eg. `delete 3f2a1b9c`
+    command: String,
+    ///in the order they were made
+    changes: Vec<Change>,
+    ///when `undo` put it back
+    #[serde(default)]
+    undone: Option<DateTime<Utc>>,
+}
+
+///one todo created, changed or deleted
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Change {
+    ///the url of its calendar
+    calendar: String,
+    url: String,
+    uid: String,
+    summary: String,
+    ///None if this created it
+    before: Option<Version>,
+    ///None if this deleted it
+    after: Option<Version>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Version {
+    etag: String,
+    ics: String,
+}
+
+impl Change {
+    ///`created`, `changed` or `deleted`
+    fn kind(&self) -> &'static str {
+        match (&self.before, &self.after) {
+            (None, _) => "created",
+            (Some(_), Some(_)) => "changed",
+            (Some(_), None) => "deleted",
+        }
+    }
+
+    fn new(calendar: Option<String>, todo: &CalendarTodo, before: Option<&CalendarTodo>, after: Option<&CalendarTodo>) -> Change {
+        let version = |todo: &CalendarTodo| Version { etag: todo.etag.clone(), ics: todo.ics.clone() };
+        Change {
+            calendar: calendar.unwrap_or_default(),
+            url: todo.url.clone(),
+            uid: todo.uid().unwrap_or_default(),
+            summary: todo.text("SUMMARY").unwrap_or_default(),
+            before: before.map(version),
+            after: after.map(version),
+        }
+    }
+}
+
+///the operations `undo` can take back, oldest first
+#[derive(Default, Serialize, Deserialize)]
+struct Journal {
+    operations: Vec<Operation>,
+}
+
+impl Journal {
+    async fn load(path: &Path) -> anyhow::Result<Journal> {
+        match fs::read_to_string(path).await {
+            Ok(json) => serde_json::from_str(&json).with_context(|| format!("can't read {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Journal::default()),
+            Err(e) => Err(e).with_context(|| format!("can't read {}", path.display())),
+        }
+    }
+
+    ///a rename, so it's never half written
+    async fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string(self)?).await?;
+        fs::rename(&tmp, path).await.with_context(|| format!("can't write {}", path.display()))
+    }
+}
+
+fn journal_path() -> anyhow::Result<PathBuf> {
+    Ok(state_dir()?.join("journal.json"))
+}
+
+///held from loading the journal to saving it, so two commands finishing
+///together don't lose one's operation
+async fn lock(path: &Path) -> anyhow::Result<FileLock> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    let lock = path.with_extension("lock");
+    FileLock::acquire(&lock).await.with_context(|| format!("can't lock {}", lock.display()))
+}
+
+///a store that writes down every todo created, changed or deleted through
+///it, for [`Journaled::save`] to keep once the command is done. calendars
+///themselves aren't
+pub struct Journaled {
+    inner: Arc<dyn TodoStore>,
+    changes: Mutex<Vec<Change>>,
+}
+
+impl Journaled {
+    pub fn new(inner: Arc<dyn TodoStore>) -> Journaled {
+        Journaled { inner, changes: Mutex::new(vec![]) }
+    }
+
+    fn record(&self, change: Change) {
+        self.changes.lock().unwrap().push(change);
+    }
+
+    ///adds what was changed to the journal as one operation, if anything was
+    pub async fn save(&self) -> anyhow::Result<()> {
+        let changes = std::mem::take(&mut *self.changes.lock().unwrap());
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let path = journal_path()?;
+        let _lock = lock(&path).await?;
+        let mut journal = Journal::load(&path).await?;
+        journal.operations.push(Operation { at: Utc::now(), command: command_line(), changes, undone: None });
+        let extra = journal.operations.len().saturating_sub(KEEP);
+        journal.operations.drain(..extra);
+        journal.save(&path).await
+    }
+}
+
+impl TodoStore for Journaled {
+    fn calendars(&self) -> &[CalendarRef] {
+        self.inner.calendars()
+    }
+
+    fn todos<'a>(&'a self, cal: &'a RwLock<Calendar>, query: Query) -> StoreFuture<'a, Arc<Partial<CalendarTodo>>> {
+        self.inner.todos(cal, query)
+    }
+
+    fn create<'a>(&'a self, cal: &'a Calendar, uid: &'a str, ics: &'a str) -> StoreFuture<'a, CalendarTodo> {
+        Box::pin(async move {
+            let todo = self.inner.create(cal, uid, ics).await?;
+            self.record(Change::new(Some(cal.url.clone()), &todo, None, Some(&todo)));
+            Ok(todo)
+        })
+    }
+
+    fn update<'a>(&'a self, todo: &'a CalendarTodo, ics: &'a str) -> StoreFuture<'a, CalendarTodo> {
+        Box::pin(async move {
+            let updated = self.inner.update(todo, ics).await?;
+            let cal = calendar_of(&*self.inner, &todo.url).await;
+            self.record(Change::new(cal, &updated, Some(todo), Some(&updated)));
+            Ok(updated)
+        })
+    }
+
+    fn delete<'a>(&'a self, todo: &'a CalendarTodo) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            self.inner.delete(todo).await?;
+            let cal = calendar_of(&*self.inner, &todo.url).await;
+            self.record(Change::new(cal, todo, Some(todo), None));
+            Ok(())
+        })
+    }
+
+    fn create_calendar<'a>(&'a self, id: &'a str, props: &'a CalendarProps) -> StoreFuture<'a, Calendar> {
+        self.inner.create_calendar(id, props)
+    }
+
+    fn update_calendar<'a>(&'a self, cal: &'a RwLock<Calendar>, props: &'a CalendarProps) -> StoreFuture<'a, ()> {
+        self.inner.update_calendar(cal, props)
+    }
+
+    fn delete_calendar<'a>(&'a self, cal: &'a Calendar) -> StoreFuture<'a, ()> {
+        self.inner.delete_calendar(cal)
+    }
+
+    fn server_info(&self) -> Option<&ServerInfo> {
+        self.inner.server_info()
+    }
+}
+
+///the url of the calendar `url` is in
+async fn calendar_of(store: &dyn TodoStore, url: &str) -> Option<String> {
+    for cal in store.calendars() {
+        let cal = cal.read().await;
+        let dir = cal.url.trim_end_matches('/');
+        if url.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/')) {
+            return Some(cal.url.clone());
+        }
+    }
+    None
+}
+
+///how this was run, quoting arguments with spaces in them
+fn command_line() -> String {
+    let words: Vec<String> = env::args()
+        .skip(1)
+        .map(|word| match word.contains(char::is_whitespace) || word.is_empty() {
+            true => format!("'{word}'"),
+            false => word,
+        })
+        .collect();
+    words.join(" ")
+}
+
+///puts back what the last operation not undone yet changed, newest change
+///first. a todo that changed again since is left alone unless `--force`
+pub async fn undo(store: &dyn TodoStore, command: &UndoCommand, output: &Output) -> anyhow::Result<()> {
+    let path = journal_path()?;
+    //for the whole undo, so a second one doesn't take the same operation
+    let _lock = lock(&path).await?;
+    let mut journal = Journal::load(&path).await?;
+    let Some(operation) = journal.operations.iter_mut().rev().find(|op| op.undone.is_none()) else {
+        return match output.is_json() {
+            true => output.json::<UndoResult>(&[]),
+            false => {
+                println!("Nothing to undo");
+                Ok(())
+            }
+        };
+    };
+    if !output.is_json() {
+        println!("Undoing {}", operation.command);
+    }
+
+    let total = operation.changes.len();
+    let mut failed = 0;
+    let mut last_error = None;
+    let mut results = vec![];
+    for change in operation.changes.iter().rev() {
+        let mut result = UndoResult { uid: change.uid.clone(), summary: change.summary.clone(), result: "failed", message: String::new() };
+        match restore(store, change, command.force).await {
+            Ok((result_name, message)) => {
+                result.result = result_name;
+                result.message = message;
+                if !output.is_json() {
+                    println!("{}", result.message);
+                }
+            }
+            Err(e) => {
+                result.message = format!("{e:#}");
+                if !output.is_json() {
+                    eprintln!("{}: {}", change.summary, result.message);
+                }
+                failed += 1;
+                last_error = Some(e);
+            }
+        }
+        results.push(result);
+    }
+    //one that didn't all go back can be tried again
+    if failed == 0 {
+        operation.undone = Some(Utc::now());
+        journal.save(&path).await?;
+    }
+    if output.is_json() {
+        output.json(&results)?;
+    }
+    failures(failed, total, last_error)
+}
+
+///how undoing one change went, for JSON
+#[derive(Serialize)]
+struct UndoResult {
+    uid: String,
+    summary: String,
+    ///`restored`, `recreated`, `removed` or `unchanged`, or `failed`
+    result: &'static str,
+    message: String,
+}
+
+///undoes `change`: the old version goes back with If-Match on the current
+///etag, a deleted todo is created again and a created one deleted
+async fn restore(store: &dyn TodoStore, change: &Change, force: bool) -> anyhow::Result<(&'static str, String)> {
+    let summary = &change.summary;
+    let mut cal = None;
+    for found in store.calendars() {
+        if found.read().await.url == change.calendar {
+            cal = Some(found.clone());
+        }
+    }
+    let cal = cal.ok_or(CliError::NoSuchCalendar(change.calendar.clone()))?;
+    let todos = store.todos(&cal, Query::All).await?;
+    let current = todos.items.iter().find(|todo| todo.url == change.url);
+    //nobody else changed it since, going by the etag, or the content for servers that don't send one back
+    let untouched = |current: &CalendarTodo, after: &Version| force || current.etag == after.etag || current.ics == after.ics;
+
+    match (&change.before, &change.after, current) {
+        (Some(before), Some(_), Some(current)) if current.ics == before.ics => {
+            Ok(("unchanged", format!("Already as it was: {summary}")))
+        }
+        (Some(before), Some(after), Some(current)) => {
+            if !untouched(current, after) {
+                return Err(CliError::ChangedSince(summary.clone()).into());
+            }
+            store.update(current, &before.ics).await?;
+            Ok(("restored", format!("Restored: {summary}")))
+        }
+        (Some(_), Some(_), None) => Err(CliError::NoSuchReminder(change.uid.clone()).into()),
+        (Some(before), None, _) => {
+            if todos.items.iter().any(|todo| todo.uid().as_ref() == Some(&change.uid)) {
+                return Ok(("unchanged", format!("Already there: {summary}")));
+            }
+            store.create(&*cal.read().await, &change.uid, &before.ics).await?;
+            Ok(("recreated", format!("Recreated: {summary}")))
+        }
+        (None, Some(after), Some(current)) => {
+            if !untouched(current, after) {
+                return Err(CliError::ChangedSince(summary.clone()).into());
+            }
+            store.delete(current).await?;
+            Ok(("removed", format!("Removed: {summary}")))
+        }
+        (None, _, _) => Ok(("unchanged", format!("Already gone: {summary}"))),
+    }
+}
+
+///an operation for `log --format json`
+#[derive(Serialize)]
+struct LogEntry<'a> {
+    ///1 for the latest
+    n: usize,
+    at: DateTime<Utc>,
+    command: &'a str,
+    changes: Vec<LogChange<'a>>,
+    undone: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct LogChange<'a> {
+    uid: &'a str,
+    summary: &'a str,
+    ///`created`, `changed` or `deleted`
+    change: &'static str,
+}
+
+///the latest operations, newest first
+pub async fn log(command: &LogCommand, output: &Output) -> anyhow::Result<()> {
+    let journal = Journal::load(&journal_path()?).await?;
+    let latest = journal.operations.iter().rev().take(command.limit).enumerate();
+    if output.is_json() {
+        let entries: Vec<LogEntry> = latest
+            .map(|(i, op)| LogEntry {
+                n: i + 1,
+                at: op.at,
+                command: &op.command,
+                changes: op
+                    .changes
+                    .iter()
+                    .map(|c| LogChange { uid: &c.uid, summary: &c.summary, change: c.kind() })
+                    .collect(),
+                undone: op.undone,
+            })
+            .collect();
+        return output.json(&entries);
+    }
+    let time = |at: DateTime<Utc>| at.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S").to_string();
+    let rows: Vec<Vec<String>> = latest
+        .map(|(i, op)| {
+            let mut counts: Vec<(&str, usize)> = vec![];
+            for change in &op.changes {
+                match counts.iter_mut().find(|(kind, _)| *kind == change.kind()) {
+                    Some((_, n)) => *n += 1,
+                    None => counts.push((change.kind(), 1)),
+                }
+            }
+            let changes: Vec<String> = match &op.changes[..] {
+                [one] => vec![format!("{} {}", one.kind(), one.summary)],
+                _ => counts.iter().map(|(kind, n)| format!("{n} {kind}")).collect(),
+            };
+            vec![(i + 1).to_string(), time(op.at), op.command.clone(), changes.join(", "), op.undone.map(time).unwrap_or_default()]
+        })
+        .collect();
+    output.rows(&["#", "TIME", "COMMAND", "CHANGES", "UNDONE"], &rows);
+    Ok(())
+}
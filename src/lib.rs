@@ -0,0 +1,33 @@
+//! CalDAV todo/reminder client.
+//!
+//! [`caldav`] talks to the server: discovery, auth, calendars and todos.
+//! [`ical`] parses and writes the iCalendar objects inside them.
+//! [`store`] puts the client and a local vdir behind one [`store::TodoStore`] trait.
+//!
+//! ```no_run
+//! use reminder_rs::caldav::{CalDAVClient, Credentials};
+//!
+//! # async fn run() -> reminder_rs::caldav::Result<()> {
+//! let credentials = Credentials::builder()
+//!     .username("me".to_string())
+//!     .password("secret".to_string())
+//!     .build();
+//! let client = CalDAVClient::builder()
+//!     .base_url("me@example.com")
+//!     .credentials(credentials)
+//!     .build()
+//!     .await?;
+//!
+//! for cal in &client.calendars {
+//!     let todos = client.get_current_todos(cal).await?;
+//!     println!("{}: {} todos", cal.read().await.name, todos.items.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod caldav;
+pub mod ical;
+pub mod store;
+#[cfg(feature = "mock-server")]
+pub mod mock;
@@ -0,0 +1,294 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Datelike, Duration, Local};
+use clap::ValueEnum;
+use reminder_rs::caldav::agenda::{agenda_slot, is_overdue, AgendaSlot};
+use reminder_rs::caldav::datetime::DateValue;
+use reminder_rs::caldav::details::priority_label;
+use reminder_rs::caldav::{Calendar, CalendarTodo, Partial, TodoState, TodoTree};
+
+use crate::output::{Group, Progress, TodoRow};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Due,
+    Start,
+    Priority,
+    Created,
+    Modified,
+    Summary,
+    Calendar,
+    Status,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+pub const DEFAULT_SORT: [SortKey; 3] = [
+    SortKey { field: SortField::Due, descending: false },
+    SortKey { field: SortField::Priority, descending: false },
+    SortKey { field: SortField::Summary, descending: false },
+];
+
+impl SortKey {
+    ///`due`, or `-due` for the other way round. for clap
+    pub fn parse(text: &str) -> Result<SortKey, String> {
+        let (descending, name) = match text.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, text),
+        };
+        let field = match name.trim() {
+            "due" => SortField::Due,
+            "start" => SortField::Start,
+            "priority" => SortField::Priority,
+            "created" => SortField::Created,
+            "modified" => SortField::Modified,
+            "summary" => SortField::Summary,
+            "calendar" => SortField::Calendar,
+            "status" => SortField::Status,
+            _ => {
+                return Err(format!(
+                    "unknown sort key {name}, expected due, start, priority, created, modified, summary, calendar or status"
+                ))
+            }
+        };
+        Ok(SortKey { field, descending })
+    }
+
+    ///rows without the field go last whichever way it's sorted
+    fn compare(&self, a: &TodoRow, b: &TodoRow) -> Ordering {
+        let date = |d: &Option<DateValue>| d.as_ref().map(DateValue::instant);
+        let (x, y) = (&a.details, &b.details);
+        match self.field {
+            SortField::Due => self.compare_some(date(&x.due), date(&y.due)),
+            SortField::Start => self.compare_some(date(&x.start), date(&y.start)),
+            SortField::Created => self.compare_some(date(&x.created), date(&y.created)),
+            SortField::Modified => self.compare_some(date(&x.last_modified), date(&y.last_modified)),
+            //1 is the highest, so ascending is most important first
+            SortField::Priority => self.compare_some(x.priority.filter(|p| *p > 0), y.priority.filter(|p| *p > 0)),
+            SortField::Summary => {
+                let summary = |row: &TodoRow| row.details.summary.as_ref().map(|s| s.to_lowercase());
+                self.compare_some(summary(a), summary(b))
+            }
+            SortField::Calendar => self.compare_some(Some(a.calendar.to_lowercase()), Some(b.calendar.to_lowercase())),
+            SortField::Status => self.compare_some(Some(x.state as u8), Some(y.state as u8)),
+        }
+    }
+
+    fn compare_some<T: Ord>(&self, a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) if self.descending => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    Calendar,
+    /// Overdue, Today, Tomorrow, This week, Later, No date
+    DueBucket,
+    /// High, Medium, Low, No priority
+    Priority,
+    /// Reminders with several categories show up under each
+    Category,
+    None,
+}
+
+///stable, so rows that compare equal on every key stay in the order they came in
+pub fn sort(rows: &mut [TodoRow], keys: &[SortKey]) {
+    rows.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| key.compare(a, b))
+            .find(|order| order.is_ne())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+///rows for `cal`'s `current` todos, each knowing the closest ancestor it has
+///among them and how many of its own subtasks are done. `subtasks` are all
+///of the calendar's, done or not, for counting
+pub fn tree_rows(cal: &Calendar, current: &Partial<CalendarTodo>, subtasks: &Partial<CalendarTodo>) -> Vec<TodoRow> {
+    let mut reported = HashSet::new();
+    for e in current.errors.iter().chain(&subtasks.errors) {
+        if reported.insert(&e.href) {
+            eprintln!("Skipped {}: {:#}", e.href, e.error);
+        }
+    }
+    //the open subtasks are in both
+    let mut todos: Vec<&CalendarTodo> = current.items.iter().collect();
+    todos.extend(subtasks.items.iter().filter(|todo| !current.items.iter().any(|t| t.url == todo.url)));
+    let tree = TodoTree::new(todos.iter().copied());
+    let shown = current.items.len();
+
+    (0..shown)
+        .map(|i| {
+            let mut row = TodoRow::new(cal, todos[i], &tree.subtasks(&todos, i));
+            let mut ancestor = tree.parent(i);
+            while let Some(a) = ancestor.filter(|&a| a >= shown) {
+                ancestor = tree.parent(a);
+            }
+            row.tree_parent = ancestor.and_then(|a| todos[a].uid());
+            let children: Vec<TodoState> = tree.children(i).iter().map(|&c| todos[c].state()).collect();
+            let total = children.iter().filter(|s| **s != TodoState::Cancelled).count();
+            if total > 0 {
+                let done = children.iter().filter(|s| **s == TodoState::Completed).count();
+                row.subtasks = Some(Progress { done, total });
+            }
+            row
+        })
+        .collect()
+}
+
+///sorted by `keys`, with each row followed by the ones under it, a level
+///deeper. rows whose parent isn't in `rows` stay at the top
+pub fn nest(mut rows: Vec<TodoRow>, keys: &[SortKey]) -> Vec<TodoRow> {
+    sort(&mut rows, keys);
+    let id = |row: &TodoRow| (row.calendar.clone(), row.details.uid.clone().unwrap_or_default());
+    let index: HashMap<(String, String), usize> = rows.iter().enumerate().map(|(i, row)| (id(row), i)).collect();
+    let parent = |row: &TodoRow| {
+        let uid = row.tree_parent.clone()?;
+        index.get(&(row.calendar.clone(), uid)).copied()
+    };
+    let mut children: Vec<Vec<usize>> = vec![vec![]; rows.len()];
+    let mut stack = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        match parent(row) {
+            Some(p) if p != i => children[p].push(i),
+            _ => stack.push((i, 0)),
+        }
+    }
+    stack.reverse();
+
+    let mut order = vec![];
+    let mut seen = HashSet::new();
+    while let Some((i, depth)) = stack.pop() {
+        if seen.insert(i) {
+            order.push((i, depth));
+            stack.extend(children[i].iter().rev().map(|&c| (c, depth + 1)));
+        }
+    }
+    //only rows going round in a circle are left, and tree_rows breaks those
+    order.extend((0..rows.len()).filter(|i| !seen.contains(i)).map(|i| (i, 0)));
+
+    let mut rows: Vec<Option<TodoRow>> = rows.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|(i, depth)| {
+            let mut row = rows[i].take()?;
+            row.depth = depth;
+            Some(row)
+        })
+        .collect()
+}
+
+///splits `rows` into groups in the order they're shown, then sorts each one,
+///as a tree if `tree`
+pub fn group(rows: Vec<TodoRow>, by: GroupBy, keys: &[SortKey], now: DateTime<Local>, tree: bool) -> Vec<Group> {
+    let mut groups = match by {
+        GroupBy::None => vec![Group { title: None, rows }],
+        //calendars in the order they came in, which is the store's
+        GroupBy::Calendar => collect(rows, &[], |row| vec![row.calendar.clone()]),
+        GroupBy::DueBucket => collect(rows, &BUCKETS, |row| vec![due_bucket(row.details.due.as_ref(), now).to_string()]),
+        GroupBy::Priority => collect(rows, &["High", "Medium", "Low", "No priority"], |row| {
+            let label = match row.details.priority.filter(|p| *p > 0) {
+                Some(priority) => capitalize(priority_label(priority)),
+                None => "No priority".to_string(),
+            };
+            vec![label]
+        }),
+        GroupBy::Category => {
+            let mut groups = collect(rows, &[], |row| match row.details.categories.is_empty() {
+                true => vec!["No category".to_string()],
+                false => row.details.categories.clone(),
+            });
+            groups.sort_by_key(|g| {
+                let title = g.title.clone().unwrap_or_default();
+                (title == "No category", title.to_lowercase())
+            });
+            groups
+        }
+    };
+    for group in &mut groups {
+        match tree {
+            true => group.rows = nest(std::mem::take(&mut group.rows), keys),
+            false => sort(&mut group.rows, keys),
+        }
+    }
+    groups
+}
+
+pub const BUCKETS: [&str; 6] = ["Overdue", "Today", "Tomorrow", "This week", "Later", "No date"];
+
+///which of [`BUCKETS`] a due date goes in. a week ends on Sunday
+pub fn due_bucket(due: Option<&DateValue>, now: DateTime<Local>) -> &'static str {
+    let Some(due) = due else { return "No date" };
+    let today = now.date_naive();
+    let end_of_week = today + Duration::days(6 - today.weekday().num_days_from_monday() as i64);
+    match due.local_date() {
+        _ if is_overdue(due, now) => "Overdue",
+        day if day <= today => "Today",
+        day if day == today + Duration::days(1) => "Tomorrow",
+        day if day <= end_of_week => "This week",
+        _ => "Later",
+    }
+}
+
+///`rows` on an agenda of `days` days from today, under Overdue and then
+///one heading per day. days with nothing due are left out
+pub fn agenda(rows: Vec<TodoRow>, days: u32, now: DateTime<Local>) -> Vec<Group> {
+    let mut slots: Vec<(AgendaSlot, Vec<TodoRow>)> = vec![];
+    for row in rows {
+        let Some(slot) = agenda_slot(&row.details, now, days) else { continue };
+        match slots.iter_mut().find(|(s, _)| *s == slot) {
+            Some((_, rows)) => rows.push(row),
+            None => slots.push((slot, vec![row])),
+        }
+    }
+    slots.sort_by_key(|(slot, _)| *slot);
+    let today = now.date_naive();
+    slots
+        .into_iter()
+        .map(|(slot, mut rows)| {
+            sort(&mut rows, &DEFAULT_SORT);
+            let title = match slot {
+                AgendaSlot::Overdue => "Overdue".to_string(),
+                AgendaSlot::Day(day) if day == today => "Today".to_string(),
+                AgendaSlot::Day(day) if day == today + Duration::days(1) => "Tomorrow".to_string(),
+                AgendaSlot::Day(day) => day.format("%A %Y-%m-%d").to_string(),
+            };
+            Group { title: Some(title), rows }
+        })
+        .collect()
+}
+
+///groups by the titles `key` gives each row, ordered like `order` and
+///then by first appearance
+fn collect(rows: Vec<TodoRow>, order: &[&str], key: impl Fn(&TodoRow) -> Vec<String>) -> Vec<Group> {
+    let mut groups: Vec<Group> = vec![];
+    for row in rows {
+        for title in key(&row) {
+            match groups.iter_mut().find(|g| g.title.as_ref() == Some(&title)) {
+                Some(group) => group.rows.push(row.clone()),
+                None => groups.push(Group { title: Some(title), rows: vec![row.clone()] }),
+            }
+        }
+    }
+    groups.sort_by_key(|g| order.iter().position(|o| g.title.as_deref() == Some(*o)).unwrap_or(order.len()));
+    groups
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -1,91 +1,303 @@
-use std::{cell::RefCell, env};
+use std::{env, path::PathBuf, process::ExitCode, sync::Arc, time::Duration};
 use dotenv::dotenv;
+use actions::{Action, Options};
 use args::*;
-use caldav::{calendar::Calendar, client::CalDAVClient};
+use exit::CliError;
+use journal::Journaled;
+use output::{CalendarInfoJson, Output, ServerInfoJson, TodoRow};
+use reminder_rs::caldav::{CalDAVClient, Calendar, CalendarRef, CalendarTodo, Credentials, Partial, TodoTree};
+use reminder_rs::store::{self, Query, TodoStore, VdirStore};
 use clap::Parser;
 
-mod caldav;
-mod ical;
+mod actions;
+mod archive;
 mod args;
+mod calendars;
+mod daemon;
+mod exit;
+mod filter;
+mod history;
+mod info;
+mod journal;
+mod listing;
+mod new;
+mod output;
+mod prompt;
+mod snooze;
+#[cfg(feature = "tui")]
 mod tui;
+mod when;
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     dotenv().ok();
-    let base_url = env::var("CALDAV_URL").unwrap();
-    let username = env::var("CALDAV_USERNAME").unwrap();
-    let password = env::var("CALDAV_PASSWORD").unwrap();
+    let args = ReminderArgs::parse();
+    match run(args).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:#}");
+            exit::exit_code(&e)
+        }
+    }
+}
 
-    let client = CalDAVClient::new(&base_url, &username, &password).await.unwrap();
+///runs the subcommand, writing down what it changed for `undo`
+async fn run(args: ReminderArgs) -> anyhow::Result<()> {
+    let store = open_store(&args).await?;
+    let untracked = matches!(
+        args.subcommand,
+        Some(ReminderSubcommands::Daemon(_) | ReminderSubcommands::Undo(_) | ReminderSubcommands::Log(_))
+    );
+    if untracked {
+        return command(&args, store).await;
+    }
+    let journaled = Arc::new(Journaled::new(store));
+    let result = command(&args, journaled.clone()).await;
+    if let Err(e) = journaled.save().await {
+        eprintln!("Couldn't write down what changed for undo: {e:#}");
+    }
+    result
+}
 
-    let args = ReminderArgs::parse();
+async fn command(args: &ReminderArgs, store: Arc<dyn TodoStore>) -> anyhow::Result<()> {
+    let output = Output::new(args.format, args.columns.clone());
 
-    match &args.subcommand {
+    let Some(subcommand) = &args.subcommand else {
+        return agenda(&*store, 1, &output, args.jobs).await;
+    };
+    match subcommand {
         ReminderSubcommands::Interactive(..) => {
-            tui::main::start(client);
+            #[cfg(feature = "tui")]
+            tui::main::start(store)?;
+            #[cfg(not(feature = "tui"))]
+            return Err(CliError::Unsupported("reminder-rs was built without the `tui` feature".to_string()).into());
         }
-        ReminderSubcommands::Calendars(_) => {
-            for cal_ref in &client.calendars {
-                let cal = cal_ref.borrow();
-                println!("{}", cal.fancy_name());
-            }
+        ReminderSubcommands::Calendars(CalendarsCommand { action: Some(action), .. }) => {
+            calendars::manage(&*store, action, &output).await?
         }
-        ReminderSubcommands::List(ListCommand { calendar: calendar_name_opt }) => {
-            match calendar_name_opt {
-                Some(calendar_name) => {
-                    let cal = client.get_calendar(calendar_name).unwrap();
-                    print_todos(&client, cal).await;
-                },
-                None => {
-                    for cal in &client.calendars {
-                        print_todos(&client, cal).await;
-                    }
-                },
+        ReminderSubcommands::Calendars(CalendarsCommand { action: None, all }) => {
+            let mut cals = vec![];
+            for cal_ref in store.calendars() {
+                cals.push(cal_ref.read().await);
             }
+            let shown: Vec<&Calendar> = cals.iter().map(|cal| &**cal).filter(|cal| cal.supports_todo || *all).collect();
+            output.calendars(&shown)?;
         }
+        ReminderSubcommands::ServerInfo(_) => print_server_info(&*store, &output).await?,
+        ReminderSubcommands::List(ListCommand { calendar: calendar_name_opt, sort, group_by, flat }) => {
+            let cals = match calendar_name_opt {
+                Some(calendar_name) => vec![find_calendar(&*store, calendar_name).await?],
+                None => store::todo_calendars(&*store).await,
+            };
+            let tree = !flat && matches!(group_by, listing::GroupBy::None | listing::GroupBy::Calendar);
+            let rows = match tree {
+                true => tree_rows(&*store, &cals, args.jobs).await?,
+                false => current_rows(&*store, &cals, args.jobs).await?,
+            };
+            let keys = sort.clone().unwrap_or(listing::DEFAULT_SORT.to_vec());
+            output.groups(&listing::group(rows, *group_by, &keys, chrono::Local::now(), tree))?;
+        }
+        ReminderSubcommands::Today(_) => agenda(&*store, 1, &output, args.jobs).await?,
+        ReminderSubcommands::Agenda(AgendaCommand { days }) => agenda(&*store, *days, &output, args.jobs).await?,
         ReminderSubcommands::Search(SearchCommand { calendar: calendar_name_opt, term }) => {
-            match calendar_name_opt {
-                Some(calendar_name) => {
-                    let cal = client.get_calendar(calendar_name).unwrap();
-                    search_todos(&client, cal, term).await;
-                },
-                None => {
-                    for cal in &client.calendars {
-                        search_todos(&client, cal, term).await;
-                    }
-                },
+            let cals = match calendar_name_opt {
+                Some(calendar_name) => vec![find_calendar(&*store, calendar_name).await?],
+                None => store::todo_calendars(&*store).await,
+            };
+            let mut rows = vec![];
+            for cal in &cals {
+                let todos = store.todos(cal, Query::Current).await?;
+                let matches = |todo: &CalendarTodo| todo.text("SUMMARY").is_some_and(|s| s.contains(term.as_str()));
+                rows.extend(todo_rows(&*cal.read().await, &todos, matches));
+            }
+            output.todos(rows)?;
+        }
+        ReminderSubcommands::History(command) => {
+            let output = Output::new(args.format, args.columns.clone().or(Some(history::COLUMNS.to_vec())));
+            history::show(&*store, command, &output, args.jobs).await?
+        }
+        ReminderSubcommands::Info(command) => info::show(&*store, command, &output, args.jobs).await?,
+        ReminderSubcommands::Done(ActionCommand { selection, recursive }) => {
+            let options = Options { recursive: *recursive, ..Options::new(&selection.bulk) };
+            actions::run(&*store, Action::Done, &selection.reminders, &options, &output, args.jobs).await?
+        }
+        ReminderSubcommands::Cancel(ActionCommand { selection, recursive }) => {
+            let options = Options { recursive: *recursive, ..Options::new(&selection.bulk) };
+            actions::run(&*store, Action::Cancel, &selection.reminders, &options, &output, args.jobs).await?
+        }
+        ReminderSubcommands::Undone(ActionCommand { selection, recursive }) => {
+            let options = Options { recursive: *recursive, ..Options::new(&selection.bulk) };
+            actions::run(&*store, Action::Undone, &selection.reminders, &options, &output, args.jobs).await?
+        }
+        ReminderSubcommands::Delete(DeleteCommand { selection, recursive, local_archive, permanent }) => {
+            let archive = archive::Archive::chosen(&*store, *local_archive, *permanent).await?;
+            let action = if archive.is_some() { Action::Archive } else { Action::Delete };
+            let options = Options { recursive: *recursive, archive, ..Options::new(&selection.bulk) };
+            actions::run(&*store, action, &selection.reminders, &options, &output, args.jobs).await?
+        }
+        ReminderSubcommands::Purge(command) => archive::purge(&*store, command, &output, args.jobs).await?,
+        ReminderSubcommands::Move(MoveCommand { calendar, selection }) => {
+            let options = Options { to: Some(find_calendar(&*store, calendar).await?), ..Options::new(&selection.bulk) };
+            actions::run(&*store, Action::Move, &selection.reminders, &options, &output, args.jobs).await?
+        }
+        ReminderSubcommands::Snooze(command) => {
+            let (ids, shift) = snooze::arguments(command)?;
+            let options = Options { shift: Some(shift), ..Options::new(&command.bulk) };
+            actions::run(&*store, Action::Snooze, ids, &options, &output, args.jobs).await?
+        }
+        ReminderSubcommands::PostponeOverdue(command) => snooze::postpone(&*store, command, &output, args.jobs).await?,
+        ReminderSubcommands::New(command) => new::create(&*store, command, &output, args.jobs).await?,
+        ReminderSubcommands::Edit(command) => {
+            if command.tui {
+                return Err(CliError::Unsupported("`edit --tui` isn't implemented yet, say what to change instead".to_string()).into());
             }
+            let options = Options { changes: Some(new::Changes::new(command)), ..Options::new(&command.selection.bulk) };
+            actions::run(&*store, Action::Edit, &command.selection.reminders, &options, &output, args.jobs).await?
         }
-        _ => {}
+        ReminderSubcommands::Daemon(command) => daemon::run(&*store, command, args.jobs).await?,
+        ReminderSubcommands::Undo(command) => journal::undo(&*store, command, &output).await?,
+        ReminderSubcommands::Log(command) => journal::log(command, &output).await?,
+        ReminderSubcommands::Copy(_) => return Err(CliError::Unsupported("`copy` isn't implemented yet".to_string()).into()),
+        ReminderSubcommands::Import(_) => return Err(CliError::Unsupported("`import` isn't implemented yet".to_string()).into()),
+        ReminderSubcommands::Test(_) => return Err(CliError::Unsupported("`test` isn't implemented yet".to_string()).into()),
     }
+    Ok(())
 }
 
-async fn search_todos(client: &CalDAVClient, cal_ref: &RefCell<Calendar>, term: &str) {
-    let mut has_printed = false;
-    let todos = client.get_current_todos(cal_ref).await;
-    // for todo in todos.as_ref() {
-    //     let s = todo.vtodo.summary.0.unwrap();
-    //     let summary = s.value;
-    //
-    //     if summary.contains(term) {
-    //         if !has_printed {
-    //             println!("Todos for {}", cal_ref.borrow().fancy_name());
-    //             has_printed = true;
-    //         }
-    //         println!("{summary}");
-    //     }
-    // }
+///a local vdir if REMINDER_VDIR is set, otherwise the CalDAV server
+async fn open_store(args: &ReminderArgs) -> anyhow::Result<Arc<dyn TodoStore>> {
+    if let Ok(path) = env::var("REMINDER_VDIR") {
+        return Ok(Arc::new(VdirStore::open(path).await?));
+    }
+
+    let base_url = env::var("CALDAV_URL").map_err(|_| CliError::MissingConfig("CALDAV_URL"))?;
+    let credentials = Credentials::builder()
+        .maybe_username(env::var("CALDAV_USERNAME").ok())
+        .maybe_password(env::var("CALDAV_PASSWORD").ok())
+        .maybe_token(env::var("CALDAV_TOKEN").ok())
+        .maybe_refresh_token(env::var("CALDAV_REFRESH_TOKEN").ok())
+        .maybe_token_url(env::var("CALDAV_TOKEN_URL").ok())
+        .maybe_client_id(env::var("CALDAV_CLIENT_ID").ok())
+        .maybe_client_secret(env::var("CALDAV_CLIENT_SECRET").ok())
+        .build();
+
+    let client = CalDAVClient::builder()
+        .base_url(&base_url)
+        .credentials(credentials)
+        .verbose(args.verbose)
+        .timeout(Duration::from_secs(args.timeout))
+        .retries(args.retries)
+        .build()
+        .await?;
+    Ok(Arc::new(client))
 }
 
-async fn print_todos(client: &CalDAVClient, cal_ref: &RefCell<Calendar>) {
-    println!("Todos for {}", cal_ref.borrow().fancy_name());
+///REMINDER_STATE_DIR, or reminder-rs in the XDG state directory
+fn state_dir() -> anyhow::Result<PathBuf> {
+    match (env::var_os("REMINDER_STATE_DIR"), env::var_os("XDG_STATE_HOME"), env::var_os("HOME")) {
+        (Some(dir), _, _) => Ok(PathBuf::from(dir)),
+        (None, Some(state), _) => Ok(PathBuf::from(state).join("reminder-rs")),
+        (None, None, Some(home)) => Ok(PathBuf::from(home).join(".local/state/reminder-rs")),
+        (None, None, None) => Err(CliError::MissingConfig("REMINDER_STATE_DIR").into()),
+    }
+}
+
+async fn find_calendar(store: &dyn TodoStore, name: &str) -> anyhow::Result<CalendarRef> {
+    Ok(store::find_calendar(store, name).await.ok_or(CliError::NoSuchCalendar(name.to_string()))?)
+}
 
-    let todos = client.get_current_todos(&cal_ref).await.expect("get current todos failed :(");
-    for todo in todos.as_ref() {
-        let summary = todo.vtodo.summary.get_value().unwrap();
-        println!("{}", summary);
+async fn print_server_info(store: &dyn TodoStore, output: &Output) -> anyhow::Result<()> {
+    if output.is_json() {
+        let mut calendars = vec![];
+        for cal_ref in store.calendars() {
+            let cal = cal_ref.read().await;
+            calendars.push(CalendarInfoJson {
+                name: cal.name.clone(),
+                url: cal.url.clone(),
+                todos: cal.supports_todo,
+                capabilities: cal.capabilities.clone(),
+            });
+        }
+        return output.json_object(&ServerInfoJson { server: store.server_info(), calendars });
     }
+
+    match store.server_info() {
+        Some(server) => {
+            println!("DAV: {}", list_or_unknown(&server.dav));
+            println!("Methods: {}", list_or_unknown(&server.allow));
+        }
+        None => println!("Not a CalDAV server (REMINDER_VDIR is set)"),
+    }
+    for cal_ref in store.calendars() {
+        let cal = cal_ref.read().await;
+        let caps = &cal.capabilities;
+        println!("\n{}", output.calendar_name(&cal));
+        println!("  todos: {}", if cal.supports_todo { "yes" } else { "no" });
+        println!("  reports: {}", list_or_unknown(&caps.reports));
+        let data: Vec<String> = caps
+            .data_types
+            .iter()
+            .map(|d| match &d.version {
+                Some(version) => format!("{} {version}", d.content_type),
+                None => d.content_type.clone(),
+            })
+            .collect();
+        println!("  data: {}", list_or_unknown(&data));
+        match caps.max_resource_size {
+            Some(max) => println!("  max size: {max} bytes"),
+            None => println!("  max size: unknown"),
+        }
+        let date = |d: Option<chrono::DateTime<chrono::Utc>>| d.map(|d| d.to_rfc3339()).unwrap_or("unknown".to_string());
+        println!("  dates: {} to {}", date(caps.min_date_time), date(caps.max_date_time));
+    }
+    Ok(())
 }
 
+fn list_or_unknown(items: &[String]) -> String {
+    if items.is_empty() {
+        "unknown".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+///open todos across every todo calendar on an agenda of `days` days
+async fn agenda(store: &dyn TodoStore, days: u32, output: &Output, jobs: usize) -> anyhow::Result<()> {
+    let cals = store::todo_calendars(store).await;
+    let rows = current_rows(store, &cals, jobs).await?;
+    output.groups(&listing::agenda(rows, days, chrono::Local::now()))
+}
+
+///rows for the current todos in `cals`, fetching `jobs` calendars at once
+async fn current_rows(store: &dyn TodoStore, cals: &[CalendarRef], jobs: usize) -> anyhow::Result<Vec<TodoRow>> {
+    let results = store::current_todos_many(store, cals, jobs).await;
+    let mut rows = vec![];
+    for (cal, todos) in cals.iter().zip(results) {
+        rows.extend(todo_rows(&*cal.read().await, &*todos?, |_| true));
+    }
+    Ok(rows)
+}
 
+///like [`current_rows`], knowing which are subtasks of which
+async fn tree_rows(store: &dyn TodoStore, cals: &[CalendarRef], jobs: usize) -> anyhow::Result<Vec<TodoRow>> {
+    let current = store::current_todos_many(store, cals, jobs).await;
+    let subtasks = store::todos_many(store, cals, Query::Subtasks, jobs).await;
+    let mut rows = vec![];
+    for ((cal, current), subtasks) in cals.iter().zip(current).zip(subtasks) {
+        rows.extend(listing::tree_rows(&*cal.read().await, &*current?, &*subtasks?));
+    }
+    Ok(rows)
+}
 
+///rows for the todos in `cal` that `keep` says to, reporting the ones
+///that couldn't be read on stderr
+fn todo_rows(cal: &Calendar, todos: &Partial<CalendarTodo>, keep: impl Fn(&CalendarTodo) -> bool) -> Vec<TodoRow> {
+    for e in &todos.errors {
+        eprintln!("Skipped {}: {:#}", e.href, e.error);
+    }
+    let tree = TodoTree::new(&todos.items);
+    (0..todos.items.len())
+        .filter(|&i| keep(&todos.items[i]))
+        .map(|i| TodoRow::new(cal, &todos.items[i], &tree.subtasks(&todos.items, i)))
+        .collect()
+}
@@ -0,0 +1,104 @@
+use std::collections::BTreeSet;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use super::http::{Request, Response};
+use super::Config;
+use crate::caldav::auth::split_challenges;
+
+pub(crate) const TOKEN: &str = "/token";
+const REALM: &str = "mock";
+const NONCE: &str = "4b0a3c8e-mock";
+
+///how the mock server asks for credentials
+#[derive(Clone, Debug)]
+pub enum Auth {
+    ///Basic, with the server's username and password
+    Basic,
+    ///Digest with qop=auth, a challenge for each algorithm in turn (eg.
+    ///`["MD5", "SHA-256"]`). a nonce count can't be used twice
+    Digest(Vec<String>),
+    ///only `token` gets in. `POST /token` trades `refresh_token` for it
+    Bearer { token: String, refresh_token: String },
+}
+
+///None if `req` may go on, otherwise what to answer instead. `counts` are
+///the digest nonce counts used so far
+pub(crate) fn check(config: &Config, req: &Request, counts: &mut BTreeSet<u32>) -> Option<Response> {
+    let auth = config.auth.as_ref()?;
+    let (username, password) = config.credentials.clone().unwrap_or_default();
+    let given = req.header("authorization").unwrap_or_default();
+    let ok = match auth {
+        Auth::Basic => given == format!("Basic {}", STANDARD.encode(format!("{username}:{password}"))),
+        Auth::Digest(algorithms) => digest(algorithms, given, req, &username, &password, counts),
+        Auth::Bearer { token, .. } => given == format!("Bearer {token}"),
+    };
+    match ok {
+        true => None,
+        false => Some(challenge(auth)),
+    }
+}
+
+fn challenge(auth: &Auth) -> Response {
+    let res = Response::new(401);
+    match auth {
+        Auth::Basic => res.header("WWW-Authenticate", format!("Basic realm=\"{REALM}\"")),
+        Auth::Digest(algorithms) => algorithms.iter().fold(res, |res, algorithm| {
+            res.header(
+                "WWW-Authenticate",
+                format!("Digest realm=\"{REALM}\", qop=\"auth\", nonce=\"{NONCE}\", algorithm={algorithm}"),
+            )
+        }),
+        Auth::Bearer { .. } => res.header("WWW-Authenticate", format!("Bearer realm=\"{REALM}\"")),
+    }
+}
+
+fn digest(
+    algorithms: &[String],
+    given: &str,
+    req: &Request,
+    username: &str,
+    password: &str,
+    counts: &mut BTreeSet<u32>,
+) -> bool {
+    let Some((scheme, params)) = split_challenges(given).into_iter().next() else {
+        return false;
+    };
+    let param = |key: &str| params.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str()).unwrap_or_default();
+    let algorithm = param("algorithm");
+    if !scheme.eq_ignore_ascii_case("digest")
+        || !algorithms.iter().any(|a| a.eq_ignore_ascii_case(algorithm))
+        || param("username") != username
+        || param("realm") != REALM
+        || param("nonce") != NONCE
+        || param("uri") != req.path
+        || param("qop") != "auth"
+    {
+        return false;
+    }
+    let Ok(count) = u32::from_str_radix(param("nc"), 16) else {
+        return false;
+    };
+    let hash = |data: String| match algorithm.to_ascii_uppercase().as_str() {
+        "SHA-256" => format!("{:x}", Sha256::digest(data.as_bytes())),
+        _ => format!("{:x}", Md5::digest(data.as_bytes())),
+    };
+    let ha1 = hash(format!("{username}:{REALM}:{password}"));
+    let ha2 = hash(format!("{}:{}", req.method, req.path));
+    let expected = hash(format!("{ha1}:{NONCE}:{}:{}:auth:{ha2}", param("nc"), param("cnonce")));
+    param("response") == expected && counts.insert(count)
+}
+
+///the token endpoint, for `POST /token`
+pub(crate) fn token(config: &Config, req: &Request) -> Response {
+    let Some(Auth::Bearer { token, refresh_token }) = &config.auth else {
+        return Response::new(404);
+    };
+    let given = req.body.split('&').find_map(|pair| pair.strip_prefix("refresh_token="));
+    match given == Some(refresh_token.as_str()) {
+        true => Response::new(200).body("application/json", format!(r#"{{"access_token":"{token}"}}"#)),
+        false => Response::new(400).body("application/json", r#"{"error":"invalid_grant"}"#.to_string()),
+    }
+}
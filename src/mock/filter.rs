@@ -0,0 +1,108 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use minidom::Element;
+
+use crate::caldav::component::{Component, Prop};
+use crate::caldav::parser::NS_C;
+
+///DATE or DATE-TIME property of `comp`, see [`parse_time`]
+fn time(comp: &Component, name: &str) -> Option<NaiveDateTime> {
+    parse_time(&comp.prop(name)?.value)
+}
+
+///does the calendar object match a `<c:filter>` (RFC 4791 9.7)
+pub(crate) fn matches(filter: &Element, root: &Component) -> bool {
+    elements(filter, "comp-filter").all(|f| comp_matches(f, &[root]))
+}
+
+fn comp_matches(filter: &Element, candidates: &[&Component]) -> bool {
+    let name = filter.attr("name").unwrap_or_default();
+    let found: Vec<&Component> = candidates.iter().copied().filter(|c| c.name.eq_ignore_ascii_case(name)).collect();
+    if filter.has_child("is-not-defined", NS_C) {
+        return found.is_empty();
+    }
+    found.iter().any(|comp| {
+        let children: Vec<&Component> = comp.children.iter().collect();
+        filter.children().all(|f| match f.name() {
+            "time-range" => comp_in_range(f, comp),
+            "prop-filter" => prop_matches(f, comp),
+            "comp-filter" => comp_matches(f, &children),
+            _ => true,
+        })
+    })
+}
+
+fn prop_matches(filter: &Element, comp: &Component) -> bool {
+    let name = filter.attr("name").unwrap_or_default().to_ascii_uppercase();
+    let props: Vec<&Prop> = comp.props.iter().filter(|p| p.name == name).collect();
+    if filter.has_child("is-not-defined", NS_C) {
+        return props.is_empty();
+    }
+    props.iter().any(|prop| {
+        filter.children().all(|f| match f.name() {
+            "text-match" => text_matches(f, &prop.value),
+            "time-range" => parse_time(&prop.value).is_some_and(|t| in_range(f, t)),
+            "param-filter" => param_matches(f, prop),
+            _ => true,
+        })
+    })
+}
+
+fn param_matches(filter: &Element, prop: &Prop) -> bool {
+    let name = filter.attr("name").unwrap_or_default().to_ascii_uppercase();
+    let value = prop.params.iter().find(|(k, _)| *k == name).map(|(_, v)| v);
+    if filter.has_child("is-not-defined", NS_C) {
+        return value.is_none();
+    }
+    match (value, filter.get_child("text-match", NS_C)) {
+        (Some(value), Some(text)) => text_matches(text, value),
+        (value, None) => value.is_some(),
+        (None, Some(_)) => false,
+    }
+}
+
+fn text_matches(filter: &Element, value: &str) -> bool {
+    let needle = filter.text();
+    let negate = filter.attr("negate-condition") == Some("yes");
+    let found = match filter.attr("collation").unwrap_or("i;ascii-casemap") {
+        "i;octet" => value.contains(&needle),
+        //what clients mean by it is equality
+        "i;ascii-numeric" => value.trim().parse::<u64>().ok() == needle.trim().parse::<u64>().ok(),
+        _ => value.to_ascii_lowercase().contains(&needle.to_ascii_lowercase()),
+    };
+    found != negate
+}
+
+///RFC 4791 9.9, without expanding recurrences
+fn comp_in_range(filter: &Element, comp: &Component) -> bool {
+    let start = filter.attr("start").and_then(parse_time).unwrap_or(NaiveDateTime::MIN);
+    let end = filter.attr("end").and_then(parse_time).unwrap_or(NaiveDateTime::MAX);
+    let (dtstart, due) = (time(comp, "DTSTART"), time(comp, "DUE").or(time(comp, "DTEND")));
+    let (completed, created) = (time(comp, "COMPLETED"), time(comp, "CREATED"));
+    match (dtstart, due, completed, created) {
+        (Some(s), Some(d), ..) => (start <= s || start < d) && (end > s || end >= d),
+        (Some(s), None, ..) => start <= s && end > s,
+        (None, Some(d), ..) => start < d && end >= d,
+        (None, None, Some(c), Some(cr)) => (start <= cr || start <= c) && (end >= cr || end >= c),
+        (None, None, Some(c), None) => start <= c && end >= c,
+        (None, None, None, Some(cr)) => end > cr,
+        (None, None, None, None) => true,
+    }
+}
+
+fn in_range(filter: &Element, time: NaiveDateTime) -> bool {
+    let after_start = filter.attr("start").and_then(parse_time).is_none_or(|s| time >= s);
+    let before_end = filter.attr("end").and_then(parse_time).is_none_or(|e| time < e);
+    after_start && before_end
+}
+
+///DATE or DATE-TIME. floating and TZID times are taken as UTC
+fn parse_time(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim().trim_end_matches('Z');
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(value, "%Y%m%d").ok()?.and_hms_opt(0, 0, 0))
+}
+
+fn elements<'a>(parent: &'a Element, name: &'a str) -> impl Iterator<Item = &'a Element> {
+    parent.children().filter(move |c| c.name() == name)
+}
@@ -0,0 +1,98 @@
+use std::io;
+
+use reqwest::StatusCode;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+
+///just enough HTTP/1.1 for the client: Content-Length bodies and keep-alive
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    ///names are lowercase
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub(crate) struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Self {
+        Response { status, headers: vec![], body: String::new() }
+    }
+
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn body(mut self, content_type: &str, body: String) -> Self {
+        self.body = body;
+        self.header("Content-Type", content_type)
+    }
+
+    pub fn xml(self, body: String) -> Self {
+        self.body("application/xml; charset=utf-8", body)
+    }
+}
+
+///None once the client hung up
+pub(crate) async fn read_request(stream: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("bad request line {line:?}")));
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut headers = vec![];
+    loop {
+        line.clear();
+        stream.read_line(&mut line).await?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let mut req = Request { method, path, headers, body: String::new() };
+    let len = req.header("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).await?;
+    req.body = String::from_utf8_lossy(&body).into_owned();
+    Ok(Some(req))
+}
+
+pub(crate) async fn write_response(stream: &mut BufReader<TcpStream>, res: &Response) -> io::Result<()> {
+    let reason = StatusCode::from_u16(res.status)
+        .ok()
+        .and_then(|s| s.canonical_reason())
+        .unwrap_or("Unknown");
+    let mut head = format!("HTTP/1.1 {} {reason}\r\n", res.status);
+    for (name, value) in &res.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str(&format!("Content-Length: {}\r\n\r\n", res.body.len()));
+    let stream = stream.get_mut();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(res.body.as_bytes()).await?;
+    stream.flush().await
+}
@@ -0,0 +1,794 @@
+//! In-process CalDAV server for tests, behind the `mock-server` feature.
+//!
+//! Serves one user's calendars from memory on a random localhost port:
+//! principal and home-set discovery, PROPFIND, calendar-query, multiget,
+//! sync-collection and conditional PUT/DELETE. [`Auth`] makes it ask for
+//! credentials and [`Fault`]s make it misbehave.
+//! [`ReplayServer`] serves recorded responses from real servers instead.
+//!
+//! ```no_run
+//! use reminder_rs::mock::{self, MockCalendar, MockServer};
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let server = MockServer::builder().build().await?;
+//! server.add_calendar(MockCalendar::builder().id("work".to_string()).name("Work".to_string()).build());
+//! server.put("work", "a.ics", &mock::todo("a", "Write tests", &[]));
+//! println!("CALDAV_URL={}", server.url());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bon::{bon, Builder};
+use minidom::Element;
+use tokio::io::BufReader;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+use crate::caldav::parser::{format_ns_attrs, NS_C, NS_CS, NS_D, NS_I};
+use crate::caldav::component::Component;
+use http::{Request, Response};
+
+mod auth;
+mod filter;
+mod http;
+mod replay;
+
+pub use auth::Auth;
+pub use replay::{Recorded, ReplayServer};
+
+const PRINCIPAL: &str = "/principals/user/";
+const HOME: &str = "/calendars/user/";
+const SYNC_TOKEN: &str = "http://reminder-rs.invalid/sync/";
+
+///a calendar on the mock server
+#[derive(Builder, Clone, Debug)]
+pub struct MockCalendar {
+    ///last path segment, eg. `work` for `/calendars/user/work/`
+    id: String,
+    name: String,
+    color: Option<String>,
+    description: Option<String>,
+    ///component types it accepts
+    #[builder(default = vec!["VTODO".to_string(), "VEVENT".to_string()])]
+    components: Vec<String>,
+    ///refuse bigger PUTs, and advertise it
+    max_resource_size: Option<u64>,
+    order: Option<i64>,
+}
+
+///what to do to the next matching request
+#[derive(Clone, Debug)]
+pub enum Fault {
+    ///answer with this status instead
+    Status(u16),
+    ///wait this long, then answer as usual
+    Delay(Duration),
+    ///close the connection without answering
+    Disconnect,
+    ///send the request on to this url with a 307
+    Redirect(String),
+}
+
+struct Item {
+    ics: String,
+    etag: String,
+    revision: u64,
+}
+
+struct CalendarState {
+    info: MockCalendar,
+    items: BTreeMap<String, Item>,
+    ///deleted items and the revision they went away in, for sync-collection
+    deleted: BTreeMap<String, u64>,
+    revision: u64,
+}
+
+#[derive(Default)]
+struct State {
+    ///bumped on every change. ctags, sync tokens and ETags come from it
+    revision: u64,
+    calendars: BTreeMap<String, CalendarState>,
+    faults: VecDeque<(Option<String>, Fault)>,
+    log: Vec<String>,
+    ///the Authorization header of every request so far
+    authorizations: Vec<Option<String>>,
+    ///digest nonce counts already used
+    counts: BTreeSet<u32>,
+}
+
+struct Config {
+    credentials: Option<(String, String)>,
+    auth: Option<Auth>,
+    quirky_xml: bool,
+}
+
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+#[bon]
+impl MockServer {
+    ///starts listening on a random localhost port, until dropped
+    #[builder]
+    pub async fn new(
+        ///log in with these, with Basic unless `auth` says otherwise
+        username: Option<String>,
+        password: Option<String>,
+        auth: Option<Auth>,
+        ///answer like the less tidy servers: a default namespace, odd
+        ///prefixes, relative hrefs and an absolute principal url
+        #[builder(default)]
+        quirky_xml: bool,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let credentials = username.zip(password);
+        let auth = auth.or(credentials.as_ref().map(|_| Auth::Basic));
+        let config = Arc::new(Config { credentials, auth, quirky_xml });
+        let task = tokio::spawn(serve(listener, state.clone(), config));
+        Ok(MockServer { addr, state, task })
+    }
+}
+
+impl MockServer {
+    ///where to point the client
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    pub fn add_calendar(&self, info: MockCalendar) {
+        self.state.lock().unwrap().add_calendar(info);
+    }
+
+    ///stores `ics` as `file` in calendar `id`, skipping every check a PUT
+    ///would do. returns the ETag
+    pub fn put(&self, id: &str, file: &str, ics: &str) -> String {
+        self.state.lock().unwrap().store(id, file, ics)
+    }
+
+    pub fn get(&self, id: &str, file: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        Some(state.calendars.get(id)?.items.get(file)?.ics.clone())
+    }
+
+    ///names of the items in calendar `id`
+    pub fn files(&self, id: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state.calendars.get(id).map(|c| c.items.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    ///apply `fault` to the next request, or the next one with `method`
+    pub fn inject(&self, method: Option<&str>, fault: Fault) {
+        self.state.lock().unwrap().faults.push_back((method.map(str::to_string), fault));
+    }
+
+    ///`METHOD path` of every request so far
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().log.clone()
+    }
+
+    ///the Authorization header of every request so far, None where there wasn't one
+    pub fn authorizations(&self) -> Vec<Option<String>> {
+        self.state.lock().unwrap().authorizations.clone()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+///a VCALENDAR with one VTODO, plus any extra `props` lines (eg. `DUE:20240101T090000Z`)
+pub fn todo(uid: &str, summary: &str, props: &[&str]) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//reminder-rs//mock//EN".to_string(),
+        "BEGIN:VTODO".to_string(),
+        format!("UID:{uid}"),
+        "DTSTAMP:20240101T000000Z".to_string(),
+        format!("SUMMARY:{summary}"),
+    ];
+    lines.extend(props.iter().map(|p| p.to_string()));
+    lines.extend(["END:VTODO".to_string(), "END:VCALENDAR".to_string()]);
+    lines.join("\r\n") + "\r\n"
+}
+
+impl State {
+    fn add_calendar(&mut self, info: MockCalendar) {
+        self.revision += 1;
+        let cal = CalendarState { info, items: BTreeMap::new(), deleted: BTreeMap::new(), revision: self.revision };
+        self.calendars.insert(cal.info.id.clone(), cal);
+    }
+
+    fn store(&mut self, id: &str, file: &str, ics: &str) -> String {
+        self.revision += 1;
+        let revision = self.revision;
+        let etag = format!("\"{revision}\"");
+        let cal = self.calendars.get_mut(id).expect("no such mock calendar");
+        cal.items.insert(file.to_string(), Item { ics: ics.to_string(), etag: etag.clone(), revision });
+        cal.deleted.remove(file);
+        cal.revision = revision;
+        etag
+    }
+}
+
+async fn serve(listener: TcpListener, state: Arc<Mutex<State>>, config: Arc<Config>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        tokio::spawn(connection(stream, state.clone(), config.clone()));
+    }
+}
+
+async fn connection(stream: TcpStream, state: Arc<Mutex<State>>, config: Arc<Config>) {
+    let mut stream = BufReader::new(stream);
+    while let Ok(Some(req)) = http::read_request(&mut stream).await {
+        let fault = {
+            let mut state = state.lock().unwrap();
+            state.log.push(format!("{} {}", req.method, req.path));
+            state.authorizations.push(req.header("authorization").map(str::to_string));
+            let i = state.faults.iter().position(|(m, _)| m.as_ref().is_none_or(|m| *m == req.method));
+            i.and_then(|i| state.faults.remove(i)).map(|(_, f)| f)
+        };
+        let res = match fault {
+            Some(Fault::Disconnect) => return,
+            Some(Fault::Status(status)) => Response::new(status),
+            Some(Fault::Redirect(url)) => Response::new(307).header("Location", url),
+            Some(Fault::Delay(delay)) => {
+                tokio::time::sleep(delay).await;
+                handle(&state, &config, &req)
+            }
+            None => handle(&state, &config, &req),
+        };
+        if http::write_response(&mut stream, &res).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Resource {
+    WellKnown,
+    Root,
+    Principal,
+    Home,
+    Calendar(String),
+    Item(String, String),
+}
+
+fn resource(path: &str) -> Option<Resource> {
+    let path = path.split('?').next().unwrap_or_default();
+    let dir = path.trim_end_matches('/').to_string() + "/";
+    match dir.as_str() {
+        "/.well-known/caldav/" => return Some(Resource::WellKnown),
+        "/" => return Some(Resource::Root),
+        PRINCIPAL => return Some(Resource::Principal),
+        HOME => return Some(Resource::Home),
+        _ => {}
+    }
+    let rest = path.strip_prefix(HOME)?.trim_end_matches('/');
+    match rest.split_once('/') {
+        None => Some(Resource::Calendar(rest.to_string())),
+        Some((cal, item)) if !item.contains('/') => Some(Resource::Item(cal.to_string(), item.to_string())),
+        _ => None,
+    }
+}
+
+fn handle(state: &Mutex<State>, config: &Config, req: &Request) -> Response {
+    if req.method == "POST" && req.path == auth::TOKEN {
+        return auth::token(config, req);
+    }
+    let mut state = state.lock().unwrap();
+    if let Some(challenge) = auth::check(config, req, &mut state.counts) {
+        return challenge;
+    }
+    let Some(target) = resource(&req.path) else {
+        return Response::new(404);
+    };
+    let mut dav = Dav {
+        state: &mut state,
+        quirky: config.quirky_xml,
+        host: req.header("host").unwrap_or_default().to_string(),
+    };
+    match (req.method.as_str(), target) {
+        (_, Resource::WellKnown) => Response::new(301).header("Location", "/"),
+        ("OPTIONS", _) => Response::new(200)
+            .header("DAV", "1, 2, 3, calendar-access, extended-mkcol")
+            .header("Allow", "OPTIONS, GET, PUT, DELETE, PROPFIND, PROPPATCH, REPORT, MKCALENDAR"),
+        ("PROPFIND", target) => dav.propfind(req, target),
+        ("REPORT", Resource::Calendar(id)) => dav.report(req, &id),
+        ("GET", Resource::Item(id, file)) => dav.get(&id, &file),
+        ("PUT", Resource::Item(id, file)) => dav.put(req, &id, &file),
+        ("DELETE", Resource::Item(id, file)) => dav.delete(req, &id, &file),
+        ("MKCALENDAR", Resource::Calendar(id)) => dav.mkcalendar(req, &id),
+        ("PROPPATCH", Resource::Calendar(id)) => dav.proppatch(req, &id),
+        ("DELETE", Resource::Calendar(id)) => dav.delete_calendar(&id),
+        _ => Response::new(405),
+    }
+}
+
+///one request's view of the server
+struct Dav<'a> {
+    state: &'a mut State,
+    quirky: bool,
+    host: String,
+}
+
+impl Dav<'_> {
+    fn exists(&self, target: &Resource) -> bool {
+        match target {
+            Resource::Calendar(id) => self.state.calendars.contains_key(id),
+            Resource::Item(id, file) => self.state.calendars.get(id).is_some_and(|c| c.items.contains_key(file)),
+            _ => true,
+        }
+    }
+
+    fn propfind(&self, req: &Request, target: Resource) -> Response {
+        if !self.exists(&target) {
+            return Response::new(404);
+        }
+        //no body or no <d:prop> means allprop
+        let wanted = match parse_body(&req.body) {
+            Ok(root) => root.as_ref().and_then(|r| r.get_child("prop", NS_D)).map(prop_names),
+            Err(res) => return res,
+        };
+        let mut targets = vec![(target.clone(), false)];
+        if req.header("depth") == Some("1") {
+            match &target {
+                Resource::Home => {
+                    targets.extend(self.state.calendars.keys().map(|id| (Resource::Calendar(id.clone()), true)))
+                }
+                Resource::Calendar(id) => targets.extend(
+                    self.state.calendars[id].items.keys().map(|f| (Resource::Item(id.clone(), f.clone()), true)),
+                ),
+                _ => {}
+            }
+        }
+        let responses: Vec<String> = targets
+            .iter()
+            .map(|(t, child)| self.prop_response(t, *child, wanted.as_deref()))
+            .collect();
+        Response::new(207).xml(self.multistatus(&responses, ""))
+    }
+
+    fn report(&self, req: &Request, id: &str) -> Response {
+        let Some(cal) = self.state.calendars.get(id) else {
+            return Response::new(404);
+        };
+        let root = match parse_body(&req.body) {
+            Ok(Some(root)) => root,
+            Ok(None) => return Response::new(400),
+            Err(res) => return res,
+        };
+        let wanted = root.get_child("prop", NS_D).map(prop_names);
+        let wanted = wanted.as_deref();
+        let item = |file: &String| self.prop_response(&Resource::Item(id.to_string(), file.clone()), true, wanted);
+
+        match root.name() {
+            "calendar-query" => {
+                let filter = root.get_child("filter", NS_C);
+                let responses: Vec<String> = cal
+                    .items
+                    .iter()
+                    .filter(|(_, it)| match (filter, Component::parse(&it.ics)) {
+                        (Some(filter), Some(comp)) => filter::matches(filter, &comp),
+                        (None, _) => true,
+                        (Some(_), None) => false,
+                    })
+                    .map(|(file, _)| item(file))
+                    .collect();
+                Response::new(207).xml(self.multistatus(&responses, ""))
+            }
+            "calendar-multiget" => {
+                let responses: Vec<String> = root
+                    .children()
+                    .filter(|c| c.name() == "href")
+                    .map(|href| {
+                        let href = href.text();
+                        let file = href.trim_end_matches('/').rsplit('/').next().unwrap_or_default().to_string();
+                        match cal.items.contains_key(&file) {
+                            true => item(&file),
+                            false => self.status_response(&href, 404),
+                        }
+                    })
+                    .collect();
+                Response::new(207).xml(self.multistatus(&responses, ""))
+            }
+            "sync-collection" => {
+                let token = root.get_child("sync-token", NS_D).map(|t| t.text()).unwrap_or_default();
+                let since = match token.as_str() {
+                    "" => 0,
+                    token => match token.strip_prefix(SYNC_TOKEN).and_then(|r| r.parse().ok()) {
+                        Some(since) => since,
+                        None => return self.error(403, NS_D, "valid-sync-token"),
+                    },
+                };
+                let mut responses: Vec<String> = cal
+                    .items
+                    .iter()
+                    .filter(|(_, it)| it.revision > since)
+                    .map(|(file, _)| item(file))
+                    .collect();
+                //an initial sync has nothing to delete
+                if since > 0 {
+                    responses.extend(
+                        cal.deleted
+                            .iter()
+                            .filter(|(_, rev)| **rev > since)
+                            .map(|(file, _)| self.status_response(&format!("{HOME}{id}/{file}"), 404)),
+                    );
+                }
+                let token = format!("<{0}>{SYNC_TOKEN}{1}</{0}>", self.tag(NS_D, "sync-token"), cal.revision);
+                Response::new(207).xml(self.multistatus(&responses, &token))
+            }
+            _ => self.error(403, NS_D, "supported-report"),
+        }
+    }
+
+    fn get(&self, id: &str, file: &str) -> Response {
+        match self.state.calendars.get(id).and_then(|c| c.items.get(file)) {
+            Some(item) => Response::new(200)
+                .header("ETag", item.etag.clone())
+                .body("text/calendar; charset=utf-8", item.ics.clone()),
+            None => Response::new(404),
+        }
+    }
+
+    fn put(&mut self, req: &Request, id: &str, file: &str) -> Response {
+        let Some(cal) = self.state.calendars.get(id) else {
+            return Response::new(409);
+        };
+        let existing = cal.items.get(file).map(|it| it.etag.as_str());
+        if let Some(res) = precondition(req, existing) {
+            return res;
+        }
+        let Some(root) = Component::parse(&req.body).filter(|c| c.name == "VCALENDAR") else {
+            return self.error(403, NS_C, "valid-calendar-data");
+        };
+        let unsupported = root
+            .children
+            .iter()
+            .any(|c| c.name != "VTIMEZONE" && !cal.info.components.contains(&c.name));
+        if unsupported {
+            return self.error(403, NS_C, "supported-calendar-component");
+        }
+        if cal.info.max_resource_size.is_some_and(|max| req.body.len() as u64 > max) {
+            return self.error(403, NS_C, "max-resource-size");
+        }
+        let status = if existing.is_some() { 204 } else { 201 };
+        let etag = self.state.store(id, file, &req.body);
+        Response::new(status).header("ETag", etag)
+    }
+
+    fn delete(&mut self, req: &Request, id: &str, file: &str) -> Response {
+        let Some(existing) = self.state.calendars.get(id).and_then(|c| c.items.get(file)) else {
+            return Response::new(404);
+        };
+        if let Some(res) = precondition(req, Some(&existing.etag)) {
+            return res;
+        }
+        self.state.revision += 1;
+        let revision = self.state.revision;
+        let cal = self.state.calendars.get_mut(id).unwrap();
+        cal.items.remove(file);
+        cal.deleted.insert(file.to_string(), revision);
+        cal.revision = revision;
+        Response::new(204)
+    }
+
+    fn mkcalendar(&mut self, req: &Request, id: &str) -> Response {
+        if self.state.calendars.contains_key(id) {
+            return self.error(403, NS_D, "resource-must-be-null");
+        }
+        let root = match parse_body(&req.body) {
+            Ok(root) => root,
+            Err(res) => return res,
+        };
+        let prop = root
+            .as_ref()
+            .and_then(|r| r.get_child("set", NS_D))
+            .and_then(|s| s.get_child("prop", NS_D));
+        let text = |name, ns| prop.and_then(|p| p.get_child(name, ns)).map(|e| e.text());
+        let components = prop
+            .and_then(|p| p.get_child("supported-calendar-component-set", NS_C))
+            .map(|set| set.children().filter_map(|c| c.attr("name")).map(str::to_string).collect());
+        let info = MockCalendar::builder()
+            .id(id.to_string())
+            .name(text("displayname", NS_D).unwrap_or(id.to_string()))
+            .maybe_color(text("calendar-color", NS_I))
+            .maybe_description(text("calendar-description", NS_C))
+            .maybe_components(components)
+            .maybe_order(text("calendar-order", NS_I).and_then(|o| o.trim().parse().ok()))
+            .build();
+        self.state.add_calendar(info);
+        Response::new(201)
+    }
+
+    ///all or nothing, as RFC 4918 9.2 asks: if one property can't be set,
+    ///the rest come back 424 Failed Dependency
+    fn proppatch(&mut self, req: &Request, id: &str) -> Response {
+        if !self.state.calendars.contains_key(id) {
+            return Response::new(404);
+        }
+        let Ok(Some(root)) = parse_body(&req.body) else {
+            return Response::new(400);
+        };
+        //(name, namespace, the new value or None to remove it)
+        let mut changes = vec![];
+        for op in root.children() {
+            let set = op.is("set", NS_D);
+            if !set && !op.is("remove", NS_D) {
+                continue;
+            }
+            for prop in op.children().filter(|p| p.is("prop", NS_D)).flat_map(|p| p.children()) {
+                changes.push((prop.name().to_string(), prop.ns(), set.then(|| prop.text())));
+            }
+        }
+        let known = |name: &str, ns: &str| {
+            matches!(
+                (name, ns),
+                ("displayname", NS_D) | ("calendar-color", NS_I) | ("calendar-description", NS_C) | ("calendar-order", NS_I)
+            )
+        };
+        let ok = changes.iter().all(|(name, ns, _)| known(name, ns));
+        if ok {
+            self.state.revision += 1;
+            let revision = self.state.revision;
+            let cal = self.state.calendars.get_mut(id).unwrap();
+            for (name, _, value) in &changes {
+                let info = &mut cal.info;
+                match name.as_str() {
+                    "displayname" => info.name = value.clone().unwrap_or(info.id.clone()),
+                    "calendar-color" => info.color = value.clone(),
+                    "calendar-description" => info.description = value.clone(),
+                    _ => info.order = value.as_ref().and_then(|o| o.trim().parse().ok()),
+                }
+            }
+            cal.revision = revision;
+        }
+
+        let mut by_status: BTreeMap<u16, String> = BTreeMap::new();
+        for (name, ns, _) in &changes {
+            let status = match (ok, known(name, ns)) {
+                (true, _) => 200,
+                (false, true) => 424,
+                (false, false) => 403,
+            };
+            by_status.entry(status).or_default().push_str(&format!(r#"<{name} xmlns="{ns}"/>"#));
+        }
+        let propstats: String = by_status.iter().map(|(status, props)| self.propstat(props, *status)).collect();
+        let href = self.href(&Resource::Calendar(id.to_string()), false);
+        let response = format!("<{0}>{1}{propstats}</{0}>", self.tag(NS_D, "response"), self.href_xml(&href));
+        Response::new(207).xml(self.multistatus(&[response], ""))
+    }
+
+    fn delete_calendar(&mut self, id: &str) -> Response {
+        match self.state.calendars.remove(id) {
+            Some(_) => Response::new(204),
+            None => Response::new(404),
+        }
+    }
+
+    ///every property we know of for `target`, as (name, namespace, inner xml)
+    fn props(&self, target: &Resource) -> Vec<(&'static str, &'static str, String)> {
+        let mut props = vec![("current-user-principal", NS_D, self.href_xml(&self.principal_href()))];
+        match target {
+            Resource::Root | Resource::WellKnown => props.push(("resourcetype", NS_D, String::new())),
+            Resource::Principal => {
+                props.push(("resourcetype", NS_D, format!("<{}/>", self.tag(NS_D, "principal"))));
+                props.push(("displayname", NS_D, "user".to_string()));
+                props.push(("calendar-home-set", NS_C, self.href_xml(HOME)));
+            }
+            Resource::Home => {
+                props.push(("resourcetype", NS_D, format!("<{}/>", self.tag(NS_D, "collection"))));
+                props.push(("displayname", NS_D, "Calendars".to_string()));
+            }
+            Resource::Calendar(id) => {
+                let cal = &self.state.calendars[id];
+                let kind = format!("<{}/><{}/>", self.tag(NS_D, "collection"), self.tag(NS_C, "calendar"));
+                let comps: String = cal
+                    .info
+                    .components
+                    .iter()
+                    .map(|c| format!(r#"<{} name="{c}"/>"#, self.tag(NS_C, "comp")))
+                    .collect();
+                props.push(("resourcetype", NS_D, kind));
+                props.push(("displayname", NS_D, escape(&cal.info.name)));
+                props.push(("getctag", NS_CS, cal.revision.to_string()));
+                props.push(("sync-token", NS_D, format!("{SYNC_TOKEN}{}", cal.revision)));
+                props.push(("supported-calendar-component-set", NS_C, comps));
+                props.push(("supported-report-set", NS_D, self.supported_reports()));
+                props.push((
+                    "supported-calendar-data",
+                    NS_C,
+                    format!(r#"<{} content-type="text/calendar" version="2.0"/>"#, self.tag(NS_C, "calendar-data")),
+                ));
+                if let Some(max) = cal.info.max_resource_size {
+                    props.push(("max-resource-size", NS_C, max.to_string()));
+                }
+                if let Some(color) = &cal.info.color {
+                    props.push(("calendar-color", NS_I, escape(color)));
+                }
+                if let Some(description) = &cal.info.description {
+                    props.push(("calendar-description", NS_C, escape(description)));
+                }
+                if let Some(order) = cal.info.order {
+                    props.push(("calendar-order", NS_I, order.to_string()));
+                }
+            }
+            Resource::Item(id, file) => {
+                let item = &self.state.calendars[id].items[file];
+                props.push(("resourcetype", NS_D, String::new()));
+                props.push(("getetag", NS_D, escape(&item.etag)));
+                props.push(("getcontenttype", NS_D, "text/calendar; charset=utf-8".to_string()));
+                props.push(("calendar-data", NS_C, escape(&item.ics)));
+            }
+        }
+        props
+    }
+
+    fn supported_reports(&self) -> String {
+        [(NS_C, "calendar-query"), (NS_C, "calendar-multiget"), (NS_D, "sync-collection")]
+            .iter()
+            .map(|(ns, name)| {
+                format!(
+                    "<{0}><{1}><{2}/></{1}></{0}>",
+                    self.tag(NS_D, "supported-report"),
+                    self.tag(NS_D, "report"),
+                    self.tag(ns, name)
+                )
+            })
+            .collect()
+    }
+
+    ///a `<d:response>` with the `wanted` props (all of them if None) that
+    ///exist in a 200 propstat and the rest in a 404 one
+    fn prop_response(&self, target: &Resource, child: bool, wanted: Option<&[(String, String)]>) -> String {
+        let props = self.props(target);
+        let mut found = String::new();
+        let mut missing = String::new();
+        match wanted {
+            None => {
+                for (name, ns, value) in &props {
+                    found.push_str(&self.element(ns, name, value));
+                }
+            }
+            Some(wanted) => {
+                for (name, ns) in wanted {
+                    match props.iter().find(|(n, s, _)| n == name && s == ns) {
+                        Some((name, ns, value)) => found.push_str(&self.element(ns, name, value)),
+                        None => missing.push_str(&format!(r#"<{name} xmlns="{ns}"/>"#)),
+                    }
+                }
+            }
+        }
+        let mut propstats = self.propstat(&found, 200);
+        if !missing.is_empty() {
+            propstats.push_str(&self.propstat(&missing, 404));
+        }
+        let href = self.href(target, child);
+        format!("<{0}>{1}{propstats}</{0}>", self.tag(NS_D, "response"), self.href_xml(&href))
+    }
+
+    fn propstat(&self, props: &str, status: u16) -> String {
+        format!(
+            "<{0}><{1}>{props}</{1}>{2}</{0}>",
+            self.tag(NS_D, "propstat"),
+            self.tag(NS_D, "prop"),
+            self.status(status)
+        )
+    }
+
+    fn status_response(&self, href: &str, status: u16) -> String {
+        format!("<{0}>{1}{2}</{0}>", self.tag(NS_D, "response"), self.href_xml(href), self.status(status))
+    }
+
+    fn status(&self, status: u16) -> String {
+        let reason = reqwest::StatusCode::from_u16(status)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or_default();
+        format!("<{0}>HTTP/1.1 {status} {reason}</{0}>", self.tag(NS_D, "status"))
+    }
+
+    fn multistatus(&self, responses: &[String], extra: &str) -> String {
+        let ns = match self.quirky {
+            true => format!(r#"xmlns="{NS_D}" xmlns:C="{NS_C}" xmlns:CS="{NS_CS}" xmlns:ICAL="{NS_I}""#),
+            false => format_ns_attrs(),
+        };
+        let tag = self.tag(NS_D, "multistatus");
+        format!(r#"<?xml version="1.0" encoding="utf-8"?><{tag} {ns}>{}{extra}</{tag}>"#, responses.concat())
+    }
+
+    fn error(&self, status: u16, ns: &str, condition: &str) -> Response {
+        let body = format!(r#"<?xml version="1.0" encoding="utf-8"?><error xmlns="{NS_D}"><{condition} xmlns="{ns}"/></error>"#);
+        Response::new(status).xml(body)
+    }
+
+    fn element(&self, ns: &str, name: &str, value: &str) -> String {
+        let tag = self.tag(ns, name);
+        match value.is_empty() {
+            true => format!("<{tag}/>"),
+            false => format!("<{tag}>{value}</{tag}>"),
+        }
+    }
+
+    ///the prefixed name, which depends on how quirky we're being
+    fn tag(&self, ns: &str, name: &str) -> String {
+        let prefix = match (self.quirky, ns) {
+            (false, NS_D) => "d:",
+            (false, NS_C) => "c:",
+            (false, NS_CS) => "cs:",
+            (false, _) => "i:",
+            (true, NS_D) => "",
+            (true, NS_C) => "C:",
+            (true, NS_CS) => "CS:",
+            (true, _) => "ICAL:",
+        };
+        format!("{prefix}{name}")
+    }
+
+    fn href_xml(&self, href: &str) -> String {
+        format!("<{0}>{1}</{0}>", self.tag(NS_D, "href"), escape(href))
+    }
+
+    fn principal_href(&self) -> String {
+        match self.quirky {
+            true => format!("http://{}{PRINCIPAL}", self.host),
+            false => PRINCIPAL.to_string(),
+        }
+    }
+
+    ///quirky servers give children relative to the collection
+    fn href(&self, target: &Resource, child: bool) -> String {
+        let (relative, absolute) = match target {
+            Resource::WellKnown => ("".to_string(), "/.well-known/caldav".to_string()),
+            Resource::Root => ("".to_string(), "/".to_string()),
+            Resource::Principal => ("".to_string(), PRINCIPAL.to_string()),
+            Resource::Home => ("".to_string(), HOME.to_string()),
+            Resource::Calendar(id) => (format!("{id}/"), format!("{HOME}{id}/")),
+            Resource::Item(id, file) => (file.clone(), format!("{HOME}{id}/{file}")),
+        };
+        match self.quirky && child {
+            true => relative,
+            false => absolute,
+        }
+    }
+}
+
+///None if the request's If-Match/If-None-Match hold for `etag`
+fn precondition(req: &Request, etag: Option<&str>) -> Option<Response> {
+    let failed = match (req.header("if-match"), req.header("if-none-match")) {
+        (Some("*"), _) => etag.is_none(),
+        (Some(wanted), _) => etag != Some(wanted),
+        (None, Some("*")) => etag.is_some(),
+        (None, Some(unwanted)) => etag == Some(unwanted),
+        (None, None) => false,
+    };
+    failed.then(|| Response::new(412))
+}
+
+///an empty body is fine, a broken one is a 400
+fn parse_body(body: &str) -> Result<Option<Element>, Response> {
+    if body.trim().is_empty() {
+        return Ok(None);
+    }
+    body.parse().map(Some).map_err(|_| Response::new(400))
+}
+
+fn prop_names(prop: &Element) -> Vec<(String, String)> {
+    prop.children().map(|p| (p.name().to_string(), p.ns())).collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,95 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::BufReader;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+use super::http::{self, Request, Response};
+
+///a recorded response, served for every request with the same method, path
+///and (if given) Depth
+#[derive(Clone, Debug)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    ///servers where the principal is also the home get asked about the same
+    ///path at Depth 0 and 1
+    pub depth: Option<String>,
+    pub status: u16,
+    pub body: String,
+}
+
+///serves [`Recorded`] responses, eg. ones captured from a real server.
+///anything that wasn't recorded gets a 404. `{base}` in a body is replaced
+///with the server's url (without the trailing slash), for responses that
+///have absolute urls in them
+pub struct ReplayServer {
+    addr: SocketAddr,
+    log: Arc<Mutex<Vec<String>>>,
+    task: JoinHandle<()>,
+}
+
+impl ReplayServer {
+    pub async fn start(recorded: Vec<Recorded>) -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let log = Arc::new(Mutex::new(vec![]));
+        let base = format!("http://{addr}");
+        let recorded: Arc<Vec<_>> = Arc::new(
+            recorded
+                .into_iter()
+                .map(|r| Recorded { body: r.body.replace("{base}", &base), ..r })
+                .collect(),
+        );
+        let task = tokio::spawn({
+            let log = log.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let (recorded, log) = (recorded.clone(), log.clone());
+                    tokio::spawn(async move {
+                        let mut stream = BufReader::new(stream);
+                        while let Ok(Some(req)) = http::read_request(&mut stream).await {
+                            log.lock().unwrap().push(format!("{} {}", req.method, req.path));
+                            let res = match recorded.iter().find(|r| matches(r, &req)) {
+                                Some(r) => Response::new(r.status).xml(r.body.clone()),
+                                None => Response::new(404),
+                            };
+                            if http::write_response(&mut stream, &res).await.is_err() {
+                                return;
+                            }
+                        }
+                    });
+                }
+            }
+        });
+        Ok(ReplayServer { addr, log, task })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    ///`METHOD path` of every request so far
+    pub fn requests(&self) -> Vec<String> {
+        self.log.lock().unwrap().clone()
+    }
+}
+
+impl Drop for ReplayServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn matches(recorded: &Recorded, req: &Request) -> bool {
+    recorded.method == req.method
+        && same_path(&recorded.path, &req.path)
+        && recorded.depth.as_ref().is_none_or(|depth| req.header("depth") == Some(depth))
+}
+
+///ignoring the trailing slash, which clients and servers disagree on
+fn same_path(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/') == b.trim_end_matches('/')
+}
@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use reminder_rs::caldav::alarm::{add_alarms, remove_alarms, AlarmSpec, Related, Trigger};
+use reminder_rs::caldav::component::{escape, Component, Prop};
+use reminder_rs::caldav::datetime::DateValue;
+use reminder_rs::caldav::todo::{new_todo, new_uid, set_property, touched};
+use reminder_rs::caldav::CalendarTodo;
+use reminder_rs::store::{self, TodoStore};
+
+use crate::actions::{self, short_id};
+use crate::args::{EditCommand, NewCommand, TodoFields};
+use crate::exit::CliError;
+use crate::find_calendar;
+use crate::output::{Output, TodoRow};
+use crate::when;
+
+///makes the reminder and prints it, or in JSON the way `info --json` would
+pub async fn create(store: &dyn TodoStore, command: &NewCommand, output: &Output, jobs: usize) -> anyhow::Result<()> {
+    let Some(summary) = command.summary.as_deref().filter(|_| !command.tui) else {
+        return Err(CliError::Unsupported("`new --tui` isn't implemented yet, give it a summary instead".to_string()).into());
+    };
+    let parent = match &command.parent {
+        Some(id) => Some(actions::resolve(store, std::slice::from_ref(id), jobs).await?.remove(0)?),
+        None => None,
+    };
+    let cal = match (&command.calendar, &parent) {
+        (Some(name), _) => find_calendar(store, name).await?,
+        (None, Some(parent)) => parent.cal.clone(),
+        (None, None) => store::todo_calendars(store).await.into_iter().next().ok_or(CliError::NoCalendars)?,
+    };
+    if let Some(parent) = &parent {
+        let parent_cal = parent.cal.read().await;
+        if parent_cal.url != cal.read().await.url {
+            return Err(CliError::ParentElsewhere(parent_cal.name.clone()).into());
+        }
+    }
+
+    let uid = new_uid();
+    let mut ics = set_fields(&new_todo(&uid, summary, Utc::now()), &command.fields, summary)?;
+    if let Some(parent) = parent.as_ref().and_then(|p| p.todo().uid()) {
+        ics = set_property(&ics, "RELATED-TO;RELTYPE=PARENT", Some(&parent));
+    }
+
+    let cal = cal.read().await;
+    let todo = store.create(&cal, &uid, &ics).await?;
+    match output.is_json() {
+        true => output.json_object(&TodoRow::new(&cal, &todo, &[])),
+        false => {
+            println!("Created {}: {summary}", short_id(&todo));
+            Ok(())
+        }
+    }
+}
+
+///what `edit` does to each reminder it's given
+#[derive(Debug, Clone, Default)]
+pub struct Changes {
+    pub summary: Option<String>,
+    pub fields: TodoFields,
+    pub no_alarms: bool,
+    pub set: Vec<Setting>,
+}
+
+impl Changes {
+    pub fn new(command: &EditCommand) -> Changes {
+        Changes {
+            summary: command.summary.clone(),
+            fields: command.fields.clone(),
+            no_alarms: command.no_alarms,
+            set: command.set.clone(),
+        }
+    }
+}
+
+///one `edit --set name=value`. None removes the field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    Summary(String),
+    Due(Option<DateValue>),
+    Start(Option<DateValue>),
+    Priority(Option<u8>),
+    Location(Option<String>),
+    Description(Option<String>),
+    Categories(Vec<String>),
+}
+
+impl Setting {
+    ///`due=friday`, or `due=` to remove it. for clap
+    pub fn parse(text: &str) -> Result<Setting, String> {
+        let (name, value) = text.split_once('=').ok_or(format!("invalid {text}, expected name=value, eg. priority=1"))?;
+        let value = value.trim();
+        let text = || (!value.is_empty()).then(|| value.to_string());
+        let date = || match value {
+            "" => Ok(None),
+            _ => when::parse(value).map(Some),
+        };
+        Ok(match name.trim().to_ascii_lowercase().as_str() {
+            "summary" if value.is_empty() => return Err("the summary can't be empty".to_string()),
+            "summary" => Setting::Summary(value.to_string()),
+            "due" => Setting::Due(date()?),
+            "start" => Setting::Start(date()?),
+            "priority" => Setting::Priority(match value {
+                "" => None,
+                _ => Some(value.parse().ok().filter(|p| *p <= 9).ok_or("priority is 1, the highest, to 9, or 0 for none")?),
+            }),
+            "location" => Setting::Location(text()),
+            "description" => Setting::Description(text()),
+            "category" | "categories" => Setting::Categories(
+                value.split(',').map(str::trim).filter(|c| !c.is_empty()).map(str::to_string).collect(),
+            ),
+            name => {
+                return Err(format!(
+                    "unknown field {name}, expected summary, due, start, priority, location, description or categories"
+                ))
+            }
+        })
+    }
+
+    fn apply(&self, ics: &str) -> String {
+        let date = |name, date: &Option<DateValue>| match date {
+            Some(date) => {
+                let (name, value) = date.property(name);
+                set_property(ics, &name, Some(&value))
+            }
+            None => set_property(ics, name, None),
+        };
+        let text = |name, text: &Option<String>| set_property(ics, name, text.as_deref().map(escape).as_deref());
+        match self {
+            Setting::Summary(summary) => set_property(ics, "SUMMARY", Some(&escape(summary))),
+            Setting::Due(due) => date("DUE", due),
+            Setting::Start(start) => date("DTSTART", start),
+            Setting::Priority(priority) => set_property(ics, "PRIORITY", priority.map(|p| p.to_string()).as_deref()),
+            Setting::Location(location) => text("LOCATION", location),
+            Setting::Description(description) => text("DESCRIPTION", description),
+            Setting::Categories(categories) => {
+                let categories: Vec<String> = categories.iter().map(|c| escape(c)).collect();
+                set_property(ics, "CATEGORIES", Some(&categories.join(",")).filter(|c| !c.is_empty()).map(String::as_str))
+            }
+        }
+    }
+}
+
+///`todo`'s iCalendar object with `changes` made as of `now`, or None if
+///they don't change anything
+pub fn edited(todo: &CalendarTodo, changes: &Changes, now: DateTime<Utc>) -> anyhow::Result<Option<String>> {
+    let mut ics = todo.ics.clone();
+    if let Some(summary) = &changes.summary {
+        ics = set_property(&ics, "SUMMARY", Some(&escape(summary)));
+    }
+    for setting in &changes.set {
+        ics = setting.apply(&ics);
+    }
+    if changes.no_alarms {
+        ics = remove_alarms(&ics);
+    }
+    let vtodo = Component::parse(&ics).and_then(|root| root.child("VTODO").cloned());
+    let summary = vtodo.and_then(|vtodo| vtodo.prop("SUMMARY").map(Prop::text)).unwrap_or_default();
+    let ics = set_fields(&ics, &changes.fields, &summary)?;
+    Ok((ics != todo.ics).then(|| touched(&ics, now)))
+}
+
+///`ics` with what `fields` has set. `summary` is what alarms show.
+///alarms relative to a DTSTART or DUE that isn't there fail, since they'd never go off
+fn set_fields(ics: &str, fields: &TodoFields, summary: &str) -> anyhow::Result<String> {
+    let mut ics = ics.to_string();
+    for (name, date) in [("DTSTART", &fields.start), ("DUE", &fields.due)] {
+        if let Some(date) = date {
+            let (name, value) = date.property(name);
+            ics = set_property(&ics, &name, Some(&value));
+        }
+    }
+    let text = [("LOCATION", &fields.location), ("DESCRIPTION", &fields.description)];
+    for (name, value) in text {
+        if let Some(value) = value {
+            ics = set_property(&ics, name, Some(&escape(value)));
+        }
+    }
+    if let Some(priority) = fields.priority {
+        ics = set_property(&ics, "PRIORITY", Some(&priority.to_string()));
+    }
+    if !fields.categories.is_empty() {
+        let categories: Vec<String> = fields.categories.iter().map(|c| escape(c)).collect();
+        ics = set_property(&ics, "CATEGORIES", Some(&categories.join(",")));
+    }
+
+    if fields.alarms.is_empty() {
+        return Ok(ics);
+    }
+    let vtodo = Component::parse(&ics).and_then(|root| root.child("VTODO").cloned());
+    let has = |name| vtodo.as_ref().is_some_and(|vtodo| vtodo.prop(name).is_some());
+    for trigger in &fields.alarms {
+        match trigger {
+            Trigger::Relative { related: Related::Due, .. } if !has("DUE") => return Err(CliError::NoAlarmAnchor("due").into()),
+            Trigger::Relative { related: Related::Start, .. } if !has("DTSTART") => {
+                return Err(CliError::NoAlarmAnchor("start").into())
+            }
+            _ => {}
+        }
+    }
+    let alarms: Vec<AlarmSpec> =
+        fields.alarms.iter().map(|&trigger| AlarmSpec { trigger, repeat: fields.alarm_repeat }).collect();
+    Ok(add_alarms(&ics, &alarms, summary))
+}
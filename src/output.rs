@@ -0,0 +1,391 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+use clap::ValueEnum;
+use reminder_rs::caldav::datetime::DateValue;
+use reminder_rs::caldav::{Calendar, CalendarCapabilities, CalendarTodo, ServerInfo, TodoDetails, TodoState};
+use serde::Serialize;
+
+use crate::actions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// One JSON array
+    Json,
+    /// One JSON object per line
+    Ndjson,
+    /// Aligned columns with a header, colored on a terminal
+    Table,
+    /// Tab separated columns, no header or colors
+    Plain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Column {
+    Id,
+    Uid,
+    Calendar,
+    Summary,
+    Status,
+    Priority,
+    Percent,
+    Start,
+    Due,
+    Completed,
+    Categories,
+    Location,
+}
+
+impl Column {
+    fn title(&self) -> &'static str {
+        match self {
+            Column::Id => "ID",
+            Column::Uid => "UID",
+            Column::Calendar => "CALENDAR",
+            Column::Summary => "SUMMARY",
+            Column::Status => "STATUS",
+            Column::Priority => "PRIORITY",
+            Column::Percent => "DONE",
+            Column::Start => "START",
+            Column::Due => "DUE",
+            Column::Completed => "COMPLETED",
+            Column::Categories => "CATEGORIES",
+            Column::Location => "LOCATION",
+        }
+    }
+}
+
+pub const DEFAULT_COLUMNS: [Column; 4] = [Column::Id, Column::Calendar, Column::Summary, Column::Due];
+
+///how everything that isn't an error or a question gets printed
+pub struct Output {
+    pub format: Format,
+    ///ANSI escapes allowed
+    pub color: bool,
+    pub columns: Vec<Column>,
+}
+
+///a todo as `list` and friends print it. the JSON is [`TodoDetails`]
+///plus the calendar's name
+#[derive(Clone, Serialize)]
+pub struct TodoRow {
+    pub calendar: String,
+    ///of the calendar, for the swatch in front of the row
+    #[serde(skip)]
+    rgb: (u8, u8, u8),
+    #[serde(skip)]
+    id: String,
+    ///how many of its subtasks are done, when shown as a tree
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subtasks: Option<Progress>,
+    ///how far it's indented under its parent, when shown as a tree
+    #[serde(skip)]
+    pub depth: usize,
+    ///UID of the row it's shown under. not always `details.parent`, that
+    ///one might not be shown
+    #[serde(skip)]
+    pub tree_parent: Option<String>,
+    #[serde(flatten)]
+    pub details: TodoDetails,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Progress {
+    pub done: usize,
+    ///cancelled ones don't count
+    pub total: usize,
+}
+
+impl TodoRow {
+    ///`subtasks` are the ones right under it
+    pub fn new(cal: &Calendar, todo: &CalendarTodo, subtasks: &[&CalendarTodo]) -> TodoRow {
+        TodoRow {
+            calendar: cal.name.clone(),
+            rgb: cal.rgb(),
+            id: actions::short_id(todo),
+            subtasks: None,
+            depth: 0,
+            tree_parent: None,
+            details: TodoDetails::new(todo, subtasks),
+        }
+    }
+
+    fn cell(&self, column: Column) -> String {
+        let details = &self.details;
+        let date = |date: &Option<DateValue>| date.as_ref().map(|d| d.local()).unwrap_or_default();
+        let text = match column {
+            Column::Id => self.id.clone(),
+            Column::Uid => details.uid.clone().unwrap_or_default(),
+            Column::Calendar => self.calendar.clone(),
+            Column::Summary => {
+                let summary = details.summary.clone().unwrap_or_default();
+                match self.subtasks {
+                    Some(Progress { done, total }) => format!("{}{summary} ({done}/{total})", "  ".repeat(self.depth)),
+                    None => format!("{}{summary}", "  ".repeat(self.depth)),
+                }
+            }
+            Column::Status => state_name(details.state).to_string(),
+            Column::Priority => details.priority.filter(|p| *p > 0).map(|p| p.to_string()).unwrap_or_default(),
+            Column::Percent => details.percent_complete.map(|p| format!("{p}%")).unwrap_or_default(),
+            Column::Start => date(&details.start),
+            Column::Due => date(&details.due),
+            Column::Completed => date(&details.completed),
+            Column::Categories => details.categories.join(","),
+            Column::Location => details.location.clone().unwrap_or_default(),
+        };
+        //one line per row and one tab per column, whatever is in the text
+        text.replace(|c: char| c.is_control(), " ")
+    }
+}
+
+///rows under a heading, eg. `Overdue`. None for no heading at all
+pub struct Group {
+    pub title: Option<String>,
+    pub rows: Vec<TodoRow>,
+}
+
+#[derive(Serialize)]
+pub struct CalendarJson<'a> {
+    pub name: &'a str,
+    pub url: &'a str,
+    pub color: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub order: Option<i64>,
+    ///false for calendars that only hold events
+    pub todos: bool,
+}
+
+impl<'a> CalendarJson<'a> {
+    pub fn new(cal: &'a Calendar) -> Self {
+        CalendarJson {
+            name: &cal.name,
+            url: &cal.url,
+            color: cal.color.as_deref(),
+            description: cal.description.as_deref(),
+            order: cal.order,
+            todos: cal.supports_todo,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ServerInfoJson<'a> {
+    ///None for a local vdir
+    pub server: Option<&'a ServerInfo>,
+    pub calendars: Vec<CalendarInfoJson>,
+}
+
+#[derive(Serialize)]
+pub struct CalendarInfoJson {
+    pub name: String,
+    pub url: String,
+    pub todos: bool,
+    pub capabilities: CalendarCapabilities,
+}
+
+///what happened to one of the ids given to done, cancel, delete and so on
+#[derive(Serialize)]
+pub struct ActionResult {
+    pub id: String,
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    ///`done`, `cancelled`, `reopened`, `deleted`, `unchanged` or `failed`
+    pub result: &'static str,
+    ///what it printed, or the error
+    pub message: String,
+}
+
+impl Output {
+    ///`format` None picks table on a terminal, plain otherwise or if NO_COLOR
+    ///is set. colors only ever go to a terminal
+    pub fn new(format: Option<Format>, columns: Option<Vec<Column>>) -> Output {
+        let terminal = io::stdout().is_terminal();
+        let no_color = env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let format = format.unwrap_or(match terminal && !no_color {
+            true => Format::Table,
+            false => Format::Plain,
+        });
+        Output {
+            format,
+            color: format == Format::Table && terminal && !no_color,
+            columns: columns.filter(|c| !c.is_empty()).unwrap_or(DEFAULT_COLUMNS.to_vec()),
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        matches!(self.format, Format::Json | Format::Ndjson)
+    }
+
+    ///a calendar's name, with its color if that's allowed
+    pub fn calendar_name(&self, cal: &Calendar) -> String {
+        match self.color {
+            true => cal.fancy_name(),
+            false => cal.name.clone(),
+        }
+    }
+
+    ///`items` as one array, or one line each for ndjson
+    pub fn json<T: Serialize>(&self, items: &[T]) -> anyhow::Result<()> {
+        match self.format {
+            Format::Ndjson => {
+                for item in items {
+                    println!("{}", serde_json::to_string(item)?);
+                }
+            }
+            _ => println!("{}", serde_json::to_string_pretty(items)?),
+        }
+        Ok(())
+    }
+
+    ///a single object, on one line for ndjson
+    pub fn json_object<T: Serialize>(&self, item: &T) -> anyhow::Result<()> {
+        match self.format {
+            Format::Ndjson => println!("{}", serde_json::to_string(item)?),
+            _ => println!("{}", serde_json::to_string_pretty(item)?),
+        }
+        Ok(())
+    }
+
+    pub fn todos(&self, rows: Vec<TodoRow>) -> anyhow::Result<()> {
+        self.groups(&[Group { title: None, rows }])
+    }
+
+    ///in JSON the rows of titled groups get a `group` field, and in plain
+    ///output a first column with the title
+    pub fn groups(&self, groups: &[Group]) -> anyhow::Result<()> {
+        match self.format {
+            Format::Json | Format::Ndjson => {
+                #[derive(Serialize)]
+                struct Grouped<'a> {
+                    #[serde(skip_serializing_if = "Option::is_none")]
+                    group: Option<&'a str>,
+                    #[serde(flatten)]
+                    row: &'a TodoRow,
+                }
+                let items: Vec<Grouped> = groups
+                    .iter()
+                    .flat_map(|g| g.rows.iter().map(|row| Grouped { group: g.title.as_deref(), row }))
+                    .collect();
+                self.json(&items)?
+            }
+            Format::Plain => {
+                for group in groups {
+                    for row in &group.rows {
+                        let mut cells: Vec<String> = group.title.iter().cloned().collect();
+                        cells.extend(self.columns.iter().map(|c| row.cell(*c)));
+                        println!("{}", cells.join("\t"));
+                    }
+                }
+            }
+            Format::Table => self.table(groups),
+        }
+        Ok(())
+    }
+
+    ///one set of column widths for every group, so they line up
+    fn table(&self, groups: &[Group]) {
+        if groups.iter().all(|g| g.rows.is_empty()) {
+            return;
+        }
+        let header: Vec<String> = self.columns.iter().map(|c| c.title().to_string()).collect();
+        let cells: Vec<Vec<Vec<String>>> = groups
+            .iter()
+            .map(|g| g.rows.iter().map(|row| self.columns.iter().map(|c| row.cell(*c)).collect()).collect())
+            .collect();
+        let widths = widths(&header, cells.iter().flatten());
+        let line = |cells: &[String]| line(cells, &widths);
+
+        //room for the swatch
+        let indent = if self.color { "  " } else { "" };
+        println!("{indent}{}", self.bold(&line(&header)));
+        for (group, rows) in groups.iter().zip(&cells) {
+            if group.rows.is_empty() {
+                continue;
+            }
+            if let Some(title) = &group.title {
+                println!("\n{}", self.bold(title));
+            }
+            for (row, cells) in group.rows.iter().zip(rows) {
+                match self.color {
+                    true => {
+                        let (r, g, b) = row.rgb;
+                        println!("\x1B[48;2;{r};{g};{b}m \x1B[0m {}", line(cells));
+                    }
+                    false => println!("{}", line(cells)),
+                }
+            }
+        }
+    }
+
+    ///anything else that's a table: aligned under `header`, or tab separated
+    ///without it for plain
+    pub fn rows(&self, header: &[&str], rows: &[Vec<String>]) {
+        if self.format != Format::Table {
+            for row in rows {
+                println!("{}", row.join("\t"));
+            }
+            return;
+        }
+        if rows.is_empty() {
+            return;
+        }
+        let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+        let widths = widths(&header, rows.iter());
+        println!("{}", self.bold(&line(&header, &widths)));
+        for row in rows {
+            println!("{}", line(row, &widths));
+        }
+    }
+
+    fn bold(&self, text: &str) -> String {
+        match self.color {
+            true => format!("\x1B[1m{text}\x1B[0m"),
+            false => text.to_string(),
+        }
+    }
+
+    ///calendars for `calendars`, marking the ones without todos
+    pub fn calendars(&self, cals: &[&Calendar]) -> anyhow::Result<()> {
+        if self.is_json() {
+            let items: Vec<CalendarJson> = cals.iter().map(|cal| CalendarJson::new(cal)).collect();
+            return self.json(&items);
+        }
+        for cal in cals {
+            match cal.supports_todo {
+                true => println!("{}", self.calendar_name(cal)),
+                false => println!("{} (events only)", self.calendar_name(cal)),
+            }
+        }
+        Ok(())
+    }
+}
+
+///the widest cell of each column, header included
+fn widths<'a>(header: &[String], rows: impl Iterator<Item = &'a Vec<String>>) -> Vec<usize> {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    widths
+}
+
+///`cells` padded to `widths`, two spaces apart
+fn line(cells: &[String], widths: &[usize]) -> String {
+    let mut line = String::new();
+    for (cell, width) in cells.iter().zip(widths) {
+        line.push_str(cell);
+        line.push_str(&" ".repeat(width - cell.chars().count() + 2));
+    }
+    line.trim_end().to_string()
+}
+
+///open, done or cancelled
+pub fn state_name(state: TodoState) -> &'static str {
+    match state {
+        TodoState::Open => "open",
+        TodoState::Completed => "done",
+        TodoState::Cancelled => "cancelled",
+    }
+}
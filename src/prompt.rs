@@ -0,0 +1,16 @@
+use std::io::{self, BufRead, Write};
+
+///asks a yes/no question on stderr, so it stays out of anything piped, and
+///reads the answer from stdin. no unless answered with y or yes, end of
+///input counts as no
+pub fn confirm(question: &str) -> bool {
+    eprint!("{question} [y/N] ");
+    if io::stderr().flush().is_err() {
+        return false;
+    }
+    let mut answer = String::new();
+    match io::stdin().lock().read_line(&mut answer) {
+        Ok(0) | Err(_) => false,
+        Ok(_) => matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"),
+    }
+}
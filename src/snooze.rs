@@ -0,0 +1,141 @@
+use anyhow::Context;
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone, Utc};
+use reminder_rs::caldav::agenda::is_overdue;
+use reminder_rs::caldav::datetime::DateValue;
+use reminder_rs::caldav::todo::{set_property, touched};
+use reminder_rs::caldav::{CalendarTodo, TodoDetails};
+use reminder_rs::store::{self, TodoStore};
+
+use crate::actions::{self, Action, Found, Options};
+use crate::args::{PostponeCommand, SnoozeCommand};
+use crate::exit::CliError;
+use crate::find_calendar;
+use crate::output::Output;
+use crate::when;
+
+///where `snooze` moves the due date
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Shift {
+    ///later, or earlier if negative
+    By(Duration),
+    ///to that day, or that time
+    To(DateValue),
+}
+
+///`+3d` or `-2h` moves it by that much, anything else [`when::parse`]
+///takes moves it there
+pub fn parse(text: &str) -> Result<Shift, String> {
+    let trimmed = text.trim();
+    match trimmed.split_at_checked(1) {
+        Some(("+", span)) => when::span(span).map(Shift::By).ok_or(format!("invalid {trimmed}, expected eg. +3d or +2h")),
+        Some(("-", span)) => when::span(span).map(|span| Shift::By(-span)).ok_or(format!("invalid {trimmed}, expected eg. -1d")),
+        _ => when::parse(text).map(Shift::To),
+    }
+}
+
+///the ids and where to move them, which comes last. with --where it's all
+///there is
+pub fn arguments(command: &SnoozeCommand) -> anyhow::Result<(&[String], Shift)> {
+    let (when, ids) = command.args.split_last().context("snooze needs where to move them")?;
+    match (command.bulk.filter.is_some(), ids.is_empty()) {
+        (true, false) => return Err(CliError::Usage("With --where, give snooze only where to move them".to_string()).into()),
+        (false, true) => return Err(CliError::Usage("Give snooze the ids, then where to move them, eg. +1d".to_string()).into()),
+        _ => {}
+    }
+    Ok((ids, parse(when).map_err(CliError::Usage)?))
+}
+
+///`todo`'s iCalendar object with DUE moved by `shift`, and DTSTART by as
+///much so it's just as long, along with the new DUE. moved to a day, a DUE
+///that has a time keeps it unless that's before `now`, then it's due that
+///day. a todo without a DUE gets one
+pub fn snoozed(todo: &CalendarTodo, shift: &Shift, now: DateTime<Utc>) -> anyhow::Result<(String, DateValue)> {
+    let details = TodoDetails::new(todo, &[]);
+    let today = now.with_timezone(&Local).date_naive();
+    let due = match (shift, &details.due) {
+        (Shift::By(span), Some(DateValue::Date(_))) if span.num_seconds() % 86400 != 0 => {
+            anyhow::bail!("it's due on a day rather than at a time, so it only moves by whole days")
+        }
+        (Shift::By(span), Some(due)) => moved(due, *span),
+        (Shift::By(span), None) if span.num_seconds() % 86400 == 0 => today.checked_add_signed(*span).map(DateValue::Date),
+        (Shift::By(span), None) => now.checked_add_signed(*span).map(DateValue::Utc),
+        (Shift::To(DateValue::Date(day)), Some(due)) => Some(on(due, *day, now)),
+        (Shift::To(to), _) => Some(to.clone()),
+    }
+    .context("that's further than a date can go")?;
+
+    let (name, value) = due.property("DUE");
+    let mut ics = set_property(&todo.ics, &name, Some(&value));
+    let delta = match (shift, &details.due) {
+        (Shift::By(span), _) => Some(*span),
+        (_, Some(old)) => Some(due.instant() - old.instant()),
+        (_, None) => None,
+    };
+    if let (Some(start), Some(delta)) = (&details.start, delta) {
+        //a day only moves by whole days, the rest of it is a time of day it doesn't have
+        let start = moved(start, delta).unwrap_or_else(|| moved(start, Duration::days(delta.num_days())).unwrap_or(start.clone()));
+        let (name, value) = start.property("DTSTART");
+        ics = set_property(&ics, &name, Some(&value));
+    }
+    Ok((touched(&ics, now), due))
+}
+
+///`value` `span` later, in the same form. None for a day and a span that
+///isn't whole days, or past the last date there is
+fn moved(value: &DateValue, span: Duration) -> Option<DateValue> {
+    Some(match value {
+        DateValue::Date(_) if span.num_seconds() % 86400 != 0 => return None,
+        DateValue::Date(date) => DateValue::Date(date.checked_add_signed(span)?),
+        DateValue::Utc(at) => DateValue::Utc(at.checked_add_signed(span)?),
+        DateValue::Floating(time) => DateValue::Floating(time.checked_add_signed(span)?),
+        DateValue::Zoned { time, tzid, utc } => DateValue::Zoned {
+            time: time.checked_add_signed(span)?,
+            tzid: tzid.clone(),
+            utc: match utc {
+                Some(utc) => Some(utc.checked_add_signed(span)?),
+                None => None,
+            },
+        },
+    })
+}
+
+///`due` on `day`, at the same time of day unless that's already past
+fn on(due: &DateValue, day: NaiveDate, now: DateTime<Utc>) -> DateValue {
+    let moved = match due {
+        DateValue::Date(_) => return DateValue::Date(day),
+        DateValue::Utc(at) => {
+            let time = at.with_timezone(&Local).time();
+            match Local.from_local_datetime(&day.and_time(time)).earliest() {
+                Some(at) => DateValue::Utc(at.with_timezone(&Utc)),
+                None => return DateValue::Date(day),
+            }
+        }
+        _ => moved(due, Duration::days((day - due.local_date()).num_days())).unwrap_or(DateValue::Date(day)),
+    };
+    match is_overdue(&moved, now.with_timezone(&Local)) {
+        true => DateValue::Date(day),
+        false => moved,
+    }
+}
+
+///snoozes every open reminder that's overdue to `--to`
+pub async fn postpone(store: &dyn TodoStore, command: &PostponeCommand, output: &Output, jobs: usize) -> anyhow::Result<()> {
+    let cals = match &command.calendar {
+        Some(name) => vec![find_calendar(store, name).await?],
+        None => store::todo_calendars(store).await,
+    };
+    let now = Local::now();
+    let mut overdue = vec![];
+    for (cal, todos) in cals.iter().zip(store::current_todos_many(store, &cals, jobs).await) {
+        let late = Found::all(cal, &todos?).into_iter().filter(|found| {
+            TodoDetails::new(found.todo(), &[]).due.is_some_and(|due| is_overdue(&due, now))
+        });
+        overdue.extend(late);
+    }
+    if overdue.is_empty() && !output.is_json() {
+        println!("Nothing is overdue");
+        return Ok(());
+    }
+    let options = Options { shift: Some(Shift::To(command.to.clone())), ..Default::default() };
+    actions::run_found(store, Action::Snooze, overdue, &options, output, jobs).await
+}
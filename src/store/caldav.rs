@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use tokio::sync::RwLock;
+
+use crate::caldav::{CalDAVClient, Calendar, CalendarProps, CalendarRef, CalendarTodo, Partial, ServerInfo};
+
+use super::{Query, StoreFuture, TodoStore};
+
+impl TodoStore for CalDAVClient {
+    fn calendars(&self) -> &[CalendarRef] {
+        &self.calendars
+    }
+
+    fn todos<'a>(&'a self, cal: &'a RwLock<Calendar>, query: Query) -> StoreFuture<'a, Arc<Partial<CalendarTodo>>> {
+        Box::pin(self.get_todos_matching(cal, query))
+    }
+
+    fn create<'a>(&'a self, cal: &'a Calendar, uid: &'a str, ics: &'a str) -> StoreFuture<'a, CalendarTodo> {
+        Box::pin(self.create_todo(cal, uid, ics))
+    }
+
+    fn update<'a>(&'a self, todo: &'a CalendarTodo, ics: &'a str) -> StoreFuture<'a, CalendarTodo> {
+        Box::pin(self.update_todo(todo, ics))
+    }
+
+    fn delete<'a>(&'a self, todo: &'a CalendarTodo) -> StoreFuture<'a, ()> {
+        Box::pin(self.delete_todo(todo))
+    }
+
+    fn create_calendar<'a>(&'a self, id: &'a str, props: &'a CalendarProps) -> StoreFuture<'a, Calendar> {
+        Box::pin(CalDAVClient::create_calendar(self, id, props))
+    }
+
+    fn update_calendar<'a>(&'a self, cal: &'a RwLock<Calendar>, props: &'a CalendarProps) -> StoreFuture<'a, ()> {
+        Box::pin(CalDAVClient::update_calendar(self, cal, props))
+    }
+
+    fn delete_calendar<'a>(&'a self, cal: &'a Calendar) -> StoreFuture<'a, ()> {
+        Box::pin(CalDAVClient::delete_calendar(self, cal))
+    }
+
+    fn server_info(&self) -> Option<&ServerInfo> {
+        Some(&self.server)
+    }
+}
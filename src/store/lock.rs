@@ -0,0 +1,22 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+///an advisory lock on a file, held until dropped. other reminder-rs
+///processes wait for it, other programs don't know it's there
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    ///waits for the lock on `path`, making the file if it isn't there
+    pub async fn acquire(path: &Path) -> io::Result<FileLock> {
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let file = OpenOptions::new().create(true).truncate(false).write(true).open(&path)?;
+            file.lock()?;
+            Ok(FileLock { _file: file })
+        })
+        .await?
+    }
+}
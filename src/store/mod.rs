@@ -0,0 +1,125 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use tokio::sync::RwLock;
+
+use crate::caldav::{Calendar, CalendarProps, CalendarRef, CalendarTodo, Partial, Result, ServerInfo, TodoState};
+
+mod caldav;
+mod lock;
+pub mod vdir;
+
+pub use lock::FileLock;
+pub use vdir::VdirStore;
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+///which todos to fetch from a calendar, see [`CalendarTodo::state`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Query {
+    ///neither completed nor cancelled
+    Current,
+    Completed,
+    Cancelled,
+    All,
+    ///subtasks of another todo, done or not
+    Subtasks,
+    ///completed or cancelled from `since` until just before `until`, going
+    ///by [`CalendarTodo::finished`]
+    History { since: Option<DateTime<Utc>>, until: Option<DateTime<Utc>> },
+}
+
+impl Query {
+    pub fn matches(&self, todo: &CalendarTodo) -> bool {
+        match self {
+            Query::Current => todo.state() == TodoState::Open,
+            Query::Completed => todo.state() == TodoState::Completed,
+            Query::Cancelled => todo.state() == TodoState::Cancelled,
+            Query::All => true,
+            Query::Subtasks => todo.parent().is_some(),
+            Query::History { since, until } => match todo.finished() {
+                Some(at) => since.is_none_or(|since| at >= since) && until.is_none_or(|until| at < until),
+                None => todo.state() != TodoState::Open && since.is_none() && until.is_none(),
+            },
+        }
+    }
+}
+
+///somewhere todos are kept: a CalDAV server or a local vdir.
+///updates and deletes only go through if the todo's `etag` is still current,
+///otherwise they fail with `Error::Conflict`
+pub trait TodoStore: Send + Sync {
+    fn calendars(&self) -> &[CalendarRef];
+
+    fn todos<'a>(&'a self, cal: &'a RwLock<Calendar>, query: Query) -> StoreFuture<'a, Arc<Partial<CalendarTodo>>>;
+
+    ///stores a new todo in `cal` and returns it as stored
+    fn create<'a>(&'a self, cal: &'a Calendar, uid: &'a str, ics: &'a str) -> StoreFuture<'a, CalendarTodo>;
+
+    ///replaces `todo` with `ics` and returns it as stored
+    fn update<'a>(&'a self, todo: &'a CalendarTodo, ics: &'a str) -> StoreFuture<'a, CalendarTodo>;
+
+    fn delete<'a>(&'a self, todo: &'a CalendarTodo) -> StoreFuture<'a, ()>;
+
+    ///makes a calendar for todos, `id` being the last part of its url or
+    ///its directory name. `calendars()` stays as it was when the store was opened
+    fn create_calendar<'a>(&'a self, id: &'a str, props: &'a CalendarProps) -> StoreFuture<'a, Calendar>;
+
+    fn update_calendar<'a>(&'a self, cal: &'a RwLock<Calendar>, props: &'a CalendarProps) -> StoreFuture<'a, ()>;
+
+    ///deletes `cal` and everything in it
+    fn delete_calendar<'a>(&'a self, cal: &'a Calendar) -> StoreFuture<'a, ()>;
+
+    ///what the server supports, for stores that have one
+    fn server_info(&self) -> Option<&ServerInfo> {
+        None
+    }
+}
+
+///current todos for many calendars, `limit` at a time.
+///results are in the same order as `cals`
+pub async fn current_todos_many(
+    store: &dyn TodoStore,
+    cals: &[CalendarRef],
+    limit: usize,
+) -> Vec<Result<Arc<Partial<CalendarTodo>>>> {
+    todos_many(store, cals, Query::Current, limit).await
+}
+
+///like [`current_todos_many`], for any query
+pub async fn todos_many(
+    store: &dyn TodoStore,
+    cals: &[CalendarRef],
+    query: Query,
+    limit: usize,
+) -> Vec<Result<Arc<Partial<CalendarTodo>>>> {
+    stream::iter(cals.iter().cloned())
+        .map(|cal| async move { store.todos(&cal, query).await })
+        .buffered(limit.max(1))
+        .collect()
+        .await
+}
+
+///the calendars that can hold todos, leaving out event-only ones
+pub async fn todo_calendars(store: &dyn TodoStore) -> Vec<CalendarRef> {
+    let mut cals = vec![];
+    for cal in store.calendars() {
+        if cal.read().await.supports_todo {
+            cals.push(cal.clone());
+        }
+    }
+    cals
+}
+
+///the calendar called `name`, if there is one
+pub async fn find_calendar(store: &dyn TodoStore, name: &str) -> Option<CalendarRef> {
+    for cal in store.calendars() {
+        if cal.read().await.name == name {
+            return Some(cal.clone());
+        }
+    }
+    None
+}
@@ -0,0 +1,266 @@
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use tokio::fs;
+use tokio::sync::RwLock;
+
+use crate::caldav::{calendar, Calendar, CalendarProps, CalendarRef, CalendarTodo, Error, ItemError, Partial, Result};
+
+use super::{FileLock, Query, StoreFuture, TodoStore};
+
+///a local vdir, the layout vdirsyncer, khal and todoman share: one directory
+///per calendar, one `.ics` file per item. a calendar's `displayname`,
+///`color`, `description` and `order` files are its metadata. there are no ETags, so the file's
+///modification time stands in for one. writes hold a lock on the calendar's
+///`.lock` file from checking that time to renaming over the item, so two
+///reminder-rs processes can't both pass the check. other programs writing to
+///the vdir don't take it and stay last-writer-wins
+pub struct VdirStore {
+    root: PathBuf,
+    calendars: Vec<CalendarRef>,
+}
+
+impl VdirStore {
+    ///every directory in `root` is a calendar
+    pub async fn open(root: impl Into<PathBuf>) -> Result<Self> {
+        let root = root.into();
+        let mut calendars = vec![];
+        for dir in list(&root, |path, meta| meta.is_dir() && !is_hidden(path)).await? {
+            calendars.push(load_calendar(&dir).await);
+        }
+        calendar::sort_by_order(&mut calendars);
+        let calendars = calendars.into_iter().map(|cal| Arc::new(RwLock::new(cal))).collect();
+        Ok(VdirStore { root, calendars })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl TodoStore for VdirStore {
+    fn calendars(&self) -> &[CalendarRef] {
+        &self.calendars
+    }
+
+    fn todos<'a>(&'a self, cal: &'a RwLock<Calendar>, query: Query) -> StoreFuture<'a, Arc<Partial<CalendarTodo>>> {
+        Box::pin(async move {
+            let dir = PathBuf::from(&cal.read().await.url);
+            let mut todos = Partial::default();
+            let items = list(&dir, |path, meta| {
+                meta.is_file() && !is_hidden(path) && path.extension().and_then(|e| e.to_str()) == Some("ics")
+            })
+            .await?;
+            for path in items {
+                match read_todo(&path).await {
+                    Ok(Some(todo)) if query.matches(&todo) => todos.items.push(todo),
+                    Ok(_) => {}
+                    Err(error) => todos.errors.push(ItemError { href: path_str(&path), error }),
+                }
+            }
+            Ok(Arc::new(todos))
+        })
+    }
+
+    fn create<'a>(&'a self, cal: &'a Calendar, uid: &'a str, ics: &'a str) -> StoreFuture<'a, CalendarTodo> {
+        Box::pin(async move {
+            let path = Path::new(&cal.url).join(item_name(uid));
+            let _lock = lock(&path).await?;
+            write_atomic(&path, ics, false).await?;
+            stored(&path, ics).await
+        })
+    }
+
+    fn update<'a>(&'a self, todo: &'a CalendarTodo, ics: &'a str) -> StoreFuture<'a, CalendarTodo> {
+        Box::pin(async move {
+            let path = Path::new(&todo.url);
+            let _lock = lock(path).await?;
+            check_version(path, &todo.etag).await?;
+            write_atomic(path, ics, true).await?;
+            stored(path, ics).await
+        })
+    }
+
+    fn delete<'a>(&'a self, todo: &'a CalendarTodo) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let path = Path::new(&todo.url);
+            let _lock = lock(path).await?;
+            check_version(path, &todo.etag).await?;
+            fs::remove_file(path).await.map_err(|e| io_error(path, e))
+        })
+    }
+
+    fn create_calendar<'a>(&'a self, id: &'a str, props: &'a CalendarProps) -> StoreFuture<'a, Calendar> {
+        Box::pin(async move {
+            let dir = self.root.join(id);
+            fs::create_dir(&dir).await.map_err(|e| io_error(&dir, e))?;
+            write_meta(&dir, props).await?;
+            Ok(load_calendar(&dir).await)
+        })
+    }
+
+    fn update_calendar<'a>(&'a self, cal: &'a RwLock<Calendar>, props: &'a CalendarProps) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let dir = PathBuf::from(&cal.read().await.url);
+            write_meta(&dir, props).await?;
+            props.apply(&mut *cal.write().await);
+            Ok(())
+        })
+    }
+
+    fn delete_calendar<'a>(&'a self, cal: &'a Calendar) -> StoreFuture<'a, ()> {
+        Box::pin(async move {
+            let dir = Path::new(&cal.url);
+            fs::remove_dir_all(dir).await.map_err(|e| io_error(dir, e))
+        })
+    }
+}
+
+async fn load_calendar(dir: &Path) -> Calendar {
+    let name = match read_meta(dir, "displayname").await {
+        Some(name) => name,
+        None => dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+    };
+    Calendar {
+        url: path_str(dir),
+        name,
+        ctag: String::new(),
+        color: read_meta(dir, "color").await,
+        description: read_meta(dir, "description").await,
+        order: read_meta(dir, "order").await.and_then(|o| o.parse().ok()),
+        supports_todo: true,
+        capabilities: Default::default(),
+        cache: Default::default(),
+    }
+}
+
+async fn read_meta(dir: &Path, name: &str) -> Option<String> {
+    let value = fs::read_to_string(dir.join(name)).await.ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+async fn write_meta(dir: &Path, props: &CalendarProps) -> Result<()> {
+    let order = props.order.map(|o| o.to_string());
+    let files = [
+        ("displayname", &props.name),
+        ("color", &props.color),
+        ("description", &props.description),
+        ("order", &order),
+    ];
+    for (name, value) in files {
+        if let Some(value) = value {
+            let path = dir.join(name);
+            fs::write(&path, value).await.map_err(|e| io_error(&path, e))?;
+        }
+    }
+    Ok(())
+}
+
+///entries of `dir` that pass `keep`, sorted so the order is stable
+async fn list(dir: &Path, keep: impl Fn(&Path, &Metadata) -> bool) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    let mut entries = fs::read_dir(dir).await.map_err(|e| io_error(dir, e))?;
+    while let Some(entry) = entries.next_entry().await.map_err(|e| io_error(dir, e))? {
+        let path = entry.path();
+        let meta = fs::metadata(&path).await.map_err(|e| io_error(&path, e))?;
+        if keep(&path, &meta) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+///None for items that aren't todos, khal keeps events in the same directories
+async fn read_todo(path: &Path) -> Result<Option<CalendarTodo>> {
+    //version first, so a write in between makes it stale rather than wrong
+    let meta = fs::metadata(path).await.map_err(|e| io_error(path, e))?;
+    let ics = fs::read_to_string(path).await.map_err(|e| io_error(path, e))?;
+    if !ics.contains("BEGIN:VTODO") {
+        return Ok(None);
+    }
+    CalendarTodo::from_ics(&path_str(path), version(&meta), &ics).map(Some)
+}
+
+async fn stored(path: &Path, ics: &str) -> Result<CalendarTodo> {
+    let meta = fs::metadata(path).await.map_err(|e| io_error(path, e))?;
+    CalendarTodo::from_ics(&path_str(path), version(&meta), ics)
+}
+
+///the lock of the calendar `item` is in
+async fn lock(item: &Path) -> Result<FileLock> {
+    let path = item.with_file_name(".lock");
+    FileLock::acquire(&path).await.map_err(|e| io_error(&path, e))
+}
+
+async fn check_version(path: &Path, etag: &str) -> Result<()> {
+    let meta = fs::metadata(path).await.map_err(|e| io_error(path, e))?;
+    let current = version(&meta);
+    if current != etag {
+        return Err(Error::Conflict { url: path_str(path), etag: Some(current), error: None });
+    }
+    Ok(())
+}
+
+///writes to a hidden temp file first so readers never see half an item.
+///without `overwrite` it fails if `path` already exists
+async fn write_atomic(path: &Path, ics: &str, overwrite: bool) -> Result<()> {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let tmp = path.with_file_name(format!(".{name}.tmp"));
+    fs::write(&tmp, ics).await.map_err(|e| io_error(&tmp, e))?;
+    let res = if overwrite {
+        fs::rename(&tmp, path).await
+    } else {
+        //unlike rename, linking never replaces an existing file
+        fs::hard_link(&tmp, path).await
+    };
+    if !overwrite || res.is_err() {
+        let _ = fs::remove_file(&tmp).await;
+    }
+    res.map_err(|e| io_error(path, e))
+}
+
+///modification time and size
+fn version(meta: &Metadata) -> String {
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    format!("{}.{:09};{}", mtime.as_secs(), mtime.subsec_nanos(), meta.len())
+}
+
+///`<uid>.ics`, with anything that doesn't belong in a file name replaced
+fn item_name(uid: &str) -> String {
+    let name: String = uid
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_.@".contains(c) { c } else { '_' })
+        .collect();
+    //a leading dot would hide it
+    match name.strip_prefix('.') {
+        Some(rest) => format!("_{rest}.ics"),
+        None => format!("{name}.ics"),
+    }
+}
+
+///temp files and the like
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|n| n.to_string_lossy().starts_with('.'))
+}
+
+fn path_str(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+fn io_error(path: &Path, source: io::Error) -> Error {
+    let path = path_str(path);
+    match source.kind() {
+        io::ErrorKind::NotFound => Error::NotFound { url: path },
+        io::ErrorKind::AlreadyExists => Error::Conflict { url: path, etag: None, error: None },
+        _ => Error::Io { path, source },
+    }
+}
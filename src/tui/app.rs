@@ -1,6 +1,9 @@
-use std::{cell::RefCell, rc::Rc};
+use std::sync::Arc;
 
-use crate::caldav::{calendar::Calendar, client::CalDAVClient};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use reminder_rs::caldav::{client::DEFAULT_CONCURRENCY, CalendarRef, CalendarTodo, Partial};
+use reminder_rs::store::{self, TodoStore};
 
 
 
@@ -8,8 +11,8 @@ use crate::caldav::{calendar::Calendar, client::CalDAVClient};
 pub enum CurrentScreen {
     Home(HomeState),
     List(ListType),
-    View(Rc<RefCell<Calendar>>, SelectedTodo),
-    Edit(Rc<RefCell<Calendar>>, SelectedTodo),
+    View(CalendarRef, SelectedTodo),
+    Edit(CalendarRef, SelectedTodo),
     New()
 }
 
@@ -28,16 +31,47 @@ pub struct SelectedTodo {
 
 }
 
+///current todos for each calendar that holds todos
+pub type TodoResults = Vec<(CalendarRef, reminder_rs::caldav::Result<Arc<Partial<CalendarTodo>>>)>;
+
 pub struct App {
-    client: CalDAVClient,
-    screen: CurrentScreen
+    store: Arc<dyn TodoStore>,
+    screen: CurrentScreen,
+    todos: TodoResults,
+    refresh_tx: UnboundedSender<TodoResults>,
+    refresh_rx: UnboundedReceiver<TodoResults>,
 }
 
 impl App {
-    pub fn new(client: CalDAVClient) -> App {
+    pub fn new(store: Arc<dyn TodoStore>) -> App {
         let state = HomeState { selection: 0 };
+        let (refresh_tx, refresh_rx) = mpsc::unbounded_channel();
         App {
-            client, screen: CurrentScreen::Home(state)
+            store, screen: CurrentScreen::Home(state), todos: vec![], refresh_tx, refresh_rx
+        }
+    }
+
+    ///fetch every calendar in a background task so the UI doesn't block.
+    ///the results get picked up by `poll_refresh`
+    pub fn refresh(&self) {
+        let store = self.store.clone();
+        let tx = self.refresh_tx.clone();
+        tokio::spawn(async move {
+            let cals = store::todo_calendars(&*store).await;
+            let results = store::current_todos_many(&*store, &cals, DEFAULT_CONCURRENCY).await;
+            let results = cals.into_iter().zip(results).collect();
+            //the app is gone if nobody's listening
+            let _ = tx.send(results);
+        });
+    }
+
+    ///take the newest finished refresh, if any. returns true if the todos changed
+    pub fn poll_refresh(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(todos) = self.refresh_rx.try_recv() {
+            self.todos = todos;
+            changed = true;
         }
+        changed
     }
 }
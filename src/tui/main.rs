@@ -6,12 +6,13 @@ use ratatui::crossterm::terminal::{
 use ratatui::prelude::{Backend, CrosstermBackend};
 use ratatui::Terminal;
 use std::io;
+use std::sync::Arc;
 
-use crate::caldav::client::CalDAVClient;
+use reminder_rs::store::TodoStore;
 
 use super::app::App;
 
-pub fn start(client: CalDAVClient) -> anyhow::Result<()> {
+pub fn start(store: Arc<dyn TodoStore>) -> anyhow::Result<()> {
     //setup
     enable_raw_mode()?;
     let mut stderr = io::stderr(); // This is a special case. Normally using stdout is fine
@@ -20,7 +21,8 @@ pub fn start(client: CalDAVClient) -> anyhow::Result<()> {
     //run
     let backend = CrosstermBackend::new(stderr);
     let mut terminal = Terminal::new(backend)?;
-    let mut app = App::new(client);
+    let mut app = App::new(store);
+    app.refresh();
     // let res = run_app(&mut terminal, &mut app);
 
     //restore
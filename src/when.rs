@@ -0,0 +1,125 @@
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use reminder_rs::caldav::alarm::{Related, Trigger};
+use reminder_rs::caldav::datetime::DateValue;
+
+const EXPECTED: &str = "expected a date like 2026-10-20, today, tomorrow, friday or +3d, optionally followed by a time like 17:00";
+
+///a date or time as typed on the command line, for clap. dates stay dates,
+///anything with a time of day is stored in UTC
+pub fn parse(text: &str) -> Result<DateValue, String> {
+    parse_at(text, Local::now())
+}
+
+///[`parse`] with `now` being now. `+2h` and `+30m` are times, `+3d` and
+///`+1w` dates
+pub fn parse_at(text: &str, now: DateTime<Local>) -> Result<DateValue, String> {
+    let text = text.trim().to_lowercase();
+    if let Some(offset) = text.strip_prefix('+') {
+        return relative(offset, now).ok_or(format!("invalid {text}, {EXPECTED}"));
+    }
+    //`2026-10-20T17:00` is one word
+    let (day, time) = match text.split_once(' ') {
+        Some((day, time)) => (day, Some(time.trim())),
+        None if text.len() > 10 && text.as_bytes()[10] == b't' => (&text[..10], Some(&text[11..])),
+        None => (text.as_str(), None),
+    };
+    let (date, time) = match (day_of(day, now.date_naive()), time) {
+        (Some(date), time) => (date, time.map(time_of)),
+        //just a time is today
+        (None, None) if time_of(day).is_some() => (now.date_naive(), Some(time_of(day))),
+        _ => return Err(format!("invalid {text}, {EXPECTED}")),
+    };
+    match time {
+        None => Ok(DateValue::Date(date)),
+        Some(None) => Err(format!("invalid time in {text}, {EXPECTED}")),
+        Some(Some(time)) => Local
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .map(|at| DateValue::Utc(at.with_timezone(&Utc)))
+            .ok_or(format!("{text} doesn't exist here")),
+    }
+}
+
+///an alarm: `-15m` before due, `start-15m` or `start+0m` from the start, or
+///a time in the grammar of [`parse`] like `2026-10-20 09:00`
+pub fn alarm(text: &str) -> Result<Trigger, String> {
+    alarm_at(text, Local::now())
+}
+
+///[`alarm`] with `now` being now
+pub fn alarm_at(text: &str, now: DateTime<Local>) -> Result<Trigger, String> {
+    let lower = text.trim().to_lowercase();
+    let (related, offset) = match lower.strip_prefix("start") {
+        Some(offset) => (Related::Start, offset),
+        None => (Related::Due, lower.strip_prefix("due").unwrap_or(&lower)),
+    };
+    if let Some(sign @ ('-' | '+')) = offset.chars().next() {
+        let span = span(&offset[1..]).ok_or(format!("invalid alarm {text}, expected eg. -15m, -1h, -2d or start-30m"))?;
+        let offset = if sign == '-' { -span } else { span };
+        return Ok(Trigger::Relative { offset, related });
+    }
+    match parse_at(text, now)? {
+        DateValue::Date(_) => Err(format!("an alarm needs a time of day, eg. {} 09:00", text.trim())),
+        at => Ok(Trigger::At(at.instant())),
+    }
+}
+
+///`3x5m`, three more times five minutes apart
+pub fn repeat(text: &str) -> Result<(u32, Duration), String> {
+    let invalid = || format!("invalid repeat {text}, expected eg. 3x5m");
+    let lower = text.trim().to_lowercase();
+    let (times, every) = lower.split_once('x').ok_or_else(invalid)?;
+    let times: u32 = times.parse().map_err(|_| invalid())?;
+    match span(every) {
+        Some(every) if every > Duration::zero() => Ok((times, every)),
+        _ => Err(invalid()),
+    }
+}
+
+///`15m`, `2h`, `3d` or `1w`. None past what chrono can hold, too
+pub fn span(text: &str) -> Option<Duration> {
+    let unit = text.chars().last()?;
+    let n: i64 = text[..text.len() - unit.len_utf8()].parse().ok()?;
+    match unit {
+        'm' => Duration::try_minutes(n),
+        'h' => Duration::try_hours(n),
+        'd' => Duration::try_days(n),
+        'w' => Duration::try_weeks(n),
+        _ => None,
+    }
+}
+
+fn relative(offset: &str, now: DateTime<Local>) -> Option<DateValue> {
+    let unit = offset.chars().last()?;
+    let n: i64 = offset[..offset.len() - unit.len_utf8()].parse().ok()?;
+    let today = now.date_naive();
+    //too far out for chrono is as invalid as a typo
+    Some(match unit {
+        'm' => DateValue::Utc(now.checked_add_signed(Duration::try_minutes(n)?)?.with_timezone(&Utc)),
+        'h' => DateValue::Utc(now.checked_add_signed(Duration::try_hours(n)?)?.with_timezone(&Utc)),
+        'd' => DateValue::Date(today.checked_add_signed(Duration::try_days(n)?)?),
+        'w' => DateValue::Date(today.checked_add_signed(Duration::try_weeks(n)?)?),
+        _ => return None,
+    })
+}
+
+///a weekday is the next one, never today
+fn day_of(text: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let weekday = |day: Weekday| {
+        let ahead = (day.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        today + Duration::days(if ahead == 0 { 7 } else { ahead as i64 })
+    };
+    match text {
+        "today" => Some(today),
+        "tomorrow" => Some(today + Duration::days(1)),
+        _ => match text.parse::<Weekday>() {
+            Ok(day) => Some(weekday(day)),
+            Err(_) => NaiveDate::parse_from_str(text, "%Y-%m-%d").ok(),
+        },
+    }
+}
+
+///`17:00` or `9:30`
+fn time_of(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text, "%H:%M").ok()
+}
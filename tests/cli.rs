@@ -0,0 +1,764 @@
+use std::process::{Output, Stdio};
+
+use reminder_rs::mock::{self, Fault, MockCalendar, MockServer};
+use tokio::process::Command;
+
+mod common;
+
+const ENV: [&str; 11] = [
+    "CALDAV_URL",
+    "CALDAV_USERNAME",
+    "CALDAV_PASSWORD",
+    "CALDAV_TOKEN",
+    "CALDAV_REFRESH_TOKEN",
+    "CALDAV_TOKEN_URL",
+    "CALDAV_CLIENT_ID",
+    "CALDAV_CLIENT_SECRET",
+    "REMINDER_VDIR",
+    "REMINDER_ALARM_COMMAND",
+    "REMINDER_STATE_DIR",
+];
+
+///runs the binary against `server`, away from any .env or config of the
+///person running the tests, with nothing on stdin
+async fn run(server: Option<&MockServer>, args: &[&str]) -> Output {
+    run_with(server, args, &[]).await
+}
+
+///[`run`] with `vars` set
+async fn run_with(server: Option<&MockServer>, args: &[&str], vars: &[(&str, &str)]) -> Output {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_reminder-rs"));
+    cmd.args(args).current_dir(std::env::temp_dir()).stdin(Stdio::null());
+    for var in ENV {
+        cmd.env_remove(var);
+    }
+    //a journal per server, rather than in the home directory of whoever runs the tests
+    let port = server.map(|server| server.url().replace(|c: char| !c.is_ascii_digit(), "")).unwrap_or_default();
+    let state = format!("reminder-rs-state-{}-{port}", std::process::id());
+    cmd.env("REMINDER_STATE_DIR", std::env::temp_dir().join(state));
+    if let Some(server) = server {
+        cmd.env("CALDAV_URL", server.url());
+    }
+    cmd.envs(vars.iter().copied());
+    cmd.output().await.unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[tokio::test]
+async fn calendars() {
+    let server = common::server().await;
+    let output = run(Some(&server), &["calendars"]).await;
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("Work"));
+    assert!(out.contains("Home"));
+}
+
+#[tokio::test]
+async fn hides_event_calendars() {
+    let server = common::server().await;
+    server.add_calendar(
+        MockCalendar::builder()
+            .id("events".to_string())
+            .name("Events".to_string())
+            .components(vec!["VEVENT".to_string()])
+            .build(),
+    );
+    assert!(!stdout(&run(Some(&server), &["calendars"]).await).contains("Events"));
+    assert!(stdout(&run(Some(&server), &["calendars", "--all"]).await).contains("Events (events only)"));
+
+    assert!(run(Some(&server), &["list"]).await.status.success());
+    assert!(!server.requests().iter().any(|r| r.starts_with("REPORT /calendars/user/events/")));
+}
+
+#[tokio::test]
+async fn list() {
+    let server = common::server().await;
+    let output = run(Some(&server), &["list"]).await;
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("Write report"));
+    assert!(out.contains("Buy milk"));
+    assert!(!out.contains("Make slides"));
+}
+
+#[tokio::test]
+async fn list_one_calendar() {
+    let server = common::server().await;
+    let output = run(Some(&server), &["list", "--calendar", "Home"]).await;
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("Buy milk"));
+    assert!(!out.contains("Write report"));
+
+    let output = run(Some(&server), &["list", "--calendar", "Nope"]).await;
+    assert_eq!(output.status.code(), Some(5));
+}
+
+#[tokio::test]
+async fn search() {
+    let server = common::server().await;
+    let output = run(Some(&server), &["search", "milk"]).await;
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("Buy milk"));
+    assert!(!out.contains("Write report"));
+}
+
+#[tokio::test]
+async fn exit_codes() {
+    assert_eq!(run(None, &["list"]).await.status.code(), Some(3));
+
+    let server = MockServer::builder()
+        .username("me".to_string())
+        .password("secret".to_string())
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(run(Some(&server), &["list"]).await.status.code(), Some(4));
+
+    assert_eq!(run(None, &["no-such-command"]).await.status.code(), Some(2));
+}
+
+#[tokio::test]
+async fn unimplemented_commands() {
+    let server = common::server().await;
+    for args in [&["copy"][..], &["import", "-c", "Home"], &["test"], &["new", "--tui"], &["edit", "report", "--tui"]] {
+        let output = run(Some(&server), args).await;
+        assert_eq!(output.status.code(), Some(11), "{args:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("isn't implemented yet"));
+    }
+    assert_eq!(server.requests().iter().filter(|r| !r.starts_with("PROPFIND") && !r.starts_with("OPTIONS")).count(), 0);
+}
+
+#[tokio::test]
+async fn server_info() {
+    let server = common::server().await;
+    let output = run(Some(&server), &["server-info"]).await;
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.contains("calendar-access"));
+    assert!(out.contains("Work"));
+    assert!(out.contains("sync-collection"));
+    assert!(out.contains("text/calendar 2.0"));
+}
+
+#[tokio::test]
+async fn manage_calendars() {
+    let server = common::server().await;
+    let output = run(Some(&server), &["calendars", "create", "Weekend plans", "--color", "00ff00"]).await;
+    assert!(output.status.success());
+    assert!(stdout(&run(Some(&server), &["calendars"]).await).contains("Weekend plans"));
+
+    assert!(run(Some(&server), &["calendars", "rename", "Weekend plans", "Weekend"]).await.status.success());
+    assert_eq!(run(Some(&server), &["calendars", "color", "Weekend", "green"]).await.status.code(), Some(2));
+    assert!(run(Some(&server), &["calendars", "order", "Weekend", "-1"]).await.status.success());
+    let out = stdout(&run(Some(&server), &["calendars"]).await);
+    assert!(out.lines().next().unwrap().ends_with("Weekend"));
+
+    //nobody to say yes
+    assert_eq!(run(Some(&server), &["calendars", "delete", "Weekend"]).await.status.code(), Some(1));
+    assert!(run(Some(&server), &["calendars", "delete", "Weekend", "--yes"]).await.status.success());
+    assert!(!stdout(&run(Some(&server), &["calendars"]).await).contains("Weekend"));
+}
+
+#[tokio::test]
+async fn done_cancel_undone() {
+    let server = common::server().await;
+    let output = run(Some(&server), &["done", "report", "mil"]).await;
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "Done: Write report\nDone: Buy milk\n");
+    assert!(server.get("work", "report.ics").unwrap().contains("STATUS:COMPLETED"));
+    assert!(!stdout(&run(Some(&server), &["list"]).await).contains("Buy milk"));
+
+    let output = run(Some(&server), &["reopen", "milk", "report"]).await;
+    assert!(output.status.success());
+    assert!(stdout(&run(Some(&server), &["list"]).await).contains("Buy milk"));
+
+    assert!(run(Some(&server), &["cancel", "milk"]).await.status.success());
+    assert!(!stdout(&run(Some(&server), &["list"]).await).contains("Buy milk"));
+    let output = run(Some(&server), &["cancel", "milk"]).await;
+    assert_eq!(stdout(&output), "Already cancelled: Buy milk\n");
+
+    //the others still go through
+    let output = run(Some(&server), &["undone", "nope", "milk"]).await;
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(stdout(&output), "Reopened: Buy milk\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("1 of 2 failed"));
+}
+
+#[tokio::test]
+async fn delete_asks_first() {
+    let server = common::server().await;
+    server.put("home", "mail.ics", &mock::todo("mail", "Post letter", &[]));
+    assert_eq!(run(Some(&server), &["done", "m"]).await.status.code(), Some(2));
+
+    //nobody to say yes
+    assert_eq!(run(Some(&server), &["delete", "milk"]).await.status.code(), Some(1));
+    assert!(server.get("home", "milk.ics").is_some());
+
+    let output = run(Some(&server), &["delete", "milk", "--yes"]).await;
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "Deleted: Buy milk\n");
+    assert_eq!(server.files("home"), ["mail.ics"]);
+    assert!(server.requests().iter().any(|r| r.starts_with("DELETE /calendars/user/home/milk.ics")));
+}
+
+///`list` with only the summary column, plus `args`
+async fn summaries(server: &MockServer, args: &[&str]) -> String {
+    let mut all = vec!["list", "--columns", "summary"];
+    all.extend(args);
+    stdout(&run(Some(server), &all).await)
+}
+
+#[tokio::test]
+async fn sort_and_group() {
+    let server = common::server().await;
+    server.put("home", "bank.ics", &mock::todo("bank", "Call bank", &["PRIORITY:1", "CATEGORIES:Errands,Phone", "DUE;VALUE=DATE:20991231", "CREATED:20240103T000000Z"]));
+    server.put("home", "rent.ics", &mock::todo("rent", "Pay rent", &["PRIORITY:9", "CATEGORIES:Errands", "DUE;VALUE=DATE:20240101", "CREATED:20240102T000000Z"]));
+
+    //due first, undated last
+    assert_eq!(summaries(&server, &[]).await, "Pay rent\nWrite report\nCall bank\nBuy milk\n");
+    assert_eq!(summaries(&server, &["--sort", "summary"]).await, "Buy milk\nCall bank\nPay rent\nWrite report\n");
+    assert_eq!(summaries(&server, &["--sort", "-summary"]).await, "Write report\nPay rent\nCall bank\nBuy milk\n");
+    assert_eq!(summaries(&server, &["--sort", "-created,summary"]).await, "Call bank\nPay rent\nBuy milk\nWrite report\n");
+    assert_eq!(summaries(&server, &["--sort", "priority,summary"]).await, "Call bank\nPay rent\nBuy milk\nWrite report\n");
+
+    assert_eq!(
+        summaries(&server, &["--group-by", "due-bucket"]).await,
+        "Overdue\tPay rent\nOverdue\tWrite report\nLater\tCall bank\nNo date\tBuy milk\n"
+    );
+    assert_eq!(
+        summaries(&server, &["--group-by", "priority"]).await,
+        "High\tCall bank\nLow\tPay rent\nNo priority\tWrite report\nNo priority\tBuy milk\n"
+    );
+    assert_eq!(
+        summaries(&server, &["--group-by", "category", "--sort", "summary"]).await,
+        "Errands\tCall bank\nErrands\tPay rent\nPhone\tCall bank\nNo category\tBuy milk\nNo category\tWrite report\n"
+    );
+    assert_eq!(
+        summaries(&server, &["--group-by", "calendar", "--sort", "summary"]).await,
+        "Home\tBuy milk\nHome\tCall bank\nHome\tPay rent\nWork\tWrite report\n"
+    );
+
+    let output = run(Some(&server), &["list", "--group-by", "due-bucket", "--format", "json"]).await;
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json[0]["group"], "Overdue");
+    assert_eq!(json[3]["group"], "No date");
+    let output = run(Some(&server), &["list", "--format", "json"]).await;
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert!(json[0].get("group").is_none());
+
+    assert_eq!(run(Some(&server), &["list", "--sort", "size"]).await.status.code(), Some(2));
+}
+
+#[tokio::test]
+async fn today_and_agenda() {
+    let server = common::server().await;
+    let today = chrono::Local::now().date_naive();
+    let due = |days: i64| format!("DUE;VALUE=DATE:{}", (today + chrono::Duration::days(days)).format("%Y%m%d"));
+    server.put("home", "rent.ics", &mock::todo("rent", "Pay rent", &[&due(-1)]));
+    server.put("home", "plants.ics", &mock::todo("plants", "Water plants", &[&due(0)]));
+    server.put("home", "bins.ics", &mock::todo("bins", "Take out bins", &[&due(3)]));
+    server.put("home", "tax.ics", &mock::todo("tax", "File taxes", &[&due(30)]));
+    let started = format!("DTSTART;VALUE=DATE:{}", (today - chrono::Duration::days(1)).format("%Y%m%d"));
+    server.put("home", "fence.ics", &mock::todo("fence", "Paint fence", &[&started, &due(30)]));
+    server.put("home", "bank.ics", &mock::todo("bank", "Call bank", &["PRIORITY:1"]));
+
+    let today_view = "Overdue\tWrite report\nOverdue\tPay rent\nToday\tWater plants\nToday\tPaint fence\nToday\tCall bank\n";
+    assert_eq!(stdout(&run(Some(&server), &["today", "--columns", "summary"]).await), today_view);
+    //no subcommand is today
+    assert_eq!(stdout(&run(Some(&server), &["--columns", "summary"]).await), today_view);
+
+    let bins = (today + chrono::Duration::days(3)).format("%A %Y-%m-%d");
+    let out = stdout(&run(Some(&server), &["agenda", "--columns", "summary"]).await);
+    assert_eq!(out, format!("{today_view}{bins}\tTake out bins\n"));
+    assert_eq!(stdout(&run(Some(&server), &["agenda", "--days", "3", "--columns", "summary"]).await), today_view);
+    assert_eq!(run(Some(&server), &["agenda", "--days", "0"]).await.status.code(), Some(2));
+}
+
+#[tokio::test]
+async fn history() {
+    let server = common::server().await;
+    //around noon, so the day is the same in any timezone
+    server.put("work", "plan.ics", &mock::todo("plan", "Plan", &["STATUS:COMPLETED", "COMPLETED:20240110T110000Z"]));
+    server.put("work", "memo.ics", &mock::todo("memo", "Memo", &["STATUS:COMPLETED", "COMPLETED:20240111T120000Z"]));
+    server.put("home", "trip.ics", &mock::todo("trip", "Trip", &["STATUS:CANCELLED", "LAST-MODIFIED:20240110T120000Z"]));
+
+    let out = stdout(&run(Some(&server), &["history", "--since", "2024-01-02", "--columns", "summary,status"]).await);
+    assert_eq!(out, "Wednesday 2024-01-10\tPlan\tdone\nWednesday 2024-01-10\tTrip\tcancelled\nThursday 2024-01-11\tMemo\tdone\n");
+    let args = ["history", "--since", "2024-01-02", "--until", "2024-01-10", "--calendar", "Work", "--columns", "summary"];
+    assert_eq!(stdout(&run(Some(&server), &args).await), "Wednesday 2024-01-10\tPlan\n");
+
+    let out = stdout(&run(Some(&server), &["history", "--since", "2024-01-02", "--stats"]).await);
+    assert_eq!(out, "day\t2024-01-10\t1\t1\t50%\nday\t2024-01-11\t1\t0\t100%\nweek\t2024-01-08\t2\t1\t67%\n");
+    let output = run(Some(&server), &["history", "--since", "2024-01-02", "--stats", "--format", "json"]).await;
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json[0]["period"], "day");
+    assert_eq!(json[0]["rate"], 0.5);
+    assert_eq!(json[2]["start"], "2024-01-08");
+
+    assert_eq!(run(Some(&server), &["history", "--since", "last week"]).await.status.code(), Some(2));
+
+    //in São Paulo 2018-11-04 started at 01:00, which doesn't make --since go away
+    server.put("work", "vote.ics", &mock::todo("vote", "Vote", &["STATUS:COMPLETED", "COMPLETED:20181103T120000Z"]));
+    server.put("work", "count.ics", &mock::todo("count", "Count", &["STATUS:COMPLETED", "COMPLETED:20181104T120000Z"]));
+    let args = ["history", "--since", "2018-11-04", "--until", "2018-11-04", "--columns", "summary"];
+    let output = run_with(Some(&server), &args, &[("TZ", "America/Sao_Paulo")]).await;
+    assert_eq!(stdout(&output), "Sunday 2018-11-04\tCount\n");
+    let output = run(Some(&server), &["history", "--until", "+262142-12-31"]).await;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("too late"));
+}
+
+///the id `new` printed
+fn created(output: &Output) -> String {
+    let out = stdout(output);
+    out.strip_prefix("Created ").and_then(|rest| rest.split_once(':')).unwrap().0.to_string()
+}
+
+#[tokio::test]
+async fn subtasks() {
+    let server = common::server().await;
+    let new = |args: &[&str]| {
+        let mut all = vec!["new"];
+        all.extend(args);
+        all.iter().map(|a| a.to_string()).collect::<Vec<String>>()
+    };
+    let make = |args: Vec<String>| {
+        let server = &server;
+        async move { run(Some(server), &args.iter().map(String::as_str).collect::<Vec<_>>()).await }
+    };
+    let plan = created(&make(new(&["Plan trip", "--calendar", "Home", "--due", "2099-01-01", "--category", "Travel"])).await);
+    let flights = created(&make(new(&["Book flights", "--parent", &plan])).await);
+    let pack = created(&make(new(&["Pack", "--parent", &plan])).await);
+    created(&make(new(&["Passport", "--parent", &flights])).await);
+    let ics = server.files("home").iter().find_map(|f| server.get("home", f).filter(|ics| ics.contains("SUMMARY:Pack"))).unwrap();
+    assert!(ics.contains("RELATED-TO;RELTYPE=PARENT:"));
+    assert!(server.get("home", &format!("{}.ics", pack)).is_none());
+
+    let list = ["list", "--calendar", "Home", "--columns", "summary"];
+    assert_eq!(stdout(&run(Some(&server), &list).await), "Plan trip (0/2)\n  Book flights (0/1)\n    Passport\n  Pack\nBuy milk\n");
+    assert!(stdout(&run(Some(&server), &["list", "--flat", "--calendar", "Home", "--columns", "summary"]).await).starts_with("Plan trip\nBook flights\n"));
+
+    assert!(run(Some(&server), &["done", &pack]).await.status.success());
+    assert_eq!(stdout(&run(Some(&server), &list).await), "Plan trip (1/2)\n  Book flights (0/1)\n    Passport\nBuy milk\n");
+
+    //what's left of the tree moves up
+    let output = run(Some(&server), &["done", &plan]).await;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("2 subtask(s) of Plan trip are still open"));
+    assert_eq!(stdout(&run(Some(&server), &list).await), "Book flights (0/1)\n  Passport\nBuy milk\n");
+
+    let output = run(Some(&server), &["undone", &plan]).await;
+    assert_eq!(stdout(&output), "Reopened: Plan trip\n");
+    let output = run(Some(&server), &["done", "--recursive", &plan]).await;
+    let out = stdout(&output);
+    assert!(out.starts_with("Done: Plan trip\n"));
+    assert!(out.contains("Done: Passport\n"));
+    assert!(out.contains("Already done: Pack\n"));
+    assert_eq!(stdout(&run(Some(&server), &list).await), "Buy milk\n");
+
+    assert!(run(Some(&server), &["undone", "-r", &plan]).await.status.success());
+    let output = run(Some(&server), &["move", "--calendar", "Work", &plan]).await;
+    assert!(output.status.success());
+    assert_eq!(stdout(&output).matches("Moved to Work: ").count(), 4);
+    assert_eq!(server.files("home"), ["milk.ics"]);
+    let list = ["list", "--calendar", "Work", "--columns", "summary"];
+    assert_eq!(stdout(&run(Some(&server), &list).await), "Write report\nPlan trip (0/2)\n  Book flights (0/1)\n    Passport\n  Pack\n");
+    assert_eq!(stdout(&run(Some(&server), &["move", "-c", "Work", &flights]).await), "Already in Work: Book flights\nAlready in Work: Passport\n");
+
+    let output = run(Some(&server), &["list", "--calendar", "Work", "--format", "json"]).await;
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    let plan = json.as_array().unwrap().iter().find(|t| t["summary"] == "Plan trip").unwrap();
+    assert_eq!(plan["subtasks"]["total"], 2);
+    assert_eq!(plan["children"].as_array().unwrap().len(), 2);
+
+    let output = run(Some(&server), &["new", "Sunscreen", "--parent", &flights, "--calendar", "Home"]).await;
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(run(Some(&server), &["new", "Later", "--due", "someday"]).await.status.code(), Some(2));
+    assert_eq!(run(Some(&server), &["new", "Later", "--due", "+99999999999d"]).await.status.code(), Some(2));
+    assert_eq!(run(Some(&server), &["new", "Later", "--due", "+99999999999999h"]).await.status.code(), Some(2));
+}
+
+///the stored iCalendar object with `summary`, wherever it is
+fn ics_of(server: &MockServer, summary: &str) -> String {
+    ["work", "home"]
+        .iter()
+        .flat_map(|cal| server.files(cal).into_iter().filter_map(|f| server.get(cal, &f)))
+        .find(|ics| ics.contains(&format!("SUMMARY:{summary}\r\n")))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn alarms() {
+    let server = common::server().await;
+    let args = ["new", "Call mom", "--due", "2099-01-01 10:00", "--alarm", "-15m", "--alarm", "2099-01-01 09:00"];
+    let output = run(Some(&server), &[&args[..], &["--alarm-repeat", "2x5m"]].concat()).await;
+    assert!(output.status.success());
+    let id = created(&output);
+    let ics = ics_of(&server, "Call mom");
+    assert_eq!(ics.matches("BEGIN:VALARM").count(), 2);
+    assert!(ics.contains("TRIGGER;RELATED=END:-PT15M\r\nREPEAT:2\r\nDURATION:PT5M\r\n"));
+    assert!(ics.contains("TRIGGER;VALUE=DATE-TIME:"));
+    let json: serde_json::Value = serde_json::from_str(&stdout(&run(Some(&server), &["info", "--json", &id]).await)).unwrap();
+    assert_eq!(json[0]["alarms"][0]["when"], "15 minutes before due");
+
+    let output = run(Some(&server), &["new", "Call dad", "--alarm", "-15m"]).await;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("needs a due date"));
+    assert_eq!(run(Some(&server), &["new", "Call dad", "--alarm", "tomorrow"]).await.status.code(), Some(2));
+
+    let output = run(Some(&server), &["edit", &id, "--no-alarms", "--start", "2099-01-01", "--alarm", "start+0m"]).await;
+    assert_eq!(stdout(&output), format!("Edited {id}: Call mom\n"));
+    let ics = ics_of(&server, "Call mom");
+    assert_eq!(ics.matches("BEGIN:VALARM").count(), 1);
+    assert!(ics.contains("DTSTART;VALUE=DATE:20990101\r\n"));
+    assert!(ics.contains("TRIGGER:PT0S\r\n"));
+    assert_eq!(stdout(&run(Some(&server), &["edit", &id]).await), "Unchanged: Call mom\n");
+    assert_eq!(stdout(&run(Some(&server), &["edit", &id, "-s", "Call mum"]).await), format!("Edited {id}: Call mum\n"));
+}
+
+#[tokio::test]
+async fn daemon() {
+    let server = common::server().await;
+    let soon = (chrono::Utc::now() + chrono::Duration::minutes(10)).format("DUE:%Y%m%dT%H%M%SZ").to_string();
+    let alarm = ["BEGIN:VALARM", "ACTION:DISPLAY", "TRIGGER;RELATED=END:-PT15M", "END:VALARM"];
+    server.put("home", "call.ics", &mock::todo("call", "Call mom", &[&[soon.as_str()][..], &alarm].concat()));
+    let later = (chrono::Utc::now() + chrono::Duration::hours(2)).format("DUE:%Y%m%dT%H%M%SZ").to_string();
+    server.put("home", "bins.ics", &mock::todo("bins", "Take out bins", &[&[later.as_str()][..], &alarm].concat()));
+
+    let state = std::env::temp_dir().join(format!("reminder-rs-daemon-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&state);
+    let vars = [("REMINDER_STATE_DIR", state.to_str().unwrap())];
+    let args = ["daemon", "--once", "--command", "echo 'Fired: {summary}' {calendar}"];
+    let output = run_with(Some(&server), &args, &vars).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout(&output), "Alarm for call: Call mom\nFired: Call mom Home\n");
+    assert!(state.join("alarms.json").exists());
+    assert!(server.get("home", "call.ics").unwrap().contains("ACKNOWLEDGED:"));
+
+    //without the ACKNOWLEDGED it's the state that keeps it quiet
+    server.put("home", "call.ics", &mock::todo("call", "Call mom", &[&[soon.as_str()][..], &alarm].concat()));
+    let output = run_with(Some(&server), &[&args[..], &["--no-acknowledge"]].concat(), &vars).await;
+    assert_eq!(stdout(&output), "");
+    assert!(!server.get("home", "call.ics").unwrap().contains("ACKNOWLEDGED:"));
+
+    //a calendar that can't be fetched is skipped, the rest still go off
+    server.put("work", "standup.ics", &mock::todo("standup", "Standup", &[&[soon.as_str()][..], &alarm].concat()));
+    server.inject(Some("REPORT"), Fault::Status(404));
+    let output = run_with(Some(&server), &[&["--jobs", "1"][..], &args].concat(), &vars).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Couldn't check the alarms in Home"));
+    assert_eq!(stdout(&output), "Alarm for standup: Standup\nFired: Standup Work\n");
+
+    //placeholders in what gets filled in stay as they are
+    server.put("work", "braces.ics", &mock::todo("braces", "Ask about {uid} and {due", &[&[soon.as_str()][..], &alarm].concat()));
+    let args = ["daemon", "--once", "--command", "echo {{summary}} {uid}"];
+    let output = run_with(Some(&server), &args, &vars).await;
+    assert_eq!(stdout(&output), "Alarm for braces: Ask about {uid} and {due\n{Ask about {uid} and {due} braces\n");
+    let _ = std::fs::remove_dir_all(&state);
+}
+
+#[tokio::test]
+async fn snooze() {
+    let server = common::server().await;
+    server.put("home", "rent.ics", &mock::todo("rent", "Pay rent", &["DTSTART;VALUE=DATE:20240101", "DUE;VALUE=DATE:20240103"]));
+    let utc = [("TZ", "UTC")];
+    let snooze = |args: &'static [&'static str]| run_with(Some(&server), args, &utc);
+
+    assert_eq!(stdout(&snooze(&["snooze", "report", "+1d"]).await), "Snoozed to 2024-01-06 17:00: Write report\n");
+    assert!(server.get("work", "report.ics").unwrap().contains("DUE:20240106T170000Z\r\n"));
+    let output = snooze(&["snooze", "rent", "milk", "+2d"]).await;
+    let today = chrono::Utc::now().date_naive();
+    let in_two_days = (today + chrono::Duration::days(2)).format("%Y%m%d");
+    assert_eq!(stdout(&output), format!("Snoozed to 2024-01-05: Pay rent\nSnoozed to {}: Buy milk\n", today + chrono::Duration::days(2)));
+    let rent = server.get("home", "rent.ics").unwrap();
+    assert!(rent.contains("DUE;VALUE=DATE:20240105\r\n"));
+    assert!(rent.contains("DTSTART;VALUE=DATE:20240103\r\n"));
+    assert!(server.get("home", "milk.ics").unwrap().contains(&format!("DUE;VALUE=DATE:{in_two_days}\r\n")));
+
+    let output = snooze(&["snooze", "rent", "slides", "+2h"]).await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("only moves by whole days"));
+    assert_eq!(stdout(&output), "Already done: Make slides\n");
+    assert_eq!(snooze(&["snooze", "report", "+9999999999999w"]).await.status.code(), Some(2));
+    let output = snooze(&["snooze", "report", "+1000000000w"]).await;
+    assert!(String::from_utf8_lossy(&output.stderr).contains("further than a date can go"));
+
+    //a day keeps the time of day, a time is taken as it is
+    snooze(&["snooze", "report", "2099-01-01"]).await;
+    assert!(server.get("work", "report.ics").unwrap().contains("DUE:20990101T170000Z\r\n"));
+    snooze(&["snooze", "report", "2099-01-02 08:30"]).await;
+    assert!(server.get("work", "report.ics").unwrap().contains("DUE:20990102T083000Z\r\n"));
+
+    let output = snooze(&["--format", "json", "snooze", "report", "--", "-1d"]).await;
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json[0]["result"], "snoozed");
+
+    server.put("work", "report.ics", &mock::todo("report", "Write report", &["DUE:20240105T170000Z"]));
+    let output = snooze(&["postpone-overdue"]).await;
+    assert!(output.status.success());
+    assert_eq!(stdout(&output).lines().count(), 2);
+    //17:00 today, unless that's past too
+    let report = server.get("work", "report.ics").unwrap();
+    let due = today.format("%Y%m%d");
+    assert!(report.contains(&format!("DUE:{due}T170000Z\r\n")) || report.contains(&format!("DUE;VALUE=DATE:{due}\r\n")));
+    assert!(server.get("home", "rent.ics").unwrap().contains(&format!("DUE;VALUE=DATE:{due}\r\n")));
+    assert_eq!(stdout(&snooze(&["postpone-overdue"]).await), "Nothing is overdue\n");
+
+    //only the calendar asked for, a namesake elsewhere stays put
+    server.put("work", "late.ics", &mock::todo("late", "File taxes", &["DUE;VALUE=DATE:20240101"]));
+    server.put("home", "late.ics", &mock::todo("late", "Plan party", &["DUE;VALUE=DATE:20990101"]));
+    let output = snooze(&["postpone-overdue", "--calendar", "Work"]).await;
+    assert!(output.status.success());
+    assert_eq!(stdout(&output).lines().count(), 1);
+    assert!(server.get("work", "late.ics").unwrap().contains(&format!("DUE;VALUE=DATE:{due}\r\n")));
+    assert!(server.get("home", "late.ics").unwrap().contains("DUE;VALUE=DATE:20990101\r\n"));
+}
+
+#[tokio::test]
+async fn bulk() {
+    let server = common::server().await;
+    server.put("home", "eggs.ics", &mock::todo("eggs", "Buy eggs", &["CATEGORIES:Groceries", "STATUS:COMPLETED"]));
+    server.put("home", "bread.ics", &mock::todo("bread", "Buy bread", &["CATEGORIES:Groceries"]));
+    server.put("home", "rent.ics", &mock::todo("rent", "Pay rent", &["DUE;VALUE=DATE:20240103"]));
+    let done_groceries = ["delete", "--where", "cat:groceries and status:done"];
+
+    let output = run(Some(&server), &[&done_groceries[..], &["--dry-run"]].concat()).await;
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Buy eggs"));
+    assert!(!stdout(&output).contains("Buy bread"));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Dry run"));
+    assert!(server.get("home", "eggs.ics").is_some());
+
+    //there's no one to say yes
+    let output = run(Some(&server), &done_groceries).await;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("eggs  Buy eggs"));
+    assert!(server.get("home", "eggs.ics").is_some());
+    let output = run(Some(&server), &[&done_groceries[..], &["--yes"]].concat()).await;
+    assert_eq!(stdout(&output), "Deleted: Buy eggs\n");
+    assert!(server.get("home", "eggs.ics").is_none());
+
+    let output = run(Some(&server), &["edit", "--where", "cat:groceries", "--set", "priority=1", "--set", "location=Bakery", "-y"]).await;
+    assert_eq!(stdout(&output), "Edited bread: Buy bread\n");
+    let bread = server.get("home", "bread.ics").unwrap();
+    assert!(bread.contains("PRIORITY:1\r\n"));
+    assert!(bread.contains("LOCATION:Bakery\r\n"));
+    assert_eq!(stdout(&run(Some(&server), &["edit", "bread", "--set", "location="]).await), "Edited bread: Buy bread\n");
+    assert!(!server.get("home", "bread.ics").unwrap().contains("LOCATION"));
+    assert_eq!(run(Some(&server), &["edit", "bread", "--set", "colour=red"]).await.status.code(), Some(2));
+
+    assert_eq!(stdout(&run(Some(&server), &["done", "--where", "cal:nowhere", "-y"]).await), "Nothing matches\n");
+    assert_eq!(run(Some(&server), &["snooze", "--where", "bread", "milk", "+1d"]).await.status.code(), Some(2));
+    assert_eq!(run(Some(&server), &["done", "--where", "cat:groceries", "milk"]).await.status.code(), Some(2));
+
+    //the ones that fail are listed at the end, after the rest went through
+    let output = run(Some(&server), &["snooze", "--where", "cal:home and status:open", "-y", "-j", "2", "+1d"]).await;
+    assert!(output.status.success());
+    assert_eq!(stdout(&output).lines().count(), 3);
+    run(Some(&server), &["edit", "milk", "--set", "due=2099-01-01 10:00"]).await;
+    let output = run(Some(&server), &["snooze", "--where", "cal:home and status:open", "-y", "+2h"]).await;
+    assert!(stdout(&output).contains("Buy milk"));
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("These failed:\n"));
+    assert!(stderr.contains("  rent  Pay rent: it's due on a day"));
+    assert!(stderr.contains("2 of 3 failed"));
+}
+
+#[tokio::test]
+async fn undo() {
+    let server = common::server().await;
+    assert_eq!(stdout(&run(Some(&server), &["undo"]).await), "Nothing to undo\n");
+
+    run(Some(&server), &["done", "report"]).await;
+    let output = run(Some(&server), &["undo"]).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout(&output), "Undoing done report\nRestored: Write report\n");
+    assert!(!server.get("work", "report.ics").unwrap().contains("STATUS:COMPLETED"));
+
+    run(Some(&server), &["delete", "milk", "--yes"]).await;
+    assert!(server.get("home", "milk.ics").is_none());
+    assert_eq!(stdout(&run(Some(&server), &["undo"]).await), "Undoing delete milk --yes\nRecreated: Buy milk\n");
+    assert!(server.get("home", "milk.ics").unwrap().contains("SUMMARY:Buy milk"));
+
+    //a move is a copy and a delete, undone the other way round
+    run(Some(&server), &["move", "milk", "--calendar", "Work"]).await;
+    assert_eq!(stdout(&run(Some(&server), &["undo"]).await), "Undoing move milk --calendar Work\nRecreated: Buy milk\nRemoved: Buy milk\n");
+    assert!(server.get("home", "milk.ics").is_some());
+    assert!(server.get("work", "milk.ics").is_none());
+
+    let log = stdout(&run(Some(&server), &["log"]).await);
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].contains("\tmove milk --calendar Work\t1 created, 1 deleted\t"));
+    assert!(lines[2].contains("\tdone report\tchanged Write report\t"));
+
+    //someone else changed it since
+    run(Some(&server), &["done", "report"]).await;
+    server.put("work", "report.ics", &mock::todo("report", "Write the report", &[]));
+    let output = run(Some(&server), &["undo"]).await;
+    assert_eq!(output.status.code(), Some(7));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("changed again since"));
+    assert!(run(Some(&server), &["undo", "--force"]).await.status.success());
+    assert!(server.get("work", "report.ics").unwrap().contains("SUMMARY:Write report"));
+
+    let output = run(Some(&server), &["--format", "json", "log", "-n", "1"]).await;
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json[0]["command"], "done report");
+    assert_eq!(json[0]["changes"][0]["change"], "changed");
+    assert!(json[0]["undone"].is_string());
+    assert_eq!(stdout(&run(Some(&server), &["undo"]).await), "Nothing to undo\n");
+
+    //commands finishing together each get their operation in
+    let summaries: Vec<String> = (0..6).map(|i| format!("Errand {i}")).collect();
+    let args: Vec<[&str; 4]> = summaries.iter().map(|summary| ["new", summary, "--calendar", "Home"]).collect();
+    let runs = args.iter().map(|args| run(Some(&server), args));
+    assert!(futures::future::join_all(runs).await.iter().all(|output| output.status.success()));
+    let log = stdout(&run(Some(&server), &["log", "-n", "6"]).await);
+    assert!(summaries.iter().all(|summary| log.contains(&format!("created {summary}"))), "{log}");
+}
+
+#[tokio::test]
+async fn archive_and_purge() {
+    let server = common::server().await;
+    server.add_calendar(MockCalendar::builder().id("archive".to_string()).name("Archive".to_string()).build());
+    let state = std::env::temp_dir().join(format!("reminder-rs-archive-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&state);
+    let vars = [("REMINDER_ARCHIVE_CALENDAR", "Archive"), ("REMINDER_STATE_DIR", state.to_str().unwrap())];
+
+    //it can be put back, so there's nothing to confirm
+    let output = run_with(Some(&server), &["delete", "milk"], &vars).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(stdout(&output), "Archived to Archive: Buy milk\n");
+    assert!(server.get("home", "milk.ics").is_none());
+    assert!(server.get("archive", "milk.ics").unwrap().contains("SUMMARY:Buy milk"));
+    assert_eq!(stdout(&run_with(Some(&server), &["delete", "milk"], &vars).await), "Already in Archive: Buy milk\n");
+    run_with(Some(&server), &["delete", "milk", "--permanent", "--yes"], &vars).await;
+    assert!(server.get("archive", "milk.ics").is_none());
+
+    let output = run_with(Some(&server), &["delete", "report", "--local-archive"], &vars).await;
+    assert_eq!(stdout(&output), "Archived: Write report\n");
+    assert!(server.get("work", "report.ics").is_none());
+    let archive = state.join("archive");
+    assert!(std::fs::read_to_string(archive.join("work/report.ics")).unwrap().contains("SUMMARY:Write report"));
+    let output = run_with(None, &["list"], &[("REMINDER_VDIR", archive.to_str().unwrap())]).await;
+    assert!(stdout(&output).contains("Write report"));
+
+    server.put("home", "old.ics", &mock::todo("old", "Renew passport", &["STATUS:COMPLETED", "COMPLETED:20200101T090000Z"]));
+    server.put("home", "new.ics", &mock::todo("new", "Water plants", &["STATUS:COMPLETED", &chrono::Utc::now().format("COMPLETED:%Y%m%dT%H%M%SZ").to_string()]));
+    let output = run_with(Some(&server), &["purge", "--completed-before", "90d", "--dry-run"], &vars).await;
+    assert!(stdout(&output).contains("Renew passport"));
+    assert!(!stdout(&output).contains("Water plants"));
+    assert!(server.get("home", "old.ics").is_some());
+
+    //declined, so nothing is deleted and nothing exported
+    let output = run_with(Some(&server), &["purge", "--completed-before", "90d"], &vars).await;
+    assert_eq!(output.status.code(), Some(1));
+    assert!(server.get("home", "old.ics").is_some());
+    let exported = std::fs::read_dir(&state).unwrap().flatten().any(|entry| entry.file_name().to_string_lossy().starts_with("purged-"));
+    assert!(!exported);
+
+    let export = state.join("old.ics");
+    let args = ["purge", "--completed-before", "90d", "--yes", "--export", export.to_str().unwrap()];
+    let output = run_with(Some(&server), &args, &vars).await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    //for good, not to the archive. slides went with it, done before without saying when
+    assert_eq!(stdout(&output), "Deleted: Renew passport\nDeleted: Make slides\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Exported 2 reminder(s)"));
+    assert!(std::fs::read_to_string(&export).unwrap().contains("SUMMARY:Renew passport"));
+    assert!(server.get("home", "old.ics").is_none());
+    assert!(server.get("archive", "old.ics").is_none());
+    assert!(server.get("home", "new.ics").is_some());
+
+    //exactly what was found goes: not a namesake in another calendar, and one without a UID too
+    server.put("work", "passport.ics", &mock::todo("renew", "Renew passport again", &["STATUS:COMPLETED", "COMPLETED:20200101T090000Z"]));
+    server.put("home", "renew.ics", &mock::todo("renew", "Renew license", &[]));
+    let receipts = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VTODO\r\nSUMMARY:Sort receipts\r\nSTATUS:COMPLETED\r\nCOMPLETED:20200101T090000Z\r\nEND:VTODO\r\nEND:VCALENDAR\r\n";
+    server.put("work", "receipts.ics", receipts);
+    let output = run_with(Some(&server), &["purge", "--completed-before", "90d", "--calendar", "Work", "--yes"], &vars).await;
+    assert_eq!(stdout(&output), "Deleted: Renew passport again\nDeleted: Sort receipts\n");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Exported 2 reminder(s)"));
+    assert!(server.get("work", "passport.ics").is_none());
+    assert!(server.get("work", "receipts.ics").is_none());
+    assert!(server.get("home", "renew.ics").is_some());
+    let output = run_with(Some(&server), &["purge", "--completed-before", "2020-01-01"], &vars).await;
+    assert_eq!(stdout(&output), "Nothing was completed before 2020-01-01\n");
+    let _ = std::fs::remove_dir_all(&state);
+}
+
+#[tokio::test]
+async fn info() {
+    let server = common::server().await;
+    let output = run(Some(&server), &["info", "report"]).await;
+    assert!(output.status.success());
+    let out = stdout(&output);
+    assert!(out.starts_with("Write report\n"));
+    assert!(out.contains("Calendar:    Work"));
+    assert!(out.contains("Due:"));
+    assert!(out.contains("calendars/user/work/report.ics"));
+
+    let output = run(Some(&server), &["info", "--raw", "slides"]).await;
+    assert_eq!(stdout(&output), server.get("work", "slides.ics").unwrap());
+
+    let output = run(Some(&server), &["info", "--json", "report", "milk"]).await;
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json[0]["summary"], "Write report");
+    assert_eq!(json[0]["due"]["utc"], "2024-01-05T17:00:00Z");
+    assert_eq!(json[1]["calendar"], "Home");
+    assert_eq!(json[1]["state"], "open");
+
+    assert_eq!(run(Some(&server), &["info", "nope"]).await.status.code(), Some(5));
+}
+
+#[tokio::test]
+async fn output_formats() {
+    let server = common::server().await;
+
+    //piped, so plain: tab separated and no escapes
+    let out = stdout(&run(Some(&server), &["list", "--calendar", "Work"]).await);
+    assert_eq!(out.lines().count(), 1);
+    assert!(out.starts_with("report\tWork\tWrite report\t2024-01-0"));
+    assert!(!stdout(&run(Some(&server), &["calendars"]).await).contains('\x1B'));
+
+    let out = stdout(&run(Some(&server), &["list", "--columns", "summary,status", "--calendar", "Home"]).await);
+    assert_eq!(out, "Buy milk\topen\n");
+
+    let out = stdout(&run(Some(&server), &["list", "--format", "table", "--columns", "id,summary"]).await);
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines[0], "ID      SUMMARY");
+    assert!(lines.contains(&"milk    Buy milk"));
+    assert!(!out.contains('\x1B'));
+
+    let output = run(Some(&server), &["list", "--format", "json"]).await;
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(json.as_array().unwrap().len(), 2);
+    assert!(json.as_array().unwrap().iter().any(|t| t["uid"] == "milk" && t["calendar"] == "Home"));
+
+    let out = stdout(&run(Some(&server), &["search", "milk", "--format", "ndjson"]).await);
+    assert_eq!(out.lines().count(), 1);
+    let json: serde_json::Value = serde_json::from_str(out.lines().next().unwrap()).unwrap();
+    assert_eq!(json["summary"], "Buy milk");
+
+    let out = stdout(&run(Some(&server), &["calendars", "--format", "json"]).await);
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert!(json.as_array().unwrap().iter().any(|c| c["name"] == "Work" && c["color"] == "#ff0000"));
+
+    let out = stdout(&run(Some(&server), &["server-info", "--format", "json"]).await);
+    let json: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert!(json["server"]["dav"].as_array().unwrap().iter().any(|c| c == "calendar-access"));
+
+    let output = run(Some(&server), &["done", "milk", "nope", "--format", "ndjson"]).await;
+    assert_eq!(output.status.code(), Some(5));
+    let results: Vec<serde_json::Value> = stdout(&output).lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(results[0]["result"], "done");
+    assert_eq!(results[1]["result"], "failed");
+
+    assert_eq!(run(Some(&server), &["list", "--format", "yaml"]).await.status.code(), Some(2));
+}
+
+//`interactive` needs a terminal, and the remaining subcommands don't do anything yet
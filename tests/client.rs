@@ -0,0 +1,667 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use reminder_rs::caldav::todo::set_property;
+use reminder_rs::caldav::discovery::{self, ResolveFuture, Resolver, SrvRecord};
+use reminder_rs::caldav::{CalDAVClient, CalendarProps, Credentials, Error, TodoState};
+use reminder_rs::mock::{self, Auth, Fault, MockCalendar, MockServer};
+use reminder_rs::store::{self, Query, TodoStore};
+
+mod common;
+
+async fn connect(server: &MockServer) -> reminder_rs::caldav::Result<CalDAVClient> {
+    CalDAVClient::builder()
+        .base_url(&server.url())
+        .credentials(Credentials::default())
+        .timeout(Duration::from_secs(2))
+        .build()
+        .await
+}
+
+fn summaries(todos: &reminder_rs::caldav::Partial<reminder_rs::caldav::CalendarTodo>) -> Vec<String> {
+    let mut summaries: Vec<String> = todos.items.iter().filter_map(|t| t.property("SUMMARY")).collect();
+    summaries.sort();
+    summaries
+}
+
+#[tokio::test]
+async fn discovers_calendars() {
+    let server = common::server().await;
+    let client = connect(&server).await.unwrap();
+
+    let mut names = vec![];
+    for cal in &client.calendars {
+        let cal = cal.read().await;
+        names.push((cal.name.clone(), cal.color.clone(), cal.url.clone()));
+    }
+    names.sort();
+    assert_eq!(names, vec![
+        ("Home".to_string(), None, format!("{}calendars/user/home/", server.url())),
+        ("Work".to_string(), Some("#ff0000".to_string()), format!("{}calendars/user/work/", server.url())),
+    ]);
+}
+
+#[tokio::test]
+async fn splits_current_and_completed_todos() {
+    let server = common::server().await;
+    let client = connect(&server).await.unwrap();
+    let work = client.get_calendar("Work").await.unwrap();
+
+    let current = client.todos(&work, Query::Current).await.unwrap();
+    assert_eq!(summaries(&current), ["Write report"]);
+    assert!(current.errors.is_empty());
+    assert_eq!(current.items[0].url, format!("{}calendars/user/work/report.ics", server.url()));
+
+    let completed = client.todos(&work, Query::Completed).await.unwrap();
+    assert_eq!(summaries(&completed), ["Make slides"]);
+}
+
+#[tokio::test]
+async fn fetches_many_calendars_in_order() {
+    let server = common::server().await;
+    let client = connect(&server).await.unwrap();
+
+    let results = store::current_todos_many(&client, &client.calendars, 2).await;
+    let mut found = vec![];
+    for (cal, todos) in client.calendars.iter().zip(results) {
+        found.push((cal.read().await.name.clone(), summaries(&todos.unwrap())));
+    }
+    found.sort();
+    assert_eq!(found, vec![
+        ("Home".to_string(), vec!["Buy milk".to_string()]),
+        ("Work".to_string(), vec!["Write report".to_string()]),
+    ]);
+}
+
+#[tokio::test]
+async fn uses_cache_until_ctag_changes() {
+    let server = common::server().await;
+    let client = connect(&server).await.unwrap();
+    let work = client.get_calendar("Work").await.unwrap();
+    let reports = |server: &MockServer| server.requests().iter().filter(|r| r.starts_with("REPORT")).count();
+
+    client.get_current_todos(&work).await.unwrap();
+    let before = reports(&server);
+    client.get_current_todos(&work).await.unwrap();
+    assert_eq!(reports(&server), before);
+
+    server.put("work", "call.ics", &mock::todo("call", "Call Bob", &[]));
+    let todos = client.get_current_todos(&work).await.unwrap();
+    assert!(reports(&server) > before);
+    assert_eq!(summaries(&todos), ["Call Bob", "Write report"]);
+}
+
+#[tokio::test]
+async fn broken_todo_is_reported_not_fatal() {
+    let server = common::server().await;
+    server.put("work", "broken.ics", &mock::todo("broken", "Broken", &["this is not a content line"]));
+    let client = connect(&server).await.unwrap();
+    let work = client.get_calendar("Work").await.unwrap();
+
+    let todos = client.todos(&work, Query::Current).await.unwrap();
+    assert_eq!(summaries(&todos), ["Write report"]);
+    assert_eq!(todos.errors.len(), 1);
+    assert!(todos.errors[0].href.ends_with("broken.ics"));
+    assert!(matches!(todos.errors[0].error, Error::ICal { .. }));
+}
+
+#[tokio::test]
+async fn basic_auth() {
+    let server = MockServer::builder()
+        .username("me".to_string())
+        .password("secret".to_string())
+        .build()
+        .await
+        .unwrap();
+    common::populate(&server);
+
+    assert!(matches!(connect(&server).await, Err(Error::Auth { .. })));
+
+    let before = server.authorizations().len();
+    let client = CalDAVClient::builder()
+        .base_url(&server.url())
+        .credentials(Credentials::builder().username("me".to_string()).password("secret".to_string()).build())
+        .build()
+        .await
+        .unwrap();
+    assert_eq!(client.calendars.len(), 2);
+    //the password only goes out once the server has asked for Basic
+    let sent = &server.authorizations()[before..];
+    assert_eq!(sent[0], None);
+    assert!(sent[1].as_ref().unwrap().starts_with("Basic "));
+}
+
+async fn login(server: &MockServer, credentials: Credentials) -> reminder_rs::caldav::Result<CalDAVClient> {
+    CalDAVClient::builder()
+        .base_url(&server.url())
+        .credentials(credentials)
+        .timeout(Duration::from_secs(2))
+        .build()
+        .await
+}
+
+fn password(username: &str) -> Credentials {
+    Credentials::builder().username(username.to_string()).password("secret".to_string()).build()
+}
+
+#[tokio::test]
+async fn digest_auth() {
+    let server = MockServer::builder()
+        .username("me".to_string())
+        .password("secret".to_string())
+        .auth(Auth::Digest(vec!["MD5".to_string()]))
+        .build()
+        .await
+        .unwrap();
+    common::populate(&server);
+
+    let wrong = Credentials::builder().username("me".to_string()).password("wrong".to_string()).build();
+    assert!(matches!(login(&server, wrong).await, Err(Error::Auth { .. })));
+
+    let before = server.authorizations().len();
+    let client = login(&server, password("me")).await.unwrap();
+    let work = client.get_calendar("Work").await.unwrap();
+    let todos = client.todos(&work, Query::Current).await.unwrap();
+    assert_eq!(summaries(&todos), ["Write report"]);
+
+    //the server turns away a nonce count it has seen before, so getting
+    //this far means every request counted
+    let sent: Vec<String> = server.authorizations().into_iter().skip(before).flatten().collect();
+    assert!(sent.len() > 2);
+    assert!(sent.iter().all(|a| a.contains("algorithm=MD5") && a.contains("qop=auth")));
+    assert!(sent[0].contains("nc=00000001"));
+    assert!(sent[1].contains("nc=00000002"));
+}
+
+#[tokio::test]
+async fn digest_prefers_sha256() {
+    let username = r#"me "the \ user""#;
+    let server = MockServer::builder()
+        .username(username.to_string())
+        .password("secret".to_string())
+        .auth(Auth::Digest(vec!["MD5".to_string(), "SHA-256".to_string(), "MD5-sess".to_string()]))
+        .build()
+        .await
+        .unwrap();
+    common::populate(&server);
+
+    let client = login(&server, password(username)).await.unwrap();
+    assert_eq!(client.calendars.len(), 2);
+    let sent: Vec<String> = server.authorizations().into_iter().flatten().collect();
+    assert!(!sent.is_empty());
+    assert!(sent.iter().all(|a| a.contains("algorithm=SHA-256")));
+    assert!(sent[0].contains(r#"username="me \"the \\ user\"""#));
+}
+
+fn bearer_server() -> Auth {
+    Auth::Bearer { token: "fresh".to_string(), refresh_token: "renew".to_string() }
+}
+
+#[tokio::test]
+async fn bearer_auth() {
+    let server = MockServer::builder().auth(bearer_server()).build().await.unwrap();
+    common::populate(&server);
+
+    let client = login(&server, Credentials::builder().token("fresh".to_string()).build()).await.unwrap();
+    assert_eq!(client.calendars.len(), 2);
+    let sent = server.authorizations();
+    assert_eq!(sent[0], None);
+    assert!(sent.iter().skip(1).all(|a| a.as_deref() == Some("Bearer fresh")));
+
+    //a rejected token with no way to refresh it is the end of it
+    let stale = Credentials::builder().token("stale".to_string()).build();
+    assert!(matches!(login(&server, stale).await, Err(Error::Auth { .. })));
+}
+
+#[tokio::test]
+async fn bearer_refresh() {
+    let server = MockServer::builder().auth(bearer_server()).build().await.unwrap();
+    common::populate(&server);
+
+    let credentials = Credentials::builder()
+        .token("stale".to_string())
+        .refresh_token("renew".to_string())
+        .token_url(format!("{}token", server.url()))
+        .build();
+    let client = login(&server, credentials).await.unwrap();
+    assert_eq!(client.calendars.len(), 2);
+    let sent = server.authorizations();
+    assert!(sent.contains(&Some("Bearer stale".to_string())));
+    assert_eq!(sent.last().unwrap().as_deref(), Some("Bearer fresh"));
+    assert!(server.requests().contains(&"POST /token".to_string()));
+
+    //a refresh token the server doesn't know fails the login
+    let credentials = Credentials::builder()
+        .token("stale".to_string())
+        .refresh_token("forgotten".to_string())
+        .token_url(format!("{}token", server.url()))
+        .build();
+    assert!(matches!(login(&server, credentials).await, Err(Error::Auth { .. })));
+}
+
+#[tokio::test]
+async fn quirky_xml() {
+    let server = MockServer::builder().quirky_xml(true).build().await.unwrap();
+    common::populate(&server);
+    let client = connect(&server).await.unwrap();
+    assert_eq!(client.calendars.len(), 2);
+
+    let work = client.get_calendar("Work").await.unwrap();
+    let todos = client.todos(&work, Query::Current).await.unwrap();
+    assert_eq!(summaries(&todos), ["Write report"]);
+    assert_eq!(todos.items[0].url, format!("{}calendars/user/work/report.ics", server.url()));
+}
+
+#[tokio::test]
+async fn retries_server_errors() {
+    let server = common::server().await;
+    server.inject(Some("PROPFIND"), Fault::Status(503));
+    assert!(connect(&server).await.is_ok());
+
+    server.inject(Some("PROPFIND"), Fault::Status(503));
+    let res = CalDAVClient::builder()
+        .base_url(&server.url())
+        .credentials(Credentials::default())
+        .retries(0)
+        .build()
+        .await;
+    assert!(matches!(res, Err(Error::Status { status, .. }) if status == 503));
+}
+
+#[tokio::test]
+async fn times_out() {
+    let server = common::server().await;
+    server.inject(None, Fault::Delay(Duration::from_secs(5)));
+    let res = CalDAVClient::builder()
+        .base_url(&server.url())
+        .credentials(Credentials::default())
+        .timeout(Duration::from_millis(200))
+        .retries(0)
+        .build()
+        .await;
+    assert!(matches!(res, Err(Error::Timeout { .. })));
+}
+
+#[tokio::test]
+async fn conditional_writes_are_not_retried() {
+    let server = common::server().await;
+    let client = CalDAVClient::builder()
+        .base_url(&server.url())
+        .credentials(Credentials::default())
+        .timeout(Duration::from_millis(300))
+        .build()
+        .await
+        .unwrap();
+    let home = client.get_calendar("Home").await.unwrap();
+    let home = home.read().await;
+
+    //the create goes through but the answer comes too late. trying again
+    //would only get a conflict from If-None-Match
+    server.inject(Some("PUT"), Fault::Delay(Duration::from_millis(600)));
+    let res = client.create(&home, "bread", &mock::todo("bread", "Buy bread", &[])).await;
+    assert!(matches!(res, Err(Error::Timeout { .. })));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(server.get("home", "bread.ics").is_some());
+    assert_eq!(server.requests().iter().filter(|r| r.starts_with("PUT ")).count(), 1);
+}
+
+#[tokio::test]
+async fn dropped_connection() {
+    let server = common::server().await;
+    let client = connect(&server).await.unwrap();
+    let work = client.get_calendar("Work").await.unwrap();
+
+    server.inject(Some("REPORT"), Fault::Disconnect);
+    assert!(matches!(client.todos(&work, Query::Current).await, Err(Error::Transport { .. })));
+}
+
+#[tokio::test]
+async fn credentials_stay_on_their_origin() {
+    let mut servers = vec![];
+    for _ in 0..2 {
+        let server = MockServer::builder()
+            .username("me".to_string())
+            .password("secret".to_string())
+            .build()
+            .await
+            .unwrap();
+        common::populate(&server);
+        servers.push(server);
+    }
+    let (server, elsewhere) = (&servers[0], &servers[1]);
+    let client = login(server, password("me")).await.unwrap();
+    let work = client.get_calendar("Work").await.unwrap();
+
+    //another port on the same host is another site
+    server.inject(Some("REPORT"), Fault::Redirect(format!("{}calendars/user/work/", elsewhere.url())));
+    assert!(matches!(client.todos(&work, Query::Current).await, Err(Error::Auth { .. })));
+    assert!(!elsewhere.authorizations().is_empty());
+    assert!(elsewhere.authorizations().iter().all(Option::is_none));
+}
+
+#[tokio::test]
+async fn create_update_delete() {
+    let server = common::server().await;
+    let client = connect(&server).await.unwrap();
+    let home = client.get_calendar("Home").await.unwrap();
+    let home = home.read().await;
+
+    let ics = mock::todo("bread", "Buy bread", &[]);
+    let todo = client.create(&home, "bread", &ics).await.unwrap();
+    assert_eq!(server.get("home", "bread.ics").as_deref(), Some(ics.as_str()));
+    assert_eq!(todo.property("SUMMARY").as_deref(), Some("Buy bread"));
+
+    //already exists
+    assert!(matches!(client.create(&home, "bread", &ics).await, Err(Error::Conflict { .. })));
+
+    let ics = mock::todo("bread", "Buy rye bread", &[]);
+    let updated = client.update(&todo, &ics).await.unwrap();
+    assert_ne!(updated.etag, todo.etag);
+    assert_eq!(server.get("home", "bread.ics").as_deref(), Some(ics.as_str()));
+
+    //stale etag
+    assert!(matches!(client.update(&todo, &ics).await, Err(Error::Conflict { .. })));
+    assert!(matches!(client.delete(&todo).await, Err(Error::Conflict { .. })));
+
+    client.delete(&updated).await.unwrap();
+    assert_eq!(server.files("home"), ["milk.ics"]);
+    assert!(matches!(client.delete(&updated).await, Err(Error::NotFound { .. })));
+}
+
+#[tokio::test]
+async fn done_cancel_reopen() {
+    let server = common::server().await;
+    let client = connect(&server).await.unwrap();
+    let home = client.get_calendar("Home").await.unwrap();
+    let now = chrono::Utc::now();
+
+    let milk = &client.todos(&home, Query::Current).await.unwrap().items[0];
+    assert_eq!(milk.state(), TodoState::Open);
+    let done = client.update(milk, &milk.completed(now)).await.unwrap();
+    assert_eq!(done.state(), TodoState::Completed);
+    assert_eq!(done.property("PERCENT-COMPLETE").as_deref(), Some("100"));
+    assert!(done.property("COMPLETED").is_some());
+    assert!(summaries(&client.todos(&home, Query::Current).await.unwrap()).is_empty());
+
+    let open = client.update(&done, &done.reopened(now)).await.unwrap();
+    assert_eq!(open.state(), TodoState::Open);
+    assert_eq!(open.property("STATUS").as_deref(), Some("NEEDS-ACTION"));
+    assert_eq!(open.property("COMPLETED"), None);
+    assert_eq!(summaries(&client.todos(&home, Query::Current).await.unwrap()), ["Buy milk"]);
+
+    //cancelled todos don't count as current, even with no COMPLETED date
+    let cancelled = client.update(&open, &open.cancelled(now)).await.unwrap();
+    assert_eq!(cancelled.state(), TodoState::Cancelled);
+    assert!(summaries(&client.todos(&home, Query::Current).await.unwrap()).is_empty());
+    assert_eq!(summaries(&client.todos(&home, Query::Cancelled).await.unwrap()), ["Buy milk"]);
+    assert_eq!(summaries(&client.todos(&home, Query::All).await.unwrap()), ["Buy milk"]);
+
+    //the rest of the object is left alone
+    assert!(cancelled.ics.contains("\r\nSUMMARY:Buy milk\r\n"));
+    assert!(cancelled.ics.ends_with("END:VTODO\r\nEND:VCALENDAR\r\n"));
+}
+
+#[tokio::test]
+async fn history_by_completed_time() {
+    let server = common::server().await;
+    server.put("work", "plan.ics", &mock::todo("plan", "Plan", &["STATUS:COMPLETED", "COMPLETED:20240110T100000Z"]));
+    server.put("work", "memo.ics", &mock::todo("memo", "Memo", &["STATUS:COMPLETED", "COMPLETED:20240201T100000Z"]));
+    server.put("work", "trip.ics", &mock::todo("trip", "Trip", &["STATUS:CANCELLED", "LAST-MODIFIED:20240115T000000Z"]));
+    let client = connect(&server).await.unwrap();
+    let work = client.get_calendar("Work").await.unwrap();
+    let at = |day| chrono::NaiveDate::from_ymd_opt(2024, 1, day).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc();
+
+    //slides has no COMPLETED, so it finished when it was stamped
+    let all = client.todos(&work, Query::History { since: None, until: None }).await.unwrap();
+    assert_eq!(summaries(&all), ["Make slides", "Memo", "Plan", "Trip"]);
+    let slides = all.items.iter().find(|t| t.uid().as_deref() == Some("slides")).unwrap();
+    assert_eq!(slides.finished(), Some(at(1)));
+
+    let reports = || server.requests().iter().filter(|r| r.starts_with("REPORT")).count();
+    let before = reports();
+    let january = client.todos(&work, Query::History { since: Some(at(5)), until: Some(at(31)) }).await.unwrap();
+    assert_eq!(summaries(&january), ["Plan", "Trip"]);
+    //one for the time-range on COMPLETED, one for the todos without one
+    assert_eq!(reports() - before, 2);
+
+    //a server that won't filter by time gets asked for everything
+    server.inject(Some("REPORT"), Fault::Status(403));
+    let january = client.todos(&work, Query::History { since: Some(at(5)), until: Some(at(30)) }).await.unwrap();
+    assert_eq!(summaries(&january), ["Plan", "Trip"]);
+}
+
+#[test]
+fn set_property_skips_alarms() {
+    let ics = mock::todo("tea", "Tea", &["BEGIN:VALARM", "ACTION:DISPLAY", "DESCRIPTION:Tea", "END:VALARM", "DESCRIPTION:long", " er text"]);
+    let ics = set_property(&ics, "DESCRIPTION", Some("short"));
+    assert!(ics.contains("BEGIN:VALARM\r\nACTION:DISPLAY\r\nDESCRIPTION:Tea\r\nEND:VALARM\r\n"));
+    assert!(ics.contains("DESCRIPTION:short\r\nEND:VTODO"));
+    assert!(!ics.contains("er text"));
+}
+
+#[tokio::test]
+async fn rejects_unsupported_component() {
+    let server = common::server().await;
+    server.add_calendar(
+        MockCalendar::builder()
+            .id("events".to_string())
+            .name("Events".to_string())
+            .components(vec!["VEVENT".to_string()])
+            .build(),
+    );
+    let client = connect(&server).await.unwrap();
+    let events = client.get_calendar("Events").await.unwrap();
+    let mut events = events.write().await;
+    assert!(!events.supports_todo);
+
+    //refused before it gets to the server
+    let res = client.create(&events, "x", &mock::todo("x", "x", &[])).await;
+    assert!(matches!(res, Err(Error::Unsupported { .. })));
+    assert!(!server.requests().iter().any(|r| r.starts_with("PUT")));
+
+    //and if we got it wrong, the server says so
+    events.supports_todo = true;
+    let res = client.create(&events, "x", &mock::todo("x", "x", &[])).await;
+    match res {
+        Err(Error::Forbidden { error: Some(error), .. }) => {
+            assert!(error.conditions.iter().any(|c| c == "supported-calendar-component"))
+        }
+        _ => panic!("expected a 403"),
+    }
+}
+
+#[tokio::test]
+async fn capabilities() {
+    let server = common::server().await;
+    server.add_calendar(
+        MockCalendar::builder()
+            .id("small".to_string())
+            .name("Small".to_string())
+            .max_resource_size(200)
+            .build(),
+    );
+    let client = connect(&server).await.unwrap();
+    assert!(client.server.dav.iter().any(|c| c == "calendar-access"));
+    assert!(client.server.allow.iter().any(|m| m.eq_ignore_ascii_case("report")));
+
+    let small = client.get_calendar("Small").await.unwrap();
+    let small = small.read().await;
+    let caps = &small.capabilities;
+    assert!(caps.reports.iter().any(|r| r == "calendar-query"));
+    assert!(caps.reports.iter().any(|r| r == "sync-collection"));
+    assert!(!caps.reports.iter().any(|r| r == "free-busy-query"));
+    assert_eq!(caps.max_resource_size, Some(200));
+    assert_eq!(caps.data_types[0].content_type, "text/calendar");
+    assert_eq!(caps.data_types[0].version.as_deref(), Some("2.0"));
+
+    //refused before it gets to the server
+    let puts = || server.requests().iter().filter(|r| r.starts_with("PUT")).count();
+    let big = mock::todo("big", &"x".repeat(300), &[]);
+    assert!(matches!(client.create(&small, "big", &big).await, Err(Error::Unsupported { .. })));
+    assert_eq!(puts(), 0);
+    let ok = client.create(&small, "ok", &mock::todo("ok", "Fits", &[])).await.unwrap();
+    let grown = set_property(&ok.ics, "SUMMARY", Some(&"x".repeat(300)));
+    assert!(matches!(client.update(&ok, &grown).await, Err(Error::Unsupported { .. })));
+    assert_eq!(puts(), 1);
+}
+
+#[tokio::test]
+async fn manage_calendars() {
+    let server = common::server().await;
+    let client = connect(&server).await.unwrap();
+
+    let props = CalendarProps::builder()
+        .name("Groceries".to_string())
+        .color("#00ff00".to_string())
+        .order(1)
+        .build();
+    let groceries = client.create_calendar("groceries", &props).await.unwrap();
+    assert_eq!(groceries.url, format!("{}calendars/user/groceries/", server.url()));
+    assert_eq!(groceries.color.as_deref(), Some("#00ff00"));
+    assert!(groceries.supports_todo);
+    //only todos
+    let event = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nUID:e\nEND:VEVENT\nEND:VCALENDAR\n";
+    assert!(matches!(client.create(&groceries, "e", event).await, Err(Error::Forbidden { .. })));
+    assert!(client.create_calendar("groceries", &props).await.is_err());
+
+    let client = connect(&server).await.unwrap();
+    let work = client.get_calendar("Work").await.unwrap();
+    let props = CalendarProps::builder()
+        .name("Office".to_string())
+        .description("9 to 5".to_string())
+        .order(0)
+        .build();
+    client.update_calendar(&work, &props).await.unwrap();
+    assert_eq!(work.read().await.name, "Office");
+
+    //ordered ones first, the rest as the server lists them
+    let client = connect(&server).await.unwrap();
+    let mut names = vec![];
+    for cal in &client.calendars {
+        let cal = cal.read().await;
+        names.push((cal.name.clone(), cal.order, cal.description.clone()));
+    }
+    assert_eq!(names, [
+        ("Office".to_string(), Some(0), Some("9 to 5".to_string())),
+        ("Groceries".to_string(), Some(1), None),
+        ("Home".to_string(), None, None),
+    ]);
+
+    let groceries = client.get_calendar("Groceries").await.unwrap();
+    client.delete_calendar(&*groceries.read().await).await.unwrap();
+    let client = connect(&server).await.unwrap();
+    assert!(client.get_calendar("Groceries").await.is_none());
+}
+
+///DNS answers from a table instead of the network
+#[derive(Default)]
+struct StubResolver {
+    srv: HashMap<String, Vec<SrvRecord>>,
+    txt: HashMap<String, Vec<String>>,
+}
+
+impl Resolver for StubResolver {
+    fn srv<'a>(&'a self, name: &'a str) -> ResolveFuture<'a, Vec<SrvRecord>> {
+        let found = self.srv.get(name).cloned();
+        Box::pin(async move { found.ok_or(Error::Dns { name: name.to_string(), reason: "NXDOMAIN".to_string() }) })
+    }
+
+    fn txt<'a>(&'a self, name: &'a str) -> ResolveFuture<'a, Vec<String>> {
+        let found = self.txt.get(name).cloned();
+        Box::pin(async move { found.ok_or(Error::Dns { name: name.to_string(), reason: "NXDOMAIN".to_string() }) })
+    }
+}
+
+fn srv(priority: u16, weight: u16, port: u16, target: &str) -> SrvRecord {
+    SrvRecord { priority, weight, port, target: target.to_string() }
+}
+
+///a resolver pointing `_caldav._tcp.localhost` at the mock server, with
+///a TXT path if there is one
+fn resolver_for(server: &MockServer, path: Option<&str>) -> StubResolver {
+    let name = "_caldav._tcp.localhost".to_string();
+    let mut resolver = StubResolver::default();
+    resolver.srv.insert(name.clone(), vec![srv(0, 0, port_of(server), "127.0.0.1.")]);
+    if let Some(path) = path {
+        resolver.txt.insert(name, vec![format!("path={path}")]);
+    }
+    resolver
+}
+
+fn port_of(server: &MockServer) -> u16 {
+    server.url().rsplit(':').next().unwrap().trim_end_matches('/').parse().unwrap()
+}
+
+async fn bootstrap(input: &str, resolver: &StubResolver) -> reminder_rs::caldav::Result<CalDAVClient> {
+    CalDAVClient::builder()
+        .base_url(input)
+        .credentials(Credentials::default())
+        .resolver(resolver)
+        .timeout(Duration::from_secs(2))
+        .build()
+        .await
+}
+
+#[tokio::test]
+async fn discovers_from_srv_and_txt() {
+    let server = common::server().await;
+    let resolver = resolver_for(&server, Some("/principals/user/"));
+
+    //plain http SRV records only count for an http site, and this one's root isn't the principal
+    server.inject(None, Fault::Status(404));
+    let client = bootstrap(&format!("http://localhost:{}", port_of(&server)), &resolver).await.unwrap();
+    assert_eq!(client.calendars.len(), 2);
+    //straight to the TXT path, no well-known detour
+    assert_eq!(server.requests()[..2], ["PROPFIND /", "PROPFIND /principals/user/"]);
+}
+
+#[tokio::test]
+async fn discovers_through_well_known() {
+    let server = common::server().await;
+    //no TXT record, so the SRV host's /.well-known/caldav, which redirects to /
+    let resolver = resolver_for(&server, None);
+
+    server.inject(None, Fault::Status(404));
+    let client = bootstrap(&format!("http://localhost:{}/", port_of(&server)), &resolver).await.unwrap();
+    assert_eq!(client.calendars.len(), 2);
+    assert_eq!(server.requests()[..3], ["PROPFIND /", "PROPFIND /.well-known/caldav", "PROPFIND /"]);
+}
+
+#[tokio::test]
+async fn discovery_candidates() {
+    let mut resolver = StubResolver::default();
+    resolver.srv.insert("_caldavs._tcp.example.test".to_string(), vec![
+        srv(10, 0, 8443, "backup.example.test."),
+        srv(0, 5, 443, "light.example.test."),
+        srv(0, 50, 443, "heavy.example.test."),
+    ]);
+    resolver.txt.insert("_caldavs._tcp.example.test".to_string(), vec!["path=/dav/".to_string()]);
+    //only looked at for an http site
+    resolver.srv.insert("_caldav._tcp.example.test".to_string(), vec![srv(0, 0, 80, "plain.example.test.")]);
+
+    let urls = discovery::candidates("me@example.test", Some(&resolver), &|_| {}).await;
+    assert_eq!(urls, [
+        "https://heavy.example.test:443/dav/",
+        "https://light.example.test:443/dav/",
+        "https://backup.example.test:8443/dav/",
+        "https://example.test/.well-known/caldav",
+        "https://example.test/",
+        "https://example.test/remote.php/dav/",
+        "https://example.test/dav.php/",
+        "https://example.test/caldav/",
+        "https://example.test/dav/",
+    ]);
+
+    let urls = discovery::candidates("http://example.test:8080/", Some(&resolver), &|_| {}).await;
+    assert_eq!(urls[3..5], ["http://plain.example.test:80/.well-known/caldav", "http://example.test:8080/.well-known/caldav"]);
+    assert_eq!(urls.len(), 10);
+    //"." means the service isn't there
+    resolver.srv.insert("_caldav._tcp.example.test".to_string(), vec![srv(0, 0, 80, ".")]);
+    assert_eq!(discovery::candidates("http://example.test:8080/", Some(&resolver), &|_| {}).await.len(), 9);
+
+    //without any records it's just the well-known url and the common paths
+    let urls = discovery::candidates("example.test", Some(&StubResolver::default()), &|_| {}).await;
+    assert_eq!(urls[0], "https://example.test/.well-known/caldav");
+    assert_eq!(urls.len(), 6);
+    assert_eq!(urls, discovery::candidates("example.test", None, &|_| {}).await);
+}
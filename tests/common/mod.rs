@@ -0,0 +1,25 @@
+#![allow(dead_code)]
+
+use reminder_rs::mock::{self, MockCalendar, MockServer};
+
+///a server with a `Work` and a `Home` calendar. Work has one open and one
+///completed todo, Home has one open todo
+pub async fn server() -> MockServer {
+    let server = MockServer::builder().build().await.unwrap();
+    populate(&server);
+    server
+}
+
+pub fn populate(server: &MockServer) {
+    server.add_calendar(
+        MockCalendar::builder()
+            .id("work".to_string())
+            .name("Work".to_string())
+            .color("#ff0000".to_string())
+            .build(),
+    );
+    server.add_calendar(MockCalendar::builder().id("home".to_string()).name("Home".to_string()).build());
+    server.put("work", "report.ics", &mock::todo("report", "Write report", &["DUE:20240105T170000Z"]));
+    server.put("work", "slides.ics", &mock::todo("slides", "Make slides", &["PERCENT-COMPLETE:100", "STATUS:COMPLETED"]));
+    server.put("home", "milk.ics", &mock::todo("milk", "Buy milk", &[]));
+}
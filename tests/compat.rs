@@ -0,0 +1,161 @@
+//replays the responses in tests/fixtures through discovery and parsing,
+//see tests/fixtures/README.md
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use reminder_rs::caldav::parser::{resolve_href, MultiStatus};
+use reminder_rs::caldav::{CalDAVClient, CalendarTodo, Credentials};
+use reminder_rs::mock::{Recorded, ReplayServer};
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Fixture {
+    start: String,
+    principal: String,
+    home: String,
+    calendars: Vec<ExpectedCalendar>,
+    todos: ExpectedTodos,
+}
+
+#[derive(Deserialize)]
+struct ExpectedCalendar {
+    url: String,
+    name: String,
+    color: Option<String>,
+    todos: bool,
+    ///only checked when given
+    ctag: Option<String>,
+    reports: Option<Vec<String>>,
+    max_resource_size: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ExpectedTodos {
+    calendar: String,
+    items: Vec<ExpectedTodo>,
+}
+
+#[derive(Deserialize)]
+struct ExpectedTodo {
+    url: String,
+    etag: String,
+    summary: String,
+}
+
+fn read(dir: &Path, file: &str) -> String {
+    fs::read_to_string(dir.join(file)).unwrap_or_else(|e| panic!("{}: {e}", dir.join(file).display()))
+}
+
+async fn check(server: &str) {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(server);
+    let fixture: Fixture = serde_json::from_str(&read(&dir, "server.json")).unwrap();
+
+    let propfind = |path: &str, depth: &str, file: &str| Recorded {
+        method: "PROPFIND".to_string(),
+        path: path.to_string(),
+        depth: Some(depth.to_string()),
+        status: 207,
+        body: read(&dir, file),
+    };
+    let replay = ReplayServer::start(vec![
+        propfind(&fixture.start, "0", "principal.xml"),
+        propfind(&fixture.principal, "0", "homeset.xml"),
+        propfind(&fixture.home, "1", "calendars.xml"),
+    ])
+    .await
+    .unwrap();
+    let base = replay.url().trim_end_matches('/').to_string();
+    let url = |path: &str| format!("{base}{path}");
+
+    let client = CalDAVClient::builder()
+        .base_url(&url(&fixture.start))
+        .credentials(Credentials::default())
+        .retries(0)
+        .build()
+        .await
+        .unwrap_or_else(|e| panic!("{server}: {e:#}\nrequests: {:?}", replay.requests()));
+    assert_eq!(client.home, url(&fixture.home), "{server}: home");
+
+    let mut found = vec![];
+    for cal in &client.calendars {
+        let cal = cal.read().await;
+        found.push((cal.url.clone(), cal.name.clone(), cal.color.clone(), cal.supports_todo));
+        let expected = fixture.calendars.iter().find(|c| url(&c.url) == cal.url);
+        if let Some(ctag) = expected.and_then(|c| c.ctag.as_ref()) {
+            assert_eq!(&cal.ctag, ctag, "{server}: ctag of {}", cal.url);
+        }
+        if let Some(reports) = expected.and_then(|c| c.reports.as_ref()) {
+            assert_eq!(&cal.capabilities.reports, reports, "{server}: reports of {}", cal.url);
+        }
+        if let Some(max) = expected.and_then(|c| c.max_resource_size) {
+            assert_eq!(cal.capabilities.max_resource_size, Some(max), "{server}: max-resource-size of {}", cal.url);
+        }
+    }
+    let expected: Vec<_> = fixture
+        .calendars
+        .iter()
+        .map(|c| (url(&c.url), c.name.clone(), c.color.clone(), c.todos))
+        .collect();
+    assert_eq!(found, expected, "{server}: calendars");
+
+    //the REPORT itself is the client's business, this is about reading the answer
+    let calendar = url(&fixture.todos.calendar);
+    let root = read(&dir, "todos.xml").replace("{base}", &base).parse().unwrap();
+    let todos: Vec<_> = MultiStatus::parse(&root)
+        .responses
+        .iter()
+        .map(|res| {
+            let todo = CalendarTodo::parse(res).unwrap_or_else(|e| panic!("{server}: {e:#}"));
+            let href = resolve_href(&calendar, &todo.url).unwrap();
+            (href, todo.etag.clone(), todo.property("SUMMARY").unwrap_or_default())
+        })
+        .collect();
+    let expected: Vec<_> = fixture
+        .todos
+        .items
+        .iter()
+        .map(|t| (url(&t.url), t.etag.clone(), t.summary.clone()))
+        .collect();
+    assert_eq!(todos, expected, "{server}: todos");
+}
+
+#[tokio::test]
+async fn nextcloud() {
+    check("nextcloud").await;
+}
+
+#[tokio::test]
+async fn radicale() {
+    check("radicale").await;
+}
+
+#[tokio::test]
+async fn baikal() {
+    check("baikal").await;
+}
+
+#[tokio::test]
+async fn icloud() {
+    check("icloud").await;
+}
+
+#[tokio::test]
+async fn fastmail() {
+    check("fastmail").await;
+}
+
+#[tokio::test]
+async fn zimbra() {
+    check("zimbra").await;
+}
+
+#[tokio::test]
+async fn davical() {
+    check("davical").await;
+}
+
+#[tokio::test]
+async fn minimal() {
+    check("minimal").await;
+}
@@ -0,0 +1,275 @@
+use chrono::{Local, NaiveDate, TimeZone, Utc};
+use reminder_rs::caldav::agenda::{agenda_slot, AgendaSlot};
+use reminder_rs::caldav::alarm::{acknowledged, add_alarms, firings, remove_alarms, AlarmSpec, Related, Trigger};
+use reminder_rs::caldav::datetime::{describe_duration, format_duration, parse_duration, DateValue};
+use reminder_rs::caldav::recur::RRule;
+use reminder_rs::caldav::todo::set_property;
+use reminder_rs::caldav::{CalendarTodo, TodoDetails, TodoState, TodoTree};
+
+const BERLIN: &str = "BEGIN:VTIMEZONE\r
+TZID:Europe/Berlin\r
+BEGIN:DAYLIGHT\r
+TZOFFSETFROM:+0100\r
+TZOFFSETTO:+0200\r
+DTSTART:19700329T020000\r
+RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r
+END:DAYLIGHT\r
+BEGIN:STANDARD\r
+TZOFFSETFROM:+0200\r
+TZOFFSETTO:+0100\r
+DTSTART:19701025T030000\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=-1SU\r
+END:STANDARD\r
+END:VTIMEZONE\r
+";
+
+fn todo(props: &str) -> CalendarTodo {
+    let ics = format!(
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n{BERLIN}BEGIN:VTODO\r\nUID:plan\r\nSUMMARY:Plan\\, then do\r\n{props}END:VTODO\r\nEND:VCALENDAR\r\n"
+    );
+    CalendarTodo::from_ics("/cal/plan.ics", "\"1\"".to_string(), &ics).unwrap()
+}
+
+#[test]
+fn reads_everything() {
+    let todo = todo(
+        "DUE;TZID=Europe/Berlin:20240705T170000\r
+DTSTART;VALUE=DATE:20240701\r
+PRIORITY:2\r
+STATUS:IN-PROCESS\r
+PERCENT-COMPLETE:40\r
+CATEGORIES:work,planning\r
+CATEGORIES:q3\r
+LOCATION:Office\\; room 4\r
+GEO:52.52;13.405\r
+RRULE:FREQ=MONTHLY;BYDAY=2TU\r
+RELATED-TO:goals\r
+ATTACH:https://example.com/plan.pdf\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER;RELATED=END:-PT1H30M\r
+DESCRIPTION:Soon\r
+END:VALARM\r
+",
+    );
+    let details = TodoDetails::new(&todo, &[]);
+    assert_eq!(details.summary.as_deref(), Some("Plan, then do"));
+    assert_eq!(details.state, TodoState::Open);
+    assert_eq!(details.status.as_deref(), Some("IN-PROCESS"));
+    assert_eq!(details.percent_complete, Some(40));
+    assert_eq!(details.priority, Some(2));
+    assert_eq!(details.categories, ["work", "planning", "q3"]);
+    assert_eq!(details.location.as_deref(), Some("Office; room 4"));
+    assert_eq!(details.parent.as_deref(), Some("goals"));
+    assert_eq!(details.attachments, ["https://example.com/plan.pdf"]);
+    assert_eq!(details.recurrence.unwrap().text, "every 2nd Tuesday");
+    assert_eq!(details.alarms[0].when, "1 hour 30 minutes before due");
+
+    //summer time in Berlin
+    let due = details.due.unwrap();
+    assert_eq!(due.utc(), Some(Utc.with_ymd_and_hms(2024, 7, 5, 15, 0, 0).unwrap()));
+    assert_eq!(due.original(), "2024-07-05 17:00 Europe/Berlin");
+    assert!(matches!(details.start, Some(DateValue::Date(_))));
+}
+
+#[test]
+fn winter_time_and_unknown_zones() {
+    let todo = todo("DUE;TZID=Europe/Berlin:20240105T170000\r\nDTSTART;TZID=Mars/Olympus:20240105T170000\r\n");
+    let details = TodoDetails::new(&todo, &[]);
+    assert_eq!(details.due.unwrap().utc(), Some(Utc.with_ymd_and_hms(2024, 1, 5, 16, 0, 0).unwrap()));
+    assert_eq!(details.start.unwrap().utc(), None);
+}
+
+#[test]
+fn finds_children() {
+    let ics = set_property(&todo("").ics.replace("UID:plan", "UID:child"), "RELATED-TO", Some("plan"));
+    let child = CalendarTodo::from_ics("/cal/child.ics", "\"2\"".to_string(), &ics).unwrap();
+    let calendar = [todo(""), child];
+    assert_eq!(calendar[1].parent().as_deref(), Some("plan"));
+    let tree = TodoTree::new(&calendar);
+    assert_eq!(TodoDetails::new(&calendar[0], &tree.subtasks(&calendar, 0)).children, ["child"]);
+}
+
+#[test]
+fn describes_recurrences() {
+    let cases = [
+        ("FREQ=DAILY", "every day"),
+        ("FREQ=DAILY;INTERVAL=3;COUNT=10", "every 3 days, 10 times"),
+        ("FREQ=WEEKLY;BYDAY=MO,WE", "every Monday and Wednesday"),
+        ("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR", "every weekday"),
+        ("FREQ=WEEKLY;INTERVAL=2;BYDAY=FR;UNTIL=20241231T000000Z", "every 2 weeks on Friday, until 2024-12-31"),
+        ("FREQ=MONTHLY;BYDAY=-1FR", "every last Friday"),
+        ("FREQ=MONTHLY;INTERVAL=2;BYDAY=1MO", "every 2 months on the 1st Monday"),
+        ("FREQ=MONTHLY;BYMONTHDAY=15,-1", "every month on the 15th and last day"),
+        ("FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU", "every year on the last Sunday in March"),
+    ];
+    for (rule, text) in cases {
+        assert_eq!(RRule::parse(rule).describe(), text, "{rule}");
+    }
+}
+
+#[test]
+fn durations() {
+    assert_eq!(parse_duration("-PT15M"), Some(chrono::Duration::minutes(-15)));
+    assert_eq!(parse_duration("P1W"), Some(chrono::Duration::weeks(1)));
+    assert_eq!(parse_duration("P1DT2H"), Some(chrono::Duration::hours(26)));
+    assert_eq!(parse_duration("PT"), None);
+    assert_eq!(parse_duration("15M"), None);
+    //past what i64 seconds or chrono can hold
+    assert_eq!(parse_duration("P99999999999999W"), None);
+    assert_eq!(parse_duration("-P9999999999999W"), None);
+    assert_eq!(describe_duration(chrono::Duration::hours(-26)), "1 day 2 hours");
+    assert_eq!(format_duration(chrono::Duration::minutes(-15)), "-PT15M");
+    assert_eq!(format_duration(chrono::Duration::minutes(26 * 60 + 30)), "P1DT2H30M");
+    assert_eq!(format_duration(chrono::Duration::weeks(2)), "P2W");
+    assert_eq!(format_duration(chrono::Duration::zero()), "PT0S");
+}
+
+#[test]
+fn absurd_alarms() {
+    let todo = todo(
+        "DUE:20240705T170000Z\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER;RELATED=END:-P999999999W\r
+END:VALARM\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER;RELATED=END:-PT15M\r
+REPEAT:1000\r
+DURATION:P99999W\r
+END:VALARM\r
+",
+    );
+    //the first never goes off, the second only as often as there are dates for
+    let fired = firings(&todo);
+    assert!(fired.iter().all(|f| f.alarm == Some(1)));
+    assert_eq!(fired[0].at, Utc.with_ymd_and_hms(2024, 7, 5, 16, 45, 0).unwrap());
+    assert!(fired.len() > 1 && fired.len() < 1001);
+}
+
+#[test]
+fn alarms() {
+    let at = |h, m| Utc.with_ymd_and_hms(2024, 7, 5, h, m, 0).unwrap();
+    let todo = todo(
+        "DTSTART:20240705T090000Z\r
+DUE:20240705T170000Z\r
+BEGIN:VALARM\r
+UID:soon\r
+ACTION:DISPLAY\r
+TRIGGER;RELATED=END:-PT15M\r
+REPEAT:2\r
+DURATION:PT5M\r
+ACKNOWLEDGED:20240705T164500Z\r
+END:VALARM\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+TRIGGER:PT0S\r
+END:VALARM\r
+BEGIN:VALARM\r
+ACTION:EMAIL\r
+TRIGGER:PT0S\r
+END:VALARM\r
+BEGIN:VALARM\r
+ACTION:AUDIO\r
+TRIGGER;VALUE=DATE-TIME:20240705T120000Z\r
+END:VALARM\r
+",
+    );
+    let fired: Vec<_> = firings(&todo).into_iter().map(|f| (f.alarm, f.key, f.at, f.last)).collect();
+    assert_eq!(
+        fired,
+        [
+            (Some(0), "soon".to_string(), at(16, 50), false),
+            (Some(0), "soon".to_string(), at(16, 55), true),
+            (Some(1), "1:PT0S".to_string(), at(9, 0), true),
+            (Some(3), "3:20240705T120000Z".to_string(), at(12, 0), true),
+        ]
+    );
+
+    let ics = acknowledged(&todo.ics, Some(1), at(9, 1));
+    let todo = CalendarTodo::from_ics("/cal/plan.ics", "\"2\"".to_string(), &ics).unwrap();
+    assert!(!firings(&todo).iter().any(|f| f.alarm == Some(1)));
+    assert_eq!(firings(&todo).len(), 3);
+
+    //Thunderbird acknowledges everything at once and snoozes on the todo
+    let ics = set_property(&ics, "X-MOZ-LASTACK", Some("20240705T165200Z"));
+    let ics = set_property(&ics, "X-MOZ-SNOOZE-TIME", Some("20240705T180000Z"));
+    let todo = CalendarTodo::from_ics("/cal/plan.ics", "\"3\"".to_string(), &ics).unwrap();
+    let fired: Vec<_> = firings(&todo).into_iter().map(|f| (f.alarm, f.at)).collect();
+    assert_eq!(fired, [(Some(0), at(16, 55)), (None, at(18, 0))]);
+    let ics = acknowledged(&ics, None, at(18, 1));
+    assert!(!ics.contains("X-MOZ-SNOOZE-TIME"));
+
+    let ics = remove_alarms(&ics);
+    assert!(!ics.contains("VALARM"));
+    assert!(ics.contains("DUE:20240705T170000Z\r\nX-MOZ-LASTACK:20240705T180100Z\r\nEND:VTODO"));
+    let spec = |trigger| AlarmSpec { trigger, repeat: None };
+    let ics = add_alarms(
+        &set_property(&ics, "X-MOZ-LASTACK", None),
+        &[
+            spec(Trigger::Relative { offset: chrono::Duration::minutes(-30), related: Related::Due }),
+            AlarmSpec { repeat: Some((1, chrono::Duration::minutes(10))), ..spec(Trigger::At(at(8, 0))) },
+        ],
+        "Plan, then do",
+    );
+    assert!(ics.contains("DESCRIPTION:Plan\\, then do\r\nTRIGGER;RELATED=END:-PT30M\r\nEND:VALARM\r\n"));
+    assert!(ics.contains("TRIGGER;VALUE=DATE-TIME:20240705T080000Z\r\nREPEAT:1\r\nDURATION:PT10M\r\nEND:VALARM\r\n"));
+    let todo = CalendarTodo::from_ics("/cal/plan.ics", "\"4\"".to_string(), &ics).unwrap();
+    let fired: Vec<_> = firings(&todo).into_iter().map(|f| f.at).collect();
+    assert_eq!(fired, [at(16, 30), at(8, 0), at(8, 10)]);
+    assert_eq!(TodoDetails::new(&todo, &[]).alarms[0].when, "30 minutes before due");
+}
+
+#[test]
+fn agenda_slots() {
+    //a Wednesday
+    let now = Local.with_ymd_and_hms(2024, 7, 3, 12, 0, 0).unwrap();
+    let day = |d| Some(AgendaSlot::Day(NaiveDate::from_ymd_opt(2024, 7, d).unwrap()));
+    let slot = |props: &str, days| agenda_slot(&TodoDetails::new(&todo(props), &[]), now, days);
+
+    //all day, so not overdue until tomorrow
+    assert_eq!(slot("DUE;VALUE=DATE:20240703\r\n", 1), day(3));
+    assert_eq!(slot("DUE;VALUE=DATE:20240702\r\n", 1), Some(AgendaSlot::Overdue));
+    //floating times are local
+    assert_eq!(slot("DUE:20240703T110000\r\n", 1), Some(AgendaSlot::Overdue));
+    assert_eq!(slot("DUE:20240703T130000\r\n", 1), day(3));
+    assert_eq!(slot("DUE;TZID=Europe/Berlin:20240705T170000\r\n", 7), day(5));
+    assert_eq!(slot("DUE;TZID=Europe/Berlin:20240705T170000\r\n", 1), None);
+
+    assert_eq!(slot("DTSTART;VALUE=DATE:20240703\r\nDUE;VALUE=DATE:20240801\r\n", 1), day(3));
+    assert_eq!(slot("DTSTART:20240703T130000\r\n", 1), None);
+    assert_eq!(slot("PRIORITY:1\r\n", 1), day(3));
+    assert_eq!(slot("PRIORITY:5\r\n", 1), None);
+    assert_eq!(slot("PRIORITY:1\r\nDUE;VALUE=DATE:20240801\r\n", 7), None);
+    assert_eq!(slot("DUE;VALUE=DATE:20240702\r\nSTATUS:COMPLETED\r\n", 1), None);
+}
+
+#[test]
+fn builds_trees() {
+    let todo = |uid: &str, parent: Option<&str>| {
+        let mut ics = todo("").ics.replace("UID:plan", &format!("UID:{uid}"));
+        if let Some(parent) = parent {
+            ics = set_property(&ics, "RELATED-TO;RELTYPE=PARENT", Some(parent));
+        }
+        CalendarTodo::from_ics(&format!("/cal/{uid}.ics"), "\"1\"".to_string(), &ics).unwrap()
+    };
+    let todos = [
+        todo("a", None),
+        todo("b", Some("a")),
+        todo("c", Some("b")),
+        //its parent is somewhere else
+        todo("d", Some("gone")),
+        todo("e", Some("f")),
+        todo("f", Some("e")),
+        todo("g", Some("g")),
+    ];
+    let tree = TodoTree::new(&todos);
+    assert_eq!(tree.roots().collect::<Vec<_>>(), [0, 3, 4, 6]);
+    assert_eq!(tree.descendants(0), [1, 2]);
+    assert_eq!(tree.children(1), [2]);
+    assert_eq!(tree.parent(2), Some(1));
+    assert_eq!(tree.parent(5), Some(4));
+    assert_eq!(tree.descendants(4), [5]);
+    assert!(tree.descendants(6).is_empty());
+}
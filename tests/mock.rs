@@ -0,0 +1,110 @@
+//the reports the client doesn't send yet, spoken to directly
+
+use reminder_rs::caldav::parser::{format_ns_attrs, MultiStatus, NS_D};
+use reminder_rs::mock::{self, MockServer};
+
+mod common;
+
+async fn report(server: &MockServer, calendar: &str, body: &str) -> (u16, String) {
+    let res = reqwest::Client::new()
+        .request(reqwest::Method::from_bytes(b"REPORT").unwrap(), format!("{}calendars/user/{calendar}/", server.url()))
+        .header("Depth", "1")
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap();
+    (res.status().as_u16(), res.text().await.unwrap())
+}
+
+///(href, status) of every response, sorted
+fn hrefs(body: &str) -> Vec<(String, Option<u16>)> {
+    let root = body.parse().unwrap();
+    let mut hrefs: Vec<_> = MultiStatus::parse(&root).responses.into_iter().map(|r| (r.href, r.status)).collect();
+    hrefs.sort();
+    hrefs
+}
+
+fn query(filter: &str) -> String {
+    format!(
+        r#"<c:calendar-query {}><d:prop><d:getetag/></d:prop><c:filter><c:comp-filter name="VCALENDAR"><c:comp-filter name="VTODO">{filter}</c:comp-filter></c:comp-filter></c:filter></c:calendar-query>"#,
+        format_ns_attrs()
+    )
+}
+
+#[tokio::test]
+async fn calendar_query_filters() {
+    let server = common::server().await;
+    let work = |file: &str| (format!("/calendars/user/work/{file}"), None);
+
+    let (status, body) = report(&server, "work", &query("")).await;
+    assert_eq!(status, 207);
+    assert_eq!(hrefs(&body), [work("report.ics"), work("slides.ics")]);
+
+    let (_, body) = report(&server, "work", &query(r#"<c:prop-filter name="SUMMARY"><c:text-match>REPORT</c:text-match></c:prop-filter>"#)).await;
+    assert_eq!(hrefs(&body), [work("report.ics")]);
+
+    let (_, body) = report(&server, "work", &query(r#"<c:prop-filter name="STATUS"><c:is-not-defined/></c:prop-filter>"#)).await;
+    assert_eq!(hrefs(&body), [work("report.ics")]);
+
+    //a todo without any dates is in every range (RFC 4791 9.9)
+    let (_, body) = report(&server, "work", &query(r#"<c:time-range start="20240105T000000Z" end="20240106T000000Z"/>"#)).await;
+    assert_eq!(hrefs(&body), [work("report.ics"), work("slides.ics")]);
+
+    let (_, body) = report(&server, "work", &query(r#"<c:time-range start="20240201T000000Z"/>"#)).await;
+    assert_eq!(hrefs(&body), [work("slides.ics")]);
+}
+
+#[tokio::test]
+async fn multiget() {
+    let server = common::server().await;
+    let body = format!(
+        r#"<c:calendar-multiget {}><d:prop><d:getetag/><c:calendar-data/></d:prop><d:href>/calendars/user/work/report.ics</d:href><d:href>/calendars/user/work/gone.ics</d:href></c:calendar-multiget>"#,
+        format_ns_attrs()
+    );
+    let (_, body) = report(&server, "work", &body).await;
+    assert_eq!(hrefs(&body), [
+        ("/calendars/user/work/gone.ics".to_string(), Some(404)),
+        ("/calendars/user/work/report.ics".to_string(), None),
+    ]);
+    assert!(body.contains("SUMMARY:Write report"));
+}
+
+#[tokio::test]
+async fn sync_collection() {
+    let server = common::server().await;
+    let sync = |token: &str| {
+        format!(
+            r#"<d:sync-collection {}><d:sync-token>{token}</d:sync-token><d:sync-level>1</d:sync-level><d:prop><d:getetag/></d:prop></d:sync-collection>"#,
+            format_ns_attrs()
+        )
+    };
+    let token = |body: &str| {
+        let root: minidom::Element = body.parse().unwrap();
+        root.get_child("sync-token", NS_D).unwrap().text()
+    };
+
+    let (_, body) = report(&server, "home", &sync("")).await;
+    assert_eq!(hrefs(&body), [("/calendars/user/home/milk.ics".to_string(), None)]);
+    let first = token(&body);
+
+    let (_, body) = report(&server, "home", &sync(&first)).await;
+    assert_eq!(hrefs(&body), []);
+
+    server.put("home", "bread.ics", &mock::todo("bread", "Buy bread", &[]));
+    let res = reqwest::Client::new()
+        .delete(format!("{}calendars/user/home/milk.ics", server.url()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(res.status(), 204);
+
+    let (_, body) = report(&server, "home", &sync(&first)).await;
+    assert_eq!(hrefs(&body), [
+        ("/calendars/user/home/bread.ics".to_string(), None),
+        ("/calendars/user/home/milk.ics".to_string(), Some(404)),
+    ]);
+    assert_ne!(token(&body), first);
+
+    let (status, _) = report(&server, "home", &sync("bogus")).await;
+    assert_eq!(status, 403);
+}
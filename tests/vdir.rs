@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use reminder_rs::caldav::{CalendarProps, Error};
+use reminder_rs::store::{self, Query, TodoStore, VdirStore};
+
+fn ics(uid: &str, summary: &str) -> String {
+    format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VTODO\r\nUID:{uid}\r\nSUMMARY:{summary}\r\nEND:VTODO\r\nEND:VCALENDAR\r\n")
+}
+
+///an empty directory of its own for each test
+fn root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("reminder-rs-vdir-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    root
+}
+
+async fn summaries(store: &VdirStore, name: &str) -> Vec<String> {
+    let cal = store::find_calendar(store, name).await.unwrap();
+    let todos = store.todos(&cal, Query::All).await.unwrap();
+    todos.items.iter().filter_map(|todo| todo.property("SUMMARY")).collect()
+}
+
+#[tokio::test]
+async fn create_update_delete() {
+    let root = root("crud");
+    std::fs::create_dir(root.join("home")).unwrap();
+    let store = VdirStore::open(&root).await.unwrap();
+    let home = store::find_calendar(&store, "home").await.unwrap();
+    let home = home.read().await;
+
+    let todo = store.create(&home, "milk/1", &ics("milk/1", "Buy milk")).await.unwrap();
+    assert_eq!(todo.url, root.join("home/milk_1.ics").to_string_lossy());
+    assert!(matches!(store.create(&home, "milk/1", &ics("milk/1", "Again")).await, Err(Error::Conflict { .. })));
+
+    let todo = store.update(&todo, &ics("milk/1", "Buy oat milk")).await.unwrap();
+    assert_eq!(summaries(&store, "home").await, ["Buy oat milk"]);
+
+    store.delete(&todo).await.unwrap();
+    assert!(summaries(&store, "home").await.is_empty());
+    assert!(matches!(store.delete(&todo).await, Err(Error::NotFound { .. })));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn conflicts() {
+    let root = root("conflict");
+    std::fs::create_dir(root.join("home")).unwrap();
+    let store = VdirStore::open(&root).await.unwrap();
+    let home = store::find_calendar(&store, "home").await.unwrap();
+    let todo = store.create(&*home.read().await, "milk", &ics("milk", "Buy milk")).await.unwrap();
+
+    //someone else changed it since
+    std::fs::write(&todo.url, ics("milk", "Buy more milk")).unwrap();
+    assert!(matches!(store.update(&todo, &ics("milk", "Buy oat milk")).await, Err(Error::Conflict { .. })));
+    assert!(matches!(store.delete(&todo).await, Err(Error::Conflict { .. })));
+    assert_eq!(summaries(&store, "home").await, ["Buy more milk"]);
+
+    //two writers with the same version: only one gets through
+    let todos = store.todos(&home, Query::All).await.unwrap();
+    let other = VdirStore::open(&root).await.unwrap();
+    let (oat, almond) = (ics("milk", "Buy oat milk"), ics("milk", "Buy almond milk"));
+    let (first, second) = tokio::join!(store.update(&todos.items[0], &oat), other.update(&todos.items[0], &almond));
+    assert!(first.is_ok() != second.is_ok());
+    assert!(matches!(first.err().or(second.err()), Some(Error::Conflict { .. })));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn calendar_metadata() {
+    let root = root("meta");
+    std::fs::create_dir(root.join("work")).unwrap();
+    std::fs::write(root.join("work/displayname"), "Work\n").unwrap();
+    std::fs::write(root.join("work/color"), "#ff0000").unwrap();
+    std::fs::create_dir(root.join("home")).unwrap();
+
+    let store = VdirStore::open(&root).await.unwrap();
+    let work = store::find_calendar(&store, "Work").await.unwrap();
+    assert_eq!(work.read().await.color.as_deref(), Some("#ff0000"));
+    //without a displayname it's named after the directory
+    assert!(store::find_calendar(&store, "home").await.is_some());
+
+    let props = CalendarProps::builder().name("Groceries".to_string()).color("#00ff00".to_string()).build();
+    store.create_calendar("groceries", &props).await.unwrap();
+    assert_eq!(std::fs::read_to_string(root.join("groceries/displayname")).unwrap(), "Groceries");
+
+    let props = CalendarProps::builder().color("#0000ff".to_string()).build();
+    store.update_calendar(&work, &props).await.unwrap();
+    assert_eq!(work.read().await.color.as_deref(), Some("#0000ff"));
+
+    let store = VdirStore::open(&root).await.unwrap();
+    let groceries = store::find_calendar(&store, "Groceries").await.unwrap();
+    assert_eq!(groceries.read().await.color.as_deref(), Some("#00ff00"));
+    assert_eq!(store::find_calendar(&store, "Work").await.unwrap().read().await.color.as_deref(), Some("#0000ff"));
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[tokio::test]
+async fn skips_other_files() {
+    let root = root("skip");
+    let home = root.join("home");
+    std::fs::create_dir(&home).unwrap();
+    std::fs::write(home.join("milk.ics"), ics("milk", "Buy milk")).unwrap();
+    //khal keeps events next to todos
+    let event = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:party\r\nSUMMARY:Party\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+    std::fs::write(home.join("party.ics"), event).unwrap();
+    std::fs::write(home.join("notes.txt"), ics("notes", "Not an item")).unwrap();
+    std::fs::write(home.join(".milk.ics.tmp"), ics("milk", "Half written")).unwrap();
+    std::fs::create_dir(root.join(".hidden")).unwrap();
+
+    let store = VdirStore::open(&root).await.unwrap();
+    assert_eq!(store.calendars().len(), 1);
+    assert_eq!(summaries(&store, "home").await, ["Buy milk"]);
+    let _ = std::fs::remove_dir_all(&root);
+}